@@ -0,0 +1,139 @@
+//! Parses the `#[noms(...)]` attributes which customize the derived implementations
+
+use syn::{Attribute, Lit, MetaItem, NestedMetaItem};
+
+/// Where an attribute was found, which determines the options it may hold.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Target {
+    Container,
+    Variant,
+    Field,
+}
+
+/// The options given to a struct, enum, variant or field.
+#[derive(Default)]
+pub struct Options {
+    /// The name of a struct in Noms (`name = "..."`)
+    pub name: Option<String>,
+    /// The name of a field or variant in Noms (`rename = "..."`)
+    pub rename: Option<String>,
+    /// How the names of the fields or variants within are converted (`rename_all = "..."`)
+    pub rename_all: Option<Case>,
+    /// Leaves the field out of the Noms struct entirely (`skip`)
+    pub skip: bool,
+    /// Uses the default value for the field when it is missing (`default`)
+    pub default: bool,
+}
+
+impl Options {
+    pub fn parse(attrs: &[Attribute], target: Target) -> Self {
+        let mut options = Options::default();
+        for attr in attrs {
+            let items = match attr.value {
+                MetaItem::List(ref ident, ref items) if ident == "noms" => items,
+                _ => continue,
+            };
+            for item in items {
+                match (item, target) {
+                    (&NestedMetaItem::MetaItem(MetaItem::NameValue(ref ident, Lit::Str(ref value, _))), Target::Container)
+                        if ident == "name" => options.name = Some(value.clone()),
+                    (&NestedMetaItem::MetaItem(MetaItem::NameValue(ref ident, Lit::Str(ref value, _))), Target::Variant)
+                    | (&NestedMetaItem::MetaItem(MetaItem::NameValue(ref ident, Lit::Str(ref value, _))), Target::Field)
+                        if ident == "rename" => options.rename = Some(value.clone()),
+                    (&NestedMetaItem::MetaItem(MetaItem::NameValue(ref ident, Lit::Str(ref value, _))), Target::Container)
+                    | (&NestedMetaItem::MetaItem(MetaItem::NameValue(ref ident, Lit::Str(ref value, _))), Target::Variant)
+                        if ident == "rename_all" => options.rename_all = Some(Case::parse(value)),
+                    (&NestedMetaItem::MetaItem(MetaItem::Word(ref ident)), Target::Field)
+                        if ident == "skip" => options.skip = true,
+                    (&NestedMetaItem::MetaItem(MetaItem::Word(ref ident)), Target::Field)
+                        if ident == "default" => options.default = true,
+                    (item, target) => panic!("Unsupported option for a {:?} in #[noms(...)]: {:?}", target, item),
+                }
+            }
+        }
+        options
+    }
+}
+
+/// The naming conventions which names can be converted to by `rename_all`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Case {
+    Lower,
+    Upper,
+    Camel,
+    Pascal,
+    Snake,
+    ScreamingSnake,
+}
+
+impl Case {
+    fn parse(name: &str) -> Self {
+        match name {
+            "lowercase" => Case::Lower,
+            "UPPERCASE" => Case::Upper,
+            "camelCase" => Case::Camel,
+            "PascalCase" => Case::Pascal,
+            "snake_case" => Case::Snake,
+            "SCREAMING_SNAKE_CASE" => Case::ScreamingSnake,
+            _ => panic!("Unknown case for rename_all: {:?}", name),
+        }
+    }
+
+    /// Converts a name, which may be written in either snake_case or PascalCase, to this case.
+    pub fn apply(self, name: &str) -> String {
+        let words = words(name);
+        match self {
+            Case::Lower => name.to_lowercase(),
+            Case::Upper => name.to_uppercase(),
+            Case::Camel => {
+                let mut words = words.iter();
+                let first = words.next().cloned().unwrap_or_default();
+                words.fold(first, |name, word| name + &capitalize(word))
+            }
+            Case::Pascal => words.iter().map(|word| capitalize(word)).collect(),
+            Case::Snake => words.join("_"),
+            Case::ScreamingSnake => words.join("_").to_uppercase(),
+        }
+    }
+}
+
+/// Splits a name into lowercase words, at underscores and before capital letters.
+fn words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    for part in name.split('_').filter(|part| !part.is_empty()) {
+        let mut word = String::new();
+        for c in part.chars() {
+            if c.is_uppercase() && !word.is_empty() {
+                words.push(word);
+                word = String::new();
+            }
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cases() {
+        assert_eq!(Case::Camel.apply("input_file"), "inputFile");
+        assert_eq!(Case::Camel.apply("KeyPress"), "keyPress");
+        assert_eq!(Case::Pascal.apply("input_file"), "InputFile");
+        assert_eq!(Case::Snake.apply("KeyPress"), "key_press");
+        assert_eq!(Case::ScreamingSnake.apply("input_file"), "INPUT_FILE");
+        assert_eq!(Case::Lower.apply("KeyPress"), "keypress");
+        assert_eq!(Case::Upper.apply("input_file"), "INPUT_FILE");
+    }
+}
@@ -0,0 +1,379 @@
+//! Implements `#[derive(Noms)]`, which allows Rust structs and enums to be stored in and retrieved
+//! from a Noms database.
+//!
+//! Structs are stored as Noms structs, with their field names converted to camelCase, which is
+//! the convention used by Noms. Fields of type `Option<T>` become optional fields holding a `T`,
+//! which are left out when they are `None`. Enums are stored as a union of the kinds of their
+//! variants: unit variants become strings holding the name of the variant (or numbers, if the enum
+//! has explicit discriminants), and struct variants become Noms structs named after the variant.
+//!
+//! The names used in Noms can be customized using `#[noms(...)]` attributes:
+//!
+//! * `#[noms(name = "...")]` on a struct sets the name of the Noms struct
+//! * `#[noms(rename_all = "...")]` on a struct, enum or variant converts the names of its fields
+//!   or variants to `lowercase`, `UPPERCASE`, `camelCase`, `PascalCase`, `snake_case` or
+//!   `SCREAMING_SNAKE_CASE`
+//! * `#[noms(rename = "...")]` on a field or variant sets its name
+//! * `#[noms(skip)]` on a field leaves it out, filling it with its default value when decoding
+//! * `#[noms(default)]` on a field uses its default value when it is missing from the Noms struct
+
+extern crate proc_macro;
+extern crate syn;
+#[macro_use] extern crate quote;
+
+mod attr;
+
+use proc_macro::TokenStream;
+use syn::{Body, DeriveInput, Ident, Lifetime, LifetimeDef, PathParameters, Ty, VariantData};
+use quote::Tokens;
+use attr::{Case, Options, Target};
+
+#[proc_macro_derive(Noms, attributes(noms))]
+pub fn derive_noms(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_derive_input(&input.to_string()).unwrap();
+    let options = Options::parse(&ast.attrs, Target::Container);
+    let gen = match ast.body {
+        Body::Struct(VariantData::Struct(ref fields)) => {
+            let case = options.rename_all.unwrap_or(Case::Camel);
+            let fields = fields.iter().map(|f| Field::new(f, case)).collect();
+            impl_struct(&ast, options.name.as_ref().map_or(ast.ident.as_ref(), |name| name), fields)
+        }
+        Body::Enum(ref variants) => {
+            if options.name.is_some() {
+                panic!("#[noms(name = \"...\")] is only supported for structs. Rename the variants instead.");
+            }
+            let variants = variants.iter().map(|v| Variant::new(v, options.rename_all)).collect();
+            impl_enum(&ast, variants)
+        }
+        _ => panic!("#[derive(Noms)] is only supported for structs with named fields, and enums"),
+    };
+    gen.parse().unwrap()
+}
+
+/// A field of the struct, along with the name it is given in Noms.
+struct Field<'a> {
+    ident: &'a Ident,
+    ty: &'a Ty,
+    key: String,
+    skip: bool,
+    default: bool,
+}
+
+impl<'a> Field<'a> {
+    /// Describes a field, whose name is converted to the given `case` unless it is renamed.
+    fn new(field: &'a syn::Field, case: Case) -> Self {
+        let ident = field.ident.as_ref().unwrap();
+        let options = Options::parse(&field.attrs, Target::Field);
+        Field{
+            ident,
+            ty: &field.ty,
+            key: options.rename.unwrap_or_else(|| case.apply(ident.as_ref())),
+            skip: options.skip,
+            default: options.default,
+        }
+    }
+
+    /// The type of the field's value, and whether the field is optional. An `Option<T>` field
+    /// holds values of type `T`.
+    fn value_type(&self) -> (&'a Ty, bool) {
+        if let &Ty::Path(None, ref path) = self.ty {
+            let last = path.segments.last().unwrap();
+            if last.ident.as_ref() == "Option" {
+                if let PathParameters::AngleBracketed(ref params) = last.parameters {
+                    if params.types.len() == 1 {
+                        return (&params.types[0], true);
+                    }
+                }
+            }
+        }
+        (self.ty, false)
+    }
+
+    /// Initializes the field from the props of a struct. Optional fields, and fields with a default,
+    /// may be missing.
+    fn decode_prop(&self) -> Tokens {
+        let ident = self.ident;
+        let key = &self.key;
+        if self.skip {
+            quote! { #ident: Default::default() }
+        } else if self.value_type().1 {
+            // a missing optional field is None, whether or not it has a default
+            quote! { #ident: props.remove(#key).map(|value| value.transform()) }
+        } else if self.default {
+            quote! { #ident: props.remove(#key).map(|value| value.transform()).unwrap_or_default() }
+        } else {
+            quote! { #ident: props.remove(#key)?.transform() }
+        }
+    }
+
+    /// Adds the field, whose value is found at `place`, to the props of a struct. Optional fields
+    /// are left out when they are `None`.
+    fn encode_prop(&self, place: Tokens) -> Tokens {
+        let key = &self.key;
+        if self.skip {
+            quote!{}
+        } else if self.value_type().1 {
+            quote! {
+                if let Some(ref value) = #place {
+                    props.insert(#key.to_string(), ::nomrs::value::IntoNoms::into_noms(value));
+                }
+            }
+        } else {
+            quote! { props.insert(#key.to_string(), ::nomrs::value::IntoNoms::into_noms(&#place)); }
+        }
+    }
+}
+
+/// A variant of an enum, which is either a unit variant or has named fields, along with the name
+/// it is given in Noms.
+struct Variant<'a> {
+    ident: &'a Ident,
+    name: String,
+    fields: Option<Vec<Field<'a>>>,
+    discriminant: Option<&'a syn::ConstExpr>,
+}
+
+impl<'a> Variant<'a> {
+    /// Describes a variant, whose name is converted to the given `case`, if any, unless it is
+    /// renamed.
+    fn new(variant: &'a syn::Variant, case: Option<Case>) -> Self {
+        let options = Options::parse(&variant.attrs, Target::Variant);
+        let field_case = options.rename_all.unwrap_or(Case::Camel);
+        let fields = match variant.data {
+            VariantData::Unit => None,
+            VariantData::Struct(ref fields) => Some(fields.iter().map(|f| Field::new(f, field_case)).collect()),
+            VariantData::Tuple(_) => panic!(
+                "#[derive(Noms)] does not support tuple variants, but {} is one. Name its fields instead.",
+                variant.ident,
+            ),
+        };
+        let name = options.rename.unwrap_or_else(|| match case {
+            Some(case) => case.apply(variant.ident.as_ref()),
+            None => variant.ident.to_string(),
+        });
+        Variant{ ident: &variant.ident, name, fields, discriminant: variant.discriminant.as_ref() }
+    }
+}
+
+/// The numbers Rust gives the variants: each variant's explicit discriminant, or one more than the
+/// number of the variant before it. They are built from the discriminants rather than by casting
+/// the variants, which cannot be done to an enum with fields.
+fn numbers(variants: &[Variant]) -> Vec<Tokens> {
+    let mut base = None;
+    let mut offset = -1i64;
+    variants
+        .iter()
+        .map(|v| {
+            match v.discriminant {
+                Some(d) => { base = Some(d); offset = 0; }
+                None => offset += 1,
+            }
+            match base {
+                Some(d) => quote!{ ((#d) as i64 + #offset) },
+                None => quote!{ #offset },
+            }
+        })
+        .collect()
+}
+
+/// The generics to implement the traits with. The traits are parameterized by the lifetime of the
+/// database, so types which hold values from the database must use their own lifetime for it.
+fn impl_generics(ast: &DeriveInput) -> (syn::Generics, Lifetime) {
+    let mut generics = ast.generics.clone();
+    if generics.lifetimes.is_empty() {
+        generics.lifetimes.push(LifetimeDef::new("'a"));
+    }
+    let lifetime = generics.lifetimes[0].lifetime.clone();
+    (generics, lifetime)
+}
+
+/// Builds the Noms type of a struct with the given name and fields.
+fn struct_type(name: &str, fields: &[Field]) -> Tokens {
+    // the fields of a struct type are ordered by name
+    let mut sorted: Vec<_> = fields.iter().filter(|f| !f.skip).collect();
+    sorted.sort_by(|a, b| a.key.cmp(&b.key));
+    let keys: Vec<_> = sorted.iter().map(|f| f.key.as_str()).collect();
+    let (tys, optional): (Vec<_>, Vec<_>) = sorted.iter().map(|f| f.value_type()).unzip();
+    quote! {
+        ::nomrs::value::Type::structure(
+            #name.to_string(),
+            vec![#(#keys.to_string()),*],
+            vec![#(<#tys as ::nomrs::value::IntoNoms>::noms_type()),*],
+            vec![#(#optional),*],
+        )
+    }
+}
+
+fn impl_struct(ast: &DeriveInput, noms_name: &str, fields: Vec<Field>) -> Tokens {
+    let name = &ast.ident;
+    let (generics, lifetime) = impl_generics(ast);
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = ast.generics.split_for_impl();
+
+    let noms_type = struct_type(noms_name, &fields);
+    let from_props: Vec<_> = fields.iter().map(Field::decode_prop).collect();
+    let to_props: Vec<_> = fields
+        .iter()
+        .map(|f| { let ident = f.ident; f.encode_prop(quote!{ self.#ident }) })
+        .collect();
+
+    quote! {
+        impl #impl_generics ::nomrs::value::IntoNoms for #name #ty_generics #where_clause {
+            fn into_noms(&self) -> Vec<u8> {
+                ::nomrs::value::encode_struct(#noms_name, ::nomrs::value::NomsStruct::to_prop_list(self))
+            }
+            fn noms_type() -> ::nomrs::value::Type {
+                <Self as ::nomrs::value::NomsStruct>::noms_type()
+            }
+        }
+
+        impl #impl_generics ::nomrs::value::FromNoms<#lifetime> for #name #ty_generics #where_clause {
+            fn from_noms(chunk: &::nomrs::Chunk<#lifetime>) -> Self {
+                <::nomrs::value::NomsValue as ::nomrs::value::FromNoms>::from_noms(chunk).transform_struct()
+            }
+        }
+
+        impl #impl_generics ::nomrs::value::NomsStruct<#lifetime> for #name #ty_generics #where_clause {
+            const NAME: &'static str = #noms_name;
+
+            fn noms_type() -> ::nomrs::value::Type {
+                #noms_type
+            }
+
+            fn from_prop_list(mut props: ::std::collections::HashMap<String, ::nomrs::value::NomsValue<#lifetime>>) -> Option<Self> {
+                Some(#name {
+                    #(#from_props,)*
+                })
+            }
+
+            fn to_prop_list(&self) -> ::std::collections::HashMap<String, Vec<u8>> {
+                let mut props = ::std::collections::HashMap::new();
+                #(#to_props)*
+                props
+            }
+        }
+    }
+}
+
+fn impl_enum(ast: &DeriveInput, variants: Vec<Variant>) -> Tokens {
+    let name = &ast.ident;
+    let (generics, lifetime) = impl_generics(ast);
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+    let (_, ty_generics, _) = ast.generics.split_for_impl();
+
+    // unit variants are numbered only if the enum gives them numbers explicitly
+    let numbered = variants.iter().any(|v| v.discriminant.is_some());
+    let unit_type = if numbered { quote!{ i64 } } else { quote!{ String } };
+    let units: Vec<_> = variants
+        .iter()
+        .zip(numbers(&variants))
+        .filter(|&(v, _)| v.fields.is_none())
+        .collect();
+    let structs: Vec<_> = variants
+        .iter()
+        .filter_map(|v| v.fields.as_ref().map(|fields| (v, fields)))
+        .collect();
+
+    let mut types = vec![];
+    if !units.is_empty() {
+        types.push(quote!{ <#unit_type as ::nomrs::value::IntoNoms>::noms_type() });
+    }
+    types.extend(structs.iter().map(|&(variant, fields)| struct_type(&variant.name, fields)));
+    let noms_type = if types.len() == 1 {
+        types.pop().unwrap()
+    } else {
+        // ordered by hash, as Go Noms orders the types of a union
+        quote!{ ::nomrs::value::Type::simplified_union(vec![#(#types),*]) }
+    };
+
+    let mut encoders = vec![];
+    let mut unit_decoders = vec![];
+    for &(variant, ref number) in &units {
+        let (ident, noms_name) = (variant.ident, &variant.name);
+        if numbered {
+            encoders.push(quote!{ &#name::#ident => ::nomrs::value::IntoNoms::into_noms(&#number) });
+            unit_decoders.push(quote!{ n if n == #number => Some(#name::#ident) });
+        } else {
+            encoders.push(quote!{ &#name::#ident => ::nomrs::value::IntoNoms::into_noms(&#noms_name) });
+            unit_decoders.push(quote!{ #noms_name => Some(#name::#ident) });
+        }
+    }
+    let mut struct_decoders = vec![];
+    for &(variant, fields) in &structs {
+        let (ident, noms_name) = (variant.ident, &variant.name);
+        let idents: Vec<_> = fields.iter().filter(|f| !f.skip).map(|f| f.ident).collect();
+        let to_props: Vec<_> = fields
+            .iter()
+            .map(|f| { let ident = f.ident; f.encode_prop(quote!{ *#ident }) })
+            .collect();
+        let from_props: Vec<_> = fields.iter().map(Field::decode_prop).collect();
+        encoders.push(quote! {
+            &#name::#ident { #(ref #idents,)* .. } => {
+                let mut props = ::std::collections::HashMap::new();
+                #(#to_props)*
+                ::nomrs::value::encode_struct(#noms_name, props)
+            }
+        });
+        struct_decoders.push(quote! {
+            #noms_name => Some(#name::#ident { #(#from_props,)* })
+        });
+    }
+
+    let decode_unit = if units.is_empty() {
+        quote!{}
+    } else if numbered {
+        quote! {
+            if let Some(n) = value.clone().into_i64() {
+                return match n {
+                    #(#unit_decoders,)*
+                    _ => None,
+                };
+            }
+        }
+    } else {
+        quote! {
+            if let Some(s) = value.clone().into_string() {
+                return match s.as_str() {
+                    #(#unit_decoders,)*
+                    _ => None,
+                };
+            }
+        }
+    };
+    let decode_struct = if structs.is_empty() {
+        quote!{ None }
+    } else {
+        quote! {
+            // the struct's name says which variant it holds
+            let (name, mut props) = value.to_struct_props()?;
+            match name.as_str() {
+                #(#struct_decoders,)*
+                _ => None,
+            }
+        }
+    };
+
+    quote! {
+        impl #impl_generics ::nomrs::value::IntoNoms for #name #ty_generics #where_clause {
+            fn into_noms(&self) -> Vec<u8> {
+                match self {
+                    #(#encoders,)*
+                }
+            }
+            fn noms_type() -> ::nomrs::value::Type {
+                #noms_type
+            }
+        }
+
+        impl #impl_generics ::nomrs::value::FromNoms<#lifetime> for #name #ty_generics #where_clause {
+            fn from_noms(chunk: &::nomrs::Chunk<#lifetime>) -> Self {
+                let value = <::nomrs::value::NomsValue as ::nomrs::value::FromNoms>::from_noms(chunk);
+                let variant = (move || -> Option<Self> {
+                    #decode_unit
+                    #decode_struct
+                })();
+                variant.expect(concat!("Value is not a variant of ", stringify!(#name)))
+            }
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Stores arbitrary binary data, such as files, as Noms blobs.
+//!
+//! Large blobs are split into chunks of about 4KB, at boundaries chosen by their content, so that
+//! similar files share most of their chunks.
+
+use std::io::{Read, Write};
+use database::Database;
+use value::{NomsValue, Kind, write_blob};
+use chunk::Chunk;
+use either::Either;
+use error::Error;
+
+/// Writes the data to the database as a blob. Chunks are written as soon as they have been read,
+/// so large files are never held in memory at once. The returned blob is not written itself, so
+/// it should be committed, or held by a value which is.
+pub fn write<'a, D, R>(database: &'a D, reader: R) -> Result<NomsValue<'a>, Error>
+where D: Database, R: Read {
+    Ok(database.value_from(write_blob(database, reader)?))
+}
+
+/// The number of bytes in a blob, or `None` if the value is not a blob.
+pub fn len(value: &NomsValue) -> Option<u64> {
+    let chunk = blob_chunk(value)?;
+    Some(match chunk.reader().read_blob() {
+        Either::Left(bytes) => bytes.len() as u64,
+        Either::Right(mts) => mts.iter().map(|mt| mt.num_leaves).sum(),
+    })
+}
+
+/// Writes the bytes of a blob. Chunks are read one at a time as they are written, so large blobs
+/// are never held in memory at once.
+pub fn export<'a, W: Write>(value: &NomsValue<'a>, mut writer: W) -> Result<(), Error> {
+    let chunk = blob_chunk(value)
+        .ok_or_else(|| Error::ConversionError(format!("{:?} is not a blob", value)))?;
+    let database = chunk.database();
+    // the chunks which have not been reached yet, with the next one last
+    let mut pending = vec![Either::Left(chunk)];
+    while let Some(next) = pending.pop() {
+        let chunk = match next {
+            Either::Left(chunk) => chunk,
+            Either::Right(hash) => database
+                .ok_or_else(|| Error::ConversionError("The chunks of a blob which was not read from a database cannot be read".to_string()))?
+                .get(hash)?
+                .to_chunk(),
+        };
+        match chunk.reader().read_blob() {
+            Either::Left(bytes) => writer.write_all(&bytes)?,
+            Either::Right(mts) => pending.extend(mts.into_iter().rev().map(|mt| Either::Right(mt.reference.target_hash()))),
+        }
+    }
+    Ok(())
+}
+
+fn blob_chunk<'a>(value: &NomsValue<'a>) -> Option<Chunk<'a>> {
+    let chunk = value.clone().import().to_chunk();
+    if chunk.data().first() == Some(&(Kind::Blob as u8)) {
+        Some(chunk)
+    } else {
+        None
+    }
+}
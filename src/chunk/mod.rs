@@ -32,6 +32,10 @@ impl<'a> Chunk<'a> {
         ChunkReader::new(self.database, &self.data)
     }
 
+    pub(crate) fn database(&self) -> Option<&'a ChunkStore> {
+        self.database
+    }
+
     pub fn data(&self) -> &Vec<u8> {
         &self.data
     }
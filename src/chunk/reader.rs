@@ -3,6 +3,7 @@ use database::ChunkStore;
 use hash::{Hash, BYTE_LEN};
 use value::{Value, Type, Kind, Ref, FromNoms, IntoNoms, Map, Set, List, MetaTuple, OrderedKey, Struct};
 use chunk::Chunk;
+use error::Error;
 use byteorder::{NetworkEndian, ByteOrder};
 use either::Either;
 use std::mem::transmute;
@@ -42,6 +43,14 @@ impl<'a> ChunkReader<'a> {
         unsafe{ transmute(self.read_u8()) }
     }
 
+    /// The kind of the next value, which is not read past.
+    pub fn peek_kind(&self) -> Kind {
+        let offset = self.offset.get();
+        let kind = self.read_kind();
+        self.offset.set(offset);
+        kind
+    }
+
     pub fn read_type(&self) -> Type {
         let kind = self.read_kind();
         if kind.is_primitive() {
@@ -69,6 +78,11 @@ impl<'a> ChunkReader<'a> {
                 types.push(self.read_type());
             }
             Type::compound(kind, types)
+        } else if kind == Kind::Cycle {
+            Type::cycle(self.read_utf8())
+        } else if kind == Kind::Map {
+            let types = vec![self.read_type(), self.read_type()];
+            Type::compound(kind, types)
         } else {
             let types = vec![self.read_type()];
             Type::compound(kind, types)
@@ -90,7 +104,7 @@ impl<'a> ChunkReader<'a> {
         n
     }
 
-    fn read_varint(&self) -> u64 {
+    pub fn read_varint(&self) -> u64 {
         let (msb, bits) = split_varint(self.read_u8());
         if msb {
             bits | (self.read_varint() << 7)
@@ -117,7 +131,7 @@ impl<'a> ChunkReader<'a> {
     pub fn read_struct(&self) -> Struct<'a> {
         assert_eq!(Kind::Struct, self.read_kind());
         let name = self.read_utf8();
-        let prop_count = self.read_u8() as usize;
+        let prop_count = self.read_varint() as usize;
         let mut props = HashMap::with_capacity(prop_count);
         for _ in 0..prop_count {
             let key = self.read_utf8();
@@ -127,7 +141,7 @@ impl<'a> ChunkReader<'a> {
         Struct{ name, props }
     }
 
-    fn read_utf8(&self) -> String {
+    pub fn read_utf8(&self) -> String {
         let len = self.read_varint();
         let offset = self.offset.get();
         let string = String::from_utf8(self.chunk[offset..offset + len as usize].to_vec()).unwrap();
@@ -140,27 +154,10 @@ impl<'a> ChunkReader<'a> {
         self.read_utf8()
     }
 
+    /// Reads past the next value, returning its encoded bytes. Does not require a database.
     pub fn read_item(&self) -> Vec<u8> {
         let offset = self.offset.get();
-        let kind = self.read_kind();
-        self.offset.set(offset);
-        match kind {
-            Kind::Ref       => { self.read_ref(); }
-            Kind::Boolean   => { self.read_boolean(); }
-            Kind::Number    => { self.read_number(); }
-            Kind::String    => { self.read_string(); }
-            Kind::Struct    => { self.read_struct(); }
-            Kind::Set       => { self.read_set::<Value>(); }
-            Kind::Map       => { self.read_map::<Value, Value>(); }
-            Kind::List      => { self.read_list::<Value>(); }
-            Kind::Value     => { panic!("Should not be reading a Value of type Value"); }
-            v => unimplemented!(
-                "Reader for {:?} not yet implemented\nChunk starts with: {:?}",
-                v,
-                self.chunk[offset..min(offset + 21, self.chunk.len())].to_vec(),
-                // self.chunk[offset..].to_vec(),
-            ),
-        }
+        self.skip_value(&mut vec![]).unwrap();
         self.chunk[offset..self.offset.get()].to_vec()
     }
 
@@ -185,6 +182,7 @@ impl<'a> ChunkReader<'a> {
             Kind::Set       => Value::Set(self.read_set::<Value>()),
             Kind::Map       => Value::Map(self.read_map::<Value, Value>()),
             Kind::List      => Value::List(self.read_list::<Value>()),
+            Kind::Type      => { self.read_kind(); Value::Type(self.read_type()) },
             Kind::Value     => { panic!("Should not be reading a Value of type Value"); }
             v => unimplemented!(
                 "Reader for {:?} not yet implemented\nChunk starts with: {:?}",
@@ -224,7 +222,7 @@ impl<'a> ChunkReader<'a> {
             OrderedKey::by_hash(self.read_hash())
         } else {
             self.offset.set(offset);
-            OrderedKey::by_value(self.read_raw_value())
+            OrderedKey::by_value(self.read_value())
         }
     }
 
@@ -266,6 +264,187 @@ impl<'a> ChunkReader<'a> {
             )
     }
 
+    /// Reads a blob, which is either its bytes, or the meta tuples of its chunks.
+    pub fn read_blob(&self) -> Either<Vec<u8>, Vec<MetaTuple<'a>>> {
+        assert_eq!(Kind::Blob, self.read_kind());
+        self.read_sequence(|cr| cr.read_u8())
+    }
+
+    /// Reads past the next value, collecting the hash of every ref it contains, including those
+    /// held by the meta tuples of chunked collections. Does not require a database. Fails if the
+    /// value is not encoded correctly.
+    pub fn read_refs(&self) -> Result<Vec<Hash>, Error> {
+        Ok(self.read_refs_with_heights()?.into_iter().map(|(h, _)| h).collect())
+    }
+
+    /// Reads past the next value like `read_refs`, but also returns the height of each ref.
+    pub fn read_refs_with_heights(&self) -> Result<Vec<(Hash, u64)>, Error> {
+        let mut refs = vec![];
+        self.skip_value(&mut refs)?;
+        Ok(refs)
+    }
+
+    /// An error describing why the chunk could not be read at the current offset
+    fn invalid(&self, reason: &str) -> Error {
+        Error::ConversionError(format!("Invalid chunk at byte {}: {}", self.offset.get(), reason))
+    }
+
+    /// Reads past `len` bytes, returning the offset they start at. Fails if the chunk ends first.
+    fn skip_bytes(&self, len: u64) -> Result<usize, Error> {
+        let offset = self.offset.get();
+        match (offset as u64).checked_add(len) {
+            Some(end) if end <= self.chunk.len() as u64 => {
+                self.offset.set(end as usize);
+                Ok(offset)
+            }
+            _ => Err(self.invalid("unexpected end of chunk")),
+        }
+    }
+
+    fn skip_kind(&self) -> Result<Kind, Error> {
+        let byte = self.chunk[self.skip_bytes(1)?];
+        Kind::from_u8(byte).ok_or_else(|| self.invalid(&format!("unknown kind {}", byte)))
+    }
+
+    fn skip_varint(&self) -> Result<u64, Error> {
+        let mut n = 0;
+        for shift in (0..64).step_by(7) {
+            let (msb, bits) = split_varint(self.chunk[self.skip_bytes(1)?]);
+            n |= bits << shift;
+            if !msb {
+                return Ok(n);
+            }
+        }
+        Err(self.invalid("varint is too long"))
+    }
+
+    fn skip_utf8(&self) -> Result<(), Error> {
+        let len = self.skip_varint()?;
+        let offset = self.skip_bytes(len)?;
+        match ::std::str::from_utf8(&self.chunk[offset..self.offset.get()]) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(self.invalid("string is not UTF-8")),
+        }
+    }
+
+    fn skip_type(&self) -> Result<(), Error> {
+        let kind = self.skip_kind()?;
+        if kind.is_primitive() {
+            return Ok(());
+        }
+        match kind {
+            Kind::Struct => {
+                self.skip_utf8()?;
+                let count = self.skip_varint()?;
+                for _ in 0..count {
+                    self.skip_utf8()?;
+                }
+                for _ in 0..count {
+                    self.skip_type()?;
+                }
+                self.skip_bytes(count)?;
+            }
+            Kind::Union => {
+                for _ in 0..self.skip_varint()? {
+                    self.skip_type()?;
+                }
+            }
+            Kind::Cycle => self.skip_utf8()?,
+            Kind::Map => { self.skip_type()?; self.skip_type()?; }
+            Kind::List | Kind::Ref | Kind::Set => self.skip_type()?,
+            _ => return Err(self.invalid(&format!("{:?} is not a type", kind))),
+        }
+        Ok(())
+    }
+
+    fn skip_value(&self, refs: &mut Vec<(Hash, u64)>) -> Result<(), Error> {
+        match self.skip_kind()? {
+            Kind::Boolean   => { self.skip_bytes(1)?; }
+            Kind::Number    => { self.skip_varint()?; self.skip_varint()?; }
+            Kind::String    => self.skip_utf8()?,
+            Kind::Type      => self.skip_type()?,
+            Kind::Ref       => self.skip_ref(refs)?,
+            Kind::Struct    => {
+                self.skip_utf8()?;
+                let prop_count = self.skip_varint()?;
+                for _ in 0..prop_count {
+                    self.skip_utf8()?;
+                    self.skip_value(refs)?;
+                }
+            }
+            Kind::Blob      => self.skip_sequence(refs, |cr, _| cr.skip_bytes(1).map(|_| ()))?,
+            Kind::List | Kind::Set
+                            => self.skip_sequence(refs, |cr, refs| cr.skip_value(refs))?,
+            Kind::Map       => self.skip_sequence(refs, |cr, refs| { cr.skip_value(refs)?; cr.skip_value(refs) })?,
+            kind => return Err(self.invalid(&format!("cannot read a value of kind {:?}", kind))),
+        }
+        Ok(())
+    }
+
+    fn skip_ref(&self, refs: &mut Vec<(Hash, u64)>) -> Result<(), Error> {
+        let offset = self.skip_bytes(BYTE_LEN as u64)?;
+        let mut bytes = [0; BYTE_LEN];
+        bytes.copy_from_slice(&self.chunk[offset..offset + BYTE_LEN]);
+        self.skip_type()?;
+        refs.push((Hash::new(bytes), self.skip_varint()?));
+        Ok(())
+    }
+
+    fn skip_sequence<F>(&self, refs: &mut Vec<(Hash, u64)>, skip: F) -> Result<(), Error>
+    where F: Fn(&Self, &mut Vec<(Hash, u64)>) -> Result<(), Error> {
+        let level = self.skip_varint()?;
+        let len = self.skip_varint()?;
+        for _ in 0..len {
+            if level == 0 {
+                skip(self, refs)?;
+            } else {
+                self.skip_metatuple(refs)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_metatuple(&self, refs: &mut Vec<(Hash, u64)>) -> Result<u64, Error> {
+        if self.skip_kind()? != Kind::Ref {
+            return Err(self.invalid("meta tuple does not start with a ref"));
+        }
+        self.skip_ref(refs)?;
+        self.skip_ordered_key(refs)?;
+        self.skip_varint()
+    }
+
+    fn skip_ordered_key(&self, refs: &mut Vec<(Hash, u64)>) -> Result<(), Error> {
+        let offset = self.offset.get();
+        if self.skip_kind()? == Kind::Hash {
+            self.skip_bytes(BYTE_LEN as u64)?;
+            Ok(())
+        } else {
+            self.offset.set(offset);
+            self.skip_value(refs)
+        }
+    }
+
+    /// Reads a meta tuple like `read_metatuple`, but returns only the hash of the chunk it refers
+    /// to and its number of leaves, so that it does not require a database.
+    pub fn read_metatuple_leaves(&self) -> (Hash, u64) {
+        let mut refs = vec![];
+        let leaves = self.skip_metatuple(&mut refs).unwrap();
+        (refs[0].0, leaves)
+    }
+
+    /// Reads a meta tuple as its encoded bytes, along with the encoded bytes of its key and its
+    /// number of leaves. Does not require a database.
+    pub fn read_metatuple_bytes(&self) -> (Vec<u8>, Vec<u8>, u64) {
+        let start = self.offset.get();
+        assert_eq!(Kind::Ref, self.read_kind());
+        self.skip_ref(&mut vec![]).unwrap();
+        let key_start = self.offset.get();
+        self.skip_ordered_key(&mut vec![]).unwrap();
+        let key = self.chunk[key_start..self.offset.get()].to_vec();
+        let leaves = self.read_varint();
+        (self.chunk[start..self.offset.get()].to_vec(), key, leaves)
+    }
+
     pub fn empty(&self) -> bool {
         self.offset.get() >= self.chunk.len()
     }
@@ -0,0 +1,371 @@
+//! Generates Rust types which can hold the values of Noms types, using `#[derive(Noms)]`, so that
+//! datasets written by other programs can be read without writing their types out by hand.
+//!
+//! Structs become Rust structs with `snake_case` fields, renamed with `#[noms(rename = "...")]`
+//! where the derived name would differ from the stored one. Optional fields become `Option`s, and
+//! lists, sets, maps and refs become `NomsList`, `NomsSet`, `NomsMap` and `Ref`. Unions of named
+//! structs become enums with a variant for each struct. Anything else, such as a union of a number
+//! and a string, is read as a `NomsValue`.
+//!
+//! The generated source refers to `nomrs` by its full paths, but expects the `Noms` derive to be
+//! in scope, so it can be written to a file by a build script and included with `include!`:
+//!
+//! ```ignore
+//! let db = Noms::new().database().http("localhost:8000")?;
+//! let source = nomrs::codegen::dataset_source(&db, "people")?;
+//! fs::write(Path::new(&env::var("OUT_DIR")?).join("people.rs"), source)?;
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use database::Database;
+use value::{Kind, Type};
+use error::Error;
+
+const NOMS_VALUE: &'static str = "::nomrs::value::NomsValue<'a>";
+const KEYWORDS: &'static [&'static str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in",
+    "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "static", "struct", "super", "trait", "true", "try", "type", "typeof",
+    "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Generates the types of the value and metadata of the dataset's head commit, named after the
+/// dataset: the dataset `people` gives the aliases `PeopleValue` and `PeopleMeta`.
+pub fn dataset_source<D: Database>(database: &D, ds: &str) -> Result<String, Error> {
+    let head = database.datasets()?
+        .get(ds)?
+        .ok_or_else(|| Error::NoDataset(ds.to_string()))?;
+    let commit = head.target_type();
+    let fields = commit.fields();
+    let field = |name: &str| fields.iter()
+        .find(|f| f.name == name)
+        .map(|f| f.field_type)
+        .ok_or_else(|| Error::ConversionError(format!("The head of {} is not a commit", ds)));
+    let name = pascal_case(ds);
+    Ok(source(&[(&format!("{}Value", name), field("value")?), (&format!("{}Meta", name), field("meta")?)]))
+}
+
+/// Generates a type alias for each of the types, with the given names, along with the structs and
+/// enums which they use.
+pub fn source(types: &[(&str, &Type)]) -> String {
+    let mut generator = Generator::default();
+    generator.taken.extend(types.iter().map(|&(name, _)| name.to_string()));
+    let mut aliases = vec![];
+    for &(name, t) in types {
+        let rust_type = generator.rust_type(t, name);
+        aliases.push(format!("pub type {}{} = {};\n", name, generics(rust_type.1), rust_type.0));
+    }
+    let mut source = String::new();
+    for item in aliases.iter().chain(&generator.items) {
+        if !source.is_empty() {
+            source.push('\n');
+        }
+        source.push_str(item);
+    }
+    source
+}
+
+/// The Rust type for a Noms type, and whether it needs the lifetime `'a` of the database
+type RustType = (String, bool);
+
+#[derive(Default)]
+struct Generator {
+    /// The source of each struct and enum, in the order they were first used
+    items: Vec<String>,
+    /// The structs and enums generated so far, by the types they hold
+    generated: HashMap<Type, RustType>,
+    /// The names of the items and aliases
+    taken: HashSet<String>,
+    /// The Noms and Rust names of the structs being generated, which cycles refer back to
+    enclosing: Vec<(String, String)>,
+}
+
+impl Generator {
+    /// Finds the Rust type for a Noms type, generating the structs and enums it needs. Anonymous
+    /// structs and enums are named after `hint`.
+    fn rust_type(&mut self, t: &Type, hint: &str) -> RustType {
+        match t.kind() {
+            Kind::Boolean => ("bool".to_string(), false),
+            Kind::Number => ("f64".to_string(), false),
+            Kind::String => ("String".to_string(), false),
+            Kind::Type => ("::nomrs::value::Type".to_string(), false),
+            Kind::Ref => match self.rust_type(&t.element_types()[0], &format!("{}Target", hint)) {
+                (ref target, _) if target == NOMS_VALUE => ("::nomrs::value::Ref<'a>".to_string(), true),
+                (target, _) => (format!("::nomrs::value::Ref<'a, {}>", target), true),
+            },
+            Kind::List => {
+                let element = self.rust_type(&t.element_types()[0], &format!("{}Item", hint));
+                (format!("::nomrs::value::NomsList<'a, {}>", element.0), true)
+            }
+            Kind::Set => {
+                let element = self.key_type(&t.element_types()[0], &format!("{}Item", hint));
+                (format!("::nomrs::value::NomsSet<'a, {}>", element.0), true)
+            }
+            Kind::Map => {
+                let key = self.key_type(&t.element_types()[0], &format!("{}Key", hint));
+                let value = self.rust_type(&t.element_types()[1], &format!("{}Value", hint));
+                (format!("::nomrs::value::NomsMap<'a, {}, {}>", key.0, value.0), true)
+            }
+            Kind::Struct if t.name().is_none_or(str::is_empty) && t.fields().is_empty() => {
+                ("::nomrs::value::Empty".to_string(), false)
+            }
+            Kind::Struct => self.structure(t, hint),
+            Kind::Union => self.union(t, hint),
+            // cycles always pass through a collection or ref, so the struct holds the lifetime
+            Kind::Cycle => match self.enclosing.iter().rev().find(|s| Some(s.0.as_str()) == t.name()) {
+                Some(&(_, ref name)) => (format!("{}<'a>", name), true),
+                None => (NOMS_VALUE.to_string(), true),
+            },
+            _ => (NOMS_VALUE.to_string(), true),
+        }
+    }
+
+    /// Finds the Rust type for the keys of a map or set, which must be hashable
+    fn key_type(&mut self, t: &Type, hint: &str) -> RustType {
+        match t.kind() {
+            Kind::Boolean | Kind::String | Kind::Ref | Kind::Type => self.rust_type(t, hint),
+            _ => (NOMS_VALUE.to_string(), true),
+        }
+    }
+
+    fn structure(&mut self, t: &Type, hint: &str) -> RustType {
+        if let Some(rust_type) = self.generated.get(t) {
+            return rust_type.clone();
+        }
+        let noms_name = t.name().unwrap_or_default();
+        let name = self.unique_name(if noms_name.is_empty() { hint } else { noms_name });
+        let slot = self.reserve();
+        self.enclosing.push((noms_name.to_string(), name.clone()));
+        let (fields, lifetime) = self.fields(t, "    pub ");
+        self.enclosing.pop();
+
+        let mut item = "#[derive(Clone, Debug, Noms)]\n".to_string();
+        if name != noms_name {
+            item.push_str(&format!("#[noms(name = {:?})]\n", noms_name));
+        }
+        item.push_str(&format!("pub struct {}{} {{\n{}}}\n", name, generics(lifetime), fields));
+        self.items[slot] = item;
+        self.named(t, name, lifetime)
+    }
+
+    /// Generates an enum for a union of structs with different names. Other unions cannot be
+    /// derived, so are read as values.
+    fn union(&mut self, t: &Type, hint: &str) -> RustType {
+        if let Some(rust_type) = self.generated.get(t) {
+            return rust_type.clone();
+        }
+        let members = t.element_types();
+        let names: HashSet<&str> = members.iter().filter_map(Type::name).filter(|n| !n.is_empty()).collect();
+        if members.is_empty() || names.len() != members.len() || members.iter().any(|m| m.kind() != Kind::Struct) {
+            return (NOMS_VALUE.to_string(), true);
+        }
+        let name = self.unique_name(hint);
+        let slot = self.reserve();
+        let mut variants = String::new();
+        let mut lifetime = false;
+        for member in members {
+            let noms_name = member.name().unwrap_or_default();
+            let variant = pascal_case(noms_name);
+            if variant != noms_name {
+                variants.push_str(&format!("    #[noms(rename = {:?})]\n", noms_name));
+            }
+            self.enclosing.push((noms_name.to_string(), name.clone()));
+            let (fields, member_lifetime) = self.fields(member, "        ");
+            self.enclosing.pop();
+            lifetime |= member_lifetime;
+            variants.push_str(&format!("    {} {{\n{}    }},\n", variant, fields));
+        }
+        self.items[slot] = format!("#[derive(Clone, Debug, Noms)]\npub enum {}{} {{\n{}}}\n", name, generics(lifetime), variants);
+        self.named(t, name, lifetime)
+    }
+
+    /// Generates the fields of a struct or variant, each starting with `prefix`, and whether any
+    /// of them need the lifetime of the database.
+    fn fields(&mut self, t: &Type, prefix: &str) -> (String, bool) {
+        let indent = prefix.trim_end_matches("pub ");
+        let mut source = String::new();
+        let mut lifetime = false;
+        for field in t.fields() {
+            let ident = snake_case(field.name);
+            let (mut rust_type, field_lifetime) = self.rust_type(field.field_type, field.name);
+            lifetime |= field_lifetime;
+            if rust_type == NOMS_VALUE && field.field_type.kind() != Kind::Value {
+                source.push_str(&format!("{}/// Holds {}\n", indent, field.field_type));
+            }
+            if camel_case(&ident) != field.name {
+                source.push_str(&format!("{}#[noms(rename = {:?})]\n", indent, field.name));
+            }
+            if field.optional {
+                rust_type = format!("Option<{}>", rust_type);
+            }
+            source.push_str(&format!("{}{}: {},\n", prefix, ident, rust_type));
+        }
+        (source, lifetime)
+    }
+
+    /// Reserves a place for an item, so that items are written before the ones they use
+    fn reserve(&mut self) -> usize {
+        self.items.push(String::new());
+        self.items.len() - 1
+    }
+
+    fn named(&mut self, t: &Type, name: String, lifetime: bool) -> RustType {
+        let rust_type = (if lifetime { format!("{}<'a>", name) } else { name }, lifetime);
+        self.generated.insert(t.clone(), rust_type.clone());
+        rust_type
+    }
+
+    /// Names an item after `name`, numbering it if the name is already taken
+    fn unique_name(&mut self, name: &str) -> String {
+        let name = pascal_case(name);
+        let mut unique = name.clone();
+        let mut n = 1;
+        while !self.taken.insert(unique.clone()) {
+            n += 1;
+            unique = format!("{}{}", name, n);
+        }
+        unique
+    }
+}
+
+fn generics(lifetime: bool) -> &'static str {
+    if lifetime { "<'a>" } else { "" }
+}
+
+/// Splits a name into lowercase words, at underscores, punctuation and before capital letters
+/// which do not continue an acronym.
+fn words(name: &str) -> Vec<String> {
+    let mut words = vec![];
+    for part in name.split(|c: char| !c.is_ascii_alphanumeric()).filter(|part| !part.is_empty()) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (i, &c) in chars.iter().enumerate() {
+            let after_lower = i > 0 && !chars[i - 1].is_uppercase();
+            let ends_acronym = i > 0 && chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if c.is_uppercase() && !word.is_empty() && (after_lower || ends_acronym) {
+                words.push(word);
+                word = String::new();
+            }
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+    }
+    words
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn pascal_case(name: &str) -> String {
+    let name: String = words(name).iter().map(|word| capitalize(word)).collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_digit() => format!("T{}", name),
+        Some(_) => name,
+        None => "Value".to_string(),
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let name = words(name).join("_");
+    if name.starts_with(|c: char| c.is_ascii_digit()) || KEYWORDS.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
+
+/// Converts a field's Rust name to Noms as `#[derive(Noms)]` does, splitting words only at
+/// underscores and capitals.
+fn camel_case(ident: &str) -> String {
+    let mut words = vec![];
+    for part in ident.split('_').filter(|part| !part.is_empty()) {
+        let mut word = String::new();
+        for c in part.chars() {
+            if c.is_uppercase() && !word.is_empty() {
+                words.push(word);
+                word = String::new();
+            }
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+    }
+    let mut words = words.iter();
+    let first = words.next().cloned().unwrap_or_default();
+    words.fold(first, |name, word| name + &capitalize(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(t: &str) -> String {
+        source(&[("Data", &t.parse().unwrap())])
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(snake_case("countFemale"), "count_female");
+        assert_eq!(snake_case("userID"), "user_id");
+        assert_eq!(snake_case("HTTPServer"), "http_server");
+        assert_eq!(snake_case("type"), "type_");
+        assert_eq!(pascal_case("people-2020"), "People2020");
+        assert_eq!(pascal_case("2020"), "T2020");
+        assert_eq!(camel_case("user_id"), "userId");
+        assert_eq!(camel_case("type_"), "type");
+    }
+
+    #[test]
+    fn structs() {
+        assert_eq!(
+            generate("List<Struct Row { countFemale: String, countMale?: Number, ID: Number, tags: Set<String> }>"),
+            "pub type Data<'a> = ::nomrs::value::NomsList<'a, Row<'a>>;\n\
+             \n\
+             #[derive(Clone, Debug, Noms)]\n\
+             pub struct Row<'a> {\n    \
+                 #[noms(rename = \"ID\")]\n    \
+                 pub id: f64,\n    \
+                 pub count_female: String,\n    \
+                 pub count_male: Option<f64>,\n    \
+                 pub tags: ::nomrs::value::NomsSet<'a, String>,\n\
+             }\n",
+        );
+        assert_eq!(generate("Struct {}"), "pub type Data = ::nomrs::value::Empty;\n");
+    }
+
+    #[test]
+    fn unions_and_cycles() {
+        assert_eq!(
+            generate("Map<String, Struct tree_node { children: List<Ref<Cycle<tree_node>>>, value: Number | String }>"),
+            "pub type Data<'a> = ::nomrs::value::NomsMap<'a, String, TreeNode<'a>>;\n\
+             \n\
+             #[derive(Clone, Debug, Noms)]\n\
+             #[noms(name = \"tree_node\")]\n\
+             pub struct TreeNode<'a> {\n    \
+                 pub children: ::nomrs::value::NomsList<'a, ::nomrs::value::Ref<'a, TreeNode<'a>>>,\n    \
+                 /// Holds Number | String\n    \
+                 pub value: ::nomrs::value::NomsValue<'a>,\n\
+             }\n",
+        );
+        assert_eq!(
+            generate("List<Struct Click { x: Number } | Struct key_press { key: String }>"),
+            "pub type Data<'a> = ::nomrs::value::NomsList<'a, DataItem>;\n\
+             \n\
+             #[derive(Clone, Debug, Noms)]\n\
+             pub enum DataItem {\n    \
+                 Click {\n        \
+                     x: f64,\n    \
+                 },\n    \
+                 #[noms(rename = \"key_press\")]\n    \
+                 KeyPress {\n        \
+                     key: String,\n    \
+                 },\n\
+             }\n",
+        );
+    }
+}
@@ -0,0 +1,304 @@
+//! Imports and exports CSV files, following the conventions of the `csv-import` and `csv-export`
+//! commands of Go Noms.
+//!
+//! Each row becomes a struct, with a field for each column, and the rows are held by a list, or by
+//! a map keyed by one of the columns. Large imports are split into many chunks, so that they are
+//! never held by a single chunk.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::time::SystemTime;
+use csv_crate::{ReaderBuilder, Writer};
+use database::{Database, CommitOptions};
+use dataset::Dataset;
+use value::{NomsValue, Value, Struct, IntoNoms, encode_struct, write_list, write_map, number_to_string};
+use json::escape_field;
+use util::date;
+use error::Error;
+
+const DEFAULT_STRUCT_NAME: &'static str = "Row";
+const META_STRUCT_NAME: &'static str = "Meta";
+
+/// The type of the values of a column
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColumnType {
+    /// Every cell is kept as it is, even when empty
+    String,
+    /// Cells are parsed as numbers. Empty cells are left out of their row.
+    Number,
+    /// Cells are parsed as booleans, accepting the same strings as Go's `strconv.ParseBool`. Empty
+    /// cells are left out of their row.
+    Bool,
+}
+
+impl Default for ColumnType {
+    fn default() -> Self { ColumnType::String }
+}
+
+impl ColumnType {
+    /// Converts a cell to this type, or `None` if the cell is empty and should be left out.
+    fn encode(self, cell: &str) -> Result<Option<Vec<u8>>, Error> {
+        if cell.is_empty() && self != ColumnType::String {
+            return Ok(None);
+        }
+        Ok(Some(match self {
+            ColumnType::String => cell.into_noms(),
+            ColumnType::Number => match cell.parse::<i64>() {
+                Ok(i) => i.into_noms(),
+                Err(_) => cell.parse::<f64>()
+                    .map_err(|_| Error::ConversionError(format!("{:?} is not a number", cell)))?
+                    .into_noms(),
+            },
+            ColumnType::Bool => match cell {
+                "1" | "t" | "T" | "TRUE" | "true" | "True" => true.into_noms(),
+                "0" | "f" | "F" | "FALSE" | "false" | "False" => false.into_noms(),
+                _ => return Err(Error::ConversionError(format!("{:?} is not a boolean", cell))),
+            },
+        }))
+    }
+}
+
+/// Reads CSV files into a database. By default, the first row names the columns, every column
+/// holds strings, and the rows are held by a list of structs named `Row`.
+#[derive(Clone, Debug)]
+pub struct Importer {
+    struct_name: String,
+    header: Option<Vec<String>>,
+    column_types: Vec<ColumnType>,
+    key_column: Option<String>,
+    delimiter: u8,
+}
+
+impl Default for Importer {
+    fn default() -> Self {
+        Importer{
+            struct_name: DEFAULT_STRUCT_NAME.to_string(),
+            header: None,
+            column_types: vec![],
+            key_column: None,
+            delimiter: b',',
+        }
+    }
+}
+
+impl Importer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the struct each row becomes
+    pub fn struct_name(self, name: &str) -> Self {
+        Self{ struct_name: name.to_string(), ..self }
+    }
+
+    /// Names the columns, so that the first row is read as data instead
+    pub fn header(self, header: Vec<String>) -> Self {
+        Self{ header: Some(header), ..self }
+    }
+
+    /// Sets the types of the columns, in order. Columns without a type hold strings.
+    pub fn column_types(self, column_types: Vec<ColumnType>) -> Self {
+        Self{ column_types, ..self }
+    }
+
+    /// Holds the rows in a map keyed by the value of the named column, rather than a list. Later
+    /// rows replace earlier ones with the same key.
+    pub fn key_column(self, column: &str) -> Self {
+        Self{ key_column: Some(column.to_string()), ..self }
+    }
+
+    /// Sets the byte which separates the cells of a row
+    pub fn delimiter(self, delimiter: u8) -> Self {
+        Self{ delimiter, ..self }
+    }
+
+    /// Reads a CSV file, writing its rows to the database. The returned list or map is not written
+    /// itself, so it should be committed, or held by a value which is.
+    pub fn import<'a, D, R>(&self, database: &'a D, reader: R) -> Result<NomsValue<'a>, Error>
+    where D: Database, R: Read {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .has_headers(false)
+            .from_reader(reader);
+        let mut records = reader.records();
+        let header = match self.header {
+            Some(ref header) => header.clone(),
+            None => match records.next() {
+                Some(record) => record?.iter().map(str::to_string).collect(),
+                None => vec![],
+            },
+        };
+        let fields: Vec<String> = header.iter().map(|column| escape_column(column)).collect();
+        for (i, field) in fields.iter().enumerate() {
+            if fields[..i].contains(field) {
+                return Err(Error::ConversionError(format!("More than one column is named {}", field)));
+            }
+        }
+        let types: Vec<ColumnType> = (0..fields.len())
+            .map(|i| self.column_types.get(i).cloned().unwrap_or_default())
+            .collect();
+        let key = match self.key_column {
+            Some(ref column) => Some(
+                header.iter()
+                    .position(|c| c == column)
+                    .ok_or_else(|| Error::ConversionError(format!("There is no column named {}", column)))?
+            ),
+            None => None,
+        };
+
+        let mut rows = vec![];
+        for (line, record) in records.enumerate() {
+            let record = record?;
+            if record.len() != fields.len() {
+                return Err(Error::ConversionError(format!("Row {} has {} cells, but there are {} columns", line + 1, record.len(), fields.len())));
+            }
+            let mut props = HashMap::new();
+            let mut key_bytes = None;
+            for (i, cell) in record.iter().enumerate() {
+                if let Some(bytes) = types[i].encode(cell)? {
+                    if key == Some(i) {
+                        key_bytes = Some(bytes.clone());
+                    }
+                    props.insert(fields[i].clone(), bytes);
+                }
+            }
+            let row = encode_struct(&self.struct_name, props);
+            match key {
+                Some(_) => {
+                    let key_bytes = key_bytes
+                        .ok_or_else(|| Error::ConversionError(format!("Row {} has no key", line + 1)))?;
+                    rows.push((key_bytes, row));
+                }
+                None => rows.push((vec![], row)),
+            }
+        }
+
+        let root = match key {
+            Some(_) => write_map(database, rows)?,
+            None => write_list(database, rows.into_iter().map(|(_, row)| row).collect())?,
+        };
+        Ok(database.value_from(root))
+    }
+
+    /// Imports a CSV file, and commits it to the dataset. The commit's meta struct records when
+    /// the import happened, and the name of the file that was read.
+    pub fn commit<'a, D, R>(&self, database: &'a D, dataset: &str, reader: R, input_file: &str) -> Result<Dataset<'a>, Error>
+    where D: Database, R: Read {
+        let value = self.import(database, reader)?;
+        let mut meta = HashMap::new();
+        meta.insert("date".to_string(), date::rfc3339(SystemTime::now()).into_noms());
+        meta.insert("inputFile".to_string(), input_file.into_noms());
+        let options = CommitOptions{
+            meta: database.value_from(encode_struct(META_STRUCT_NAME, meta)),
+            ..CommitOptions::default()
+        };
+        let ds = database.dataset_or_empty(dataset)?;
+        database.commit(ds, value, options)
+    }
+}
+
+/// Turns a column name into a struct field name as Go Noms does. Valid names are kept, and others
+/// are split into words at each character which is not a letter or digit, and joined in camel
+/// case. Anything which is still not allowed is then escaped.
+fn escape_column(column: &str) -> String {
+    let valid = column.chars().enumerate().all(|(i, c)| c.is_ascii_alphabetic() || (i > 0 && (c.is_ascii_digit() || c == '_')));
+    if valid && !column.is_empty() {
+        return column.to_string();
+    }
+    let mut field = String::with_capacity(column.len());
+    for (i, word) in column.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).enumerate() {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            if i == 0 {
+                field.extend(first.to_lowercase());
+            } else {
+                field.extend(first.to_uppercase());
+            }
+            field.extend(chars);
+        }
+    }
+    escape_field(&field)
+}
+
+/// Writes a list or map of structs as a CSV file, with a column for each field of the first row.
+/// The rows of a list are read one chunk at a time as they are written, and the rows of a map are
+/// written in order of their keys.
+pub fn export<'a, W: Write>(value: &NomsValue<'a>, writer: W) -> Result<(), Error> {
+    let mut writer = Writer::from_writer(writer);
+    let mut columns = None;
+    match value.clone().import().compile() {
+        l @ Value::List(_) => {
+            for row in l.to_list::<NomsValue>().unwrap().iter() {
+                export_row(row?, &mut columns, &mut writer)?;
+            }
+        }
+        m @ Value::Map(_) => {
+            for entry in m.to_map::<NomsValue, NomsValue>().unwrap().iter() {
+                export_row(entry?.1, &mut columns, &mut writer)?;
+            }
+        }
+        v => return Err(Error::ConversionError(format!("{:?} cannot be exported as CSV", v))),
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn export_row<'a, W: Write>(row: NomsValue<'a>, columns: &mut Option<Vec<String>>, writer: &mut Writer<W>) -> Result<(), Error> {
+    let mut props = match row.import().compile() {
+        Value::Struct(Struct{ props, .. }) => props,
+        v => return Err(Error::ConversionError(format!("{:?} is not a struct, so cannot be a row", v))),
+    };
+    if columns.is_none() {
+        let mut header: Vec<String> = props.keys().cloned().collect();
+        header.sort();
+        writer.write_record(&header)?;
+        *columns = Some(header);
+    }
+    let columns = columns.as_ref().unwrap();
+    let mut cells = Vec::with_capacity(columns.len());
+    for column in columns {
+        cells.push(match props.remove(column) {
+            Some(value) => format_cell(value.import())?,
+            None => String::new(),
+        });
+    }
+    if let Some(field) = props.keys().next() {
+        return Err(Error::ConversionError(format!("The field {} is not a column of the first row", field)));
+    }
+    writer.write_record(&cells)?;
+    Ok(())
+}
+
+fn format_cell(value: Value) -> Result<String, Error> {
+    Ok(match value.compile() {
+        Value::String(s) => s,
+        Value::Boolean(b) => b.to_string(),
+        Value::Number(i, e) => number_to_string(i, e),
+        Value::Union(inner) => format_cell(*inner)?,
+        v => return Err(Error::ConversionError(format!("{:?} cannot be written to a CSV cell", v))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_columns() {
+        assert_eq!(escape_column("name"), "name");
+        assert_eq!(escape_column("count_female"), "count_female");
+        assert_eq!(escape_column("Year of birth"), "yearOfBirth");
+        assert_eq!(escape_column("2nd-place"), "Q32ndPlace");
+    }
+
+    #[test]
+    fn typed_cells() {
+        assert_eq!(ColumnType::String.encode("").unwrap(), Some("".into_noms()));
+        assert_eq!(ColumnType::Number.encode("").unwrap(), None);
+        assert_eq!(ColumnType::Number.encode("-12").unwrap(), Some((-12i64).into_noms()));
+        assert_eq!(ColumnType::Number.encode("1.5").unwrap(), Some(1.5f64.into_noms()));
+        assert_eq!(ColumnType::Bool.encode("T").unwrap(), Some(true.into_noms()));
+        assert!(ColumnType::Number.encode("many").is_err());
+        assert!(ColumnType::Bool.encode("yes").is_err());
+    }
+}
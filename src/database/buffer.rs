@@ -0,0 +1,191 @@
+//! Holds chunks which have been written locally, but not yet sent to the server
+
+use std::collections::{HashMap, HashSet};
+use hash::Hash;
+use chunk::ChunkReader;
+use error::Error;
+
+/// The most chunk data to send to the server in a single request. A buffer holding this much is
+/// considered full, and should be flushed.
+pub(crate) const MAX_BATCH_BYTES: usize = 1 << 24;
+
+/// A buffer of chunks waiting to be written. Chunks are kept in the order they were put, so that
+/// they reach the server in the same order.
+///
+/// The buffer also tracks which refs held by the written chunks point to chunks that were not
+/// written since the last commit, so that they can be checked before the root is moved.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WriteBuffer {
+    chunks: Vec<(Hash, Vec<u8>)>,
+    index: HashMap<Hash, usize>,
+    size: usize,
+    written: HashSet<Hash>,
+    unresolved: HashSet<Hash>,
+}
+
+impl WriteBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a chunk to the buffer. Returns false if the chunk was already waiting to be written.
+    /// Fails, leaving the buffer as it was, if the chunk is not encoded correctly.
+    pub fn insert(&mut self, h: Hash, data: Vec<u8>) -> Result<bool, Error> {
+        if self.index.contains_key(&h) {
+            return Ok(false);
+        }
+        for r in ChunkReader::new(None, &data).read_refs()? {
+            if !self.written.contains(&r) {
+                self.unresolved.insert(r);
+            }
+        }
+        self.unresolved.remove(&h);
+        self.written.insert(h);
+        self.size += data.len();
+        self.index.insert(h, self.chunks.len());
+        self.chunks.push((h, data));
+        Ok(true)
+    }
+
+    pub fn get(&self, h: &Hash) -> Option<&Vec<u8>> {
+        self.index.get(h).map(|&i| &self.chunks[i].1)
+    }
+
+    pub fn contains(&self, h: &Hash) -> bool {
+        self.index.contains_key(h)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.size >= MAX_BATCH_BYTES
+    }
+
+    /// Whether the chunk was written since the last commit, even if it has been flushed already.
+    pub fn was_written(&self, h: &Hash) -> bool {
+        self.written.contains(h)
+    }
+
+    /// The refs held by written chunks that point to chunks that were not written since the last
+    /// commit. These must already exist in the database for the written chunks to be complete.
+    pub fn unresolved(&self) -> &HashSet<Hash> {
+        &self.unresolved
+    }
+
+    /// Forgets which chunks were written, once they have been committed.
+    pub fn committed(&mut self) {
+        self.written.clear();
+        self.unresolved.clear();
+    }
+
+    /// The chunks to send to the server next: those put first, up to `MAX_BATCH_BYTES` of them. A
+    /// chunk larger than that is sent in a batch of its own. The batch is empty once every chunk
+    /// has been sent.
+    pub fn batch(&self) -> &[(Hash, Vec<u8>)] {
+        let (mut len, mut size) = (0, 0);
+        for &(_, ref data) in &self.chunks {
+            if len > 0 && size + data.len() > MAX_BATCH_BYTES {
+                break;
+            }
+            size += data.len();
+            len += 1;
+        }
+        &self.chunks[..len]
+    }
+
+    /// Removes the first `len` chunks, once the server has them, returning them.
+    pub fn sent(&mut self, len: usize) -> Vec<(Hash, Vec<u8>)> {
+        let sent: Vec<_> = self.chunks.drain(..len).collect();
+        for &(ref h, ref data) in &sent {
+            self.index.remove(h);
+            self.size -= data.len();
+        }
+        for i in self.index.values_mut() {
+            *i -= len;
+        }
+        sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::hash;
+    use value::{IntoNoms, encode_struct};
+
+    #[test]
+    fn write_buffer_ignores_duplicates() {
+        let a = "a".into_noms();
+        let mut buffer = WriteBuffer::new();
+        assert!(buffer.insert(hash(&a), a.clone()).unwrap());
+        assert!(!buffer.insert(hash(&a), a.clone()).unwrap());
+        assert_eq!(buffer.get(&hash(&a)), Some(&a));
+        assert_eq!(buffer.batch(), &[(hash(&a), a.clone())][..]);
+        assert_eq!(buffer.sent(1), vec![(hash(&a), a.clone())]);
+        assert!(buffer.batch().is_empty());
+        assert!(!buffer.contains(&hash(&a)));
+    }
+
+    #[test]
+    fn write_buffer_tracks_unresolved_refs() {
+        let target = "target".into_noms();
+        let mut reference = vec![7];
+        reference.extend_from_slice(&hash(&target).raw_bytes());
+        reference.extend_from_slice(&[2, 1]);
+        let mut buffer = WriteBuffer::new();
+        buffer.insert(hash(&reference), reference).unwrap();
+        assert!(buffer.unresolved().contains(&hash(&target)));
+        buffer.insert(hash(&target), target.clone()).unwrap();
+        assert!(buffer.unresolved().is_empty());
+        buffer.sent(2);
+        assert!(buffer.was_written(&hash(&target)));
+        buffer.committed();
+        assert!(!buffer.was_written(&hash(&target)));
+    }
+
+    #[test]
+    fn write_buffer_reads_refs_of_wide_structs() {
+        // more than 127 fields, so that the field count takes two bytes
+        let target = "target".into_noms();
+        let mut reference = vec![7];
+        reference.extend_from_slice(&hash(&target).raw_bytes());
+        reference.extend_from_slice(&[2, 1]);
+        let mut props: HashMap<_, _> = (0..200).map(|i| (format!("f{:03}", i), (i as u64).into_noms())).collect();
+        props.insert("last".to_string(), reference);
+        let wide = encode_struct("Wide", props);
+        let mut buffer = WriteBuffer::new();
+        buffer.insert(hash(&wide), wide).unwrap();
+        assert_eq!(buffer.unresolved().iter().collect::<Vec<_>>(), vec![&hash(&target)]);
+    }
+
+    #[test]
+    fn write_buffer_splits_batches() {
+        let (a, b) = ("a".into_noms(), "b".into_noms());
+        let big = "x".repeat(MAX_BATCH_BYTES - 8).into_noms();
+        let huge = "x".repeat(MAX_BATCH_BYTES).into_noms();
+        let mut buffer = WriteBuffer::new();
+        buffer.insert(hash(&a), a.clone()).unwrap();
+        buffer.insert(hash(&big), big.clone()).unwrap();
+        buffer.insert(hash(&b), b.clone()).unwrap();
+        buffer.insert(hash(&huge), huge.clone()).unwrap();
+        assert!(buffer.is_full());
+        assert_eq!(buffer.batch().len(), 2);
+        buffer.sent(2);
+        assert!(!buffer.contains(&hash(&a)));
+        assert_eq!(buffer.get(&hash(&b)), Some(&b));
+        assert_eq!(buffer.batch(), &[(hash(&b), b.clone())][..]);
+        buffer.sent(1);
+        assert_eq!(buffer.batch().len(), 1);
+        assert_eq!(buffer.get(&hash(&huge)), Some(&huge));
+    }
+
+    #[test]
+    fn write_buffer_rejects_malformed_chunks() {
+        let mut truncated = vec![7];
+        truncated.extend_from_slice(&hash(&"target".into_noms()).raw_bytes()[..5]);
+        let mut buffer = WriteBuffer::new();
+        for chunk in vec![truncated, vec![200], vec![2, 3, 0xff, 0xfe, 0xfd], vec![1, 0x80], vec![4]] {
+            assert!(buffer.insert(hash(&chunk), chunk.clone()).is_err(), "Accepted {:?}", chunk);
+        }
+        assert!(buffer.batch().is_empty());
+        assert!(buffer.unresolved().is_empty());
+    }
+}
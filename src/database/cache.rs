@@ -0,0 +1,450 @@
+//! Defines a database that keeps a copy of every chunk it retrieves on the local disk, so that
+//! they do not need to be downloaded again, even by a later process.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::cell::{Cell, RefCell};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, UNIX_EPOCH};
+use super::{http, CommitOptions, ChunkStore, ServerStats};
+use super::stats::{count, hit_rate};
+use value::{NomsValue, NomsStruct, Value, Ref, FromNoms, IntoNoms, NomsMap, Commit};
+use dataset::Dataset;
+use error::Error;
+use hash::{Hash, STRING_LEN};
+use chunk::{Chunk, ChunkReader};
+use serde_json::Value as Json;
+
+const TEMP_EXTENSION: &'static str = "tmp";
+/// How old a temporary file must be before it is taken to be left by an interrupted write, rather
+/// than being written by another process sharing the directory
+const STALE_TEMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// A database which wraps a remote database, storing every chunk it fetches in a local directory.
+/// Chunks are immutable, so they never need to be invalidated, but the least recently used ones
+/// are evicted once the directory grows beyond the configured size.
+pub struct CachingChunkStore {
+    backend: http::Database,
+    disk: DiskCache,
+    /// The number of chunks read from the disk, and the number which had to be fetched
+    disk_hits: Cell<u64>,
+    disk_misses: Cell<u64>,
+}
+impl ::std::fmt::Debug for CachingChunkStore {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        write!(f, "{:?} (cached in {})", self.backend, self.disk.directory.display())
+    }
+}
+
+impl CachingChunkStore {
+    /// Wraps the `backend` database, caching its chunks in `directory`. The cache is allowed to
+    /// grow to `max_bytes` before chunks are evicted.
+    pub fn new<P: AsRef<Path>>(backend: http::Database, directory: P, max_bytes: u64) -> Result<Self, Error> {
+        Ok(Self{
+            backend,
+            disk: DiskCache::open(directory.as_ref().to_path_buf(), max_bytes)?,
+            disk_hits: Cell::new(0),
+            disk_misses: Cell::new(0),
+        })
+    }
+}
+
+/// What a garbage collection of the cache found. The chunks which cannot be reached are removed,
+/// unless it was a dry run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// The number of cached chunks which can be reached from the root, and their size in bytes
+    pub reachable_chunks: usize,
+    pub reachable_bytes: u64,
+    /// The number of cached chunks which cannot be reached from the root, and their size in bytes
+    pub unreachable_chunks: usize,
+    pub unreachable_bytes: u64,
+}
+
+impl CachingChunkStore {
+    /// Removes the cached chunks which cannot be reached from the current root of the database,
+    /// such as those of deleted datasets or of commits which are no longer in any history. With
+    /// `dry_run`, nothing is removed, but the report says what would be.
+    ///
+    /// Only cached chunks are read to find the refs they hold, so nothing is fetched from the
+    /// database. A cached chunk which can only be reached through a chunk that is not cached is
+    /// taken to be unreachable, and is fetched again if it is needed later. Chunks are removed one
+    /// at a time, so a collection which is interrupted leaves every remaining chunk intact.
+    pub fn gc(&self, dry_run: bool) -> Result<GcReport, Error> {
+        let reachable = self.reachable()?;
+        let mut report = GcReport::default();
+        let mut unreachable = vec![];
+        for (h, &(len, _)) in self.disk.entries.borrow().iter() {
+            if reachable.contains(h) {
+                report.reachable_chunks += 1;
+                report.reachable_bytes += len;
+            } else {
+                report.unreachable_chunks += 1;
+                report.unreachable_bytes += len;
+                unreachable.push(*h);
+            }
+        }
+        if !dry_run {
+            for h in unreachable {
+                self.disk.remove(h)?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Finds every chunk which can be reached from the root through cached chunks, a level at a
+    /// time. A ref of height 1 refers to a chunk which holds no refs, so that chunk is not read.
+    fn reachable(&self) -> Result<HashSet<Hash>, Error> {
+        let mut reachable = HashSet::new();
+        let mut wanted = HashSet::new();
+        let root = self.root()?;
+        if !root.is_empty() {
+            reachable.insert(root);
+            wanted.insert(root);
+        }
+        while !wanted.is_empty() {
+            let mut chunks = vec![];
+            for h in wanted.drain() {
+                if let Some(data) = self.disk.read(h)? {
+                    chunks.push(data);
+                }
+            }
+            for chunk in chunks {
+                for (h, height) in ChunkReader::new(None, &chunk).read_refs_with_heights()? {
+                    if reachable.insert(h) && height > 1 {
+                        wanted.insert(h);
+                    }
+                }
+            }
+        }
+        Ok(reachable)
+    }
+}
+
+impl super::Database for CachingChunkStore {
+    fn datasets(&self) -> Result<NomsMap<String, Ref<Commit>>, Error> {
+        let root = self.root()?;
+        if root.is_empty() {
+            Ok(NomsMap::new(self))
+        } else {
+            self.get(root)
+                .and_then(|v| v.to_map().ok_or(Error::ConversionError("Value is not a map".to_string())))
+        }
+    }
+    fn dataset<'a, M, V>(&'a self, ds: &str) -> Result<Dataset<'a, M, V>, Error>
+    where M: FromNoms<'a> + IntoNoms + NomsStruct<'a>, V: FromNoms<'a> + IntoNoms, Self: Sized {
+        let r = self.datasets()?
+            .get(ds)?
+            .ok_or_else(|| Error::NoDataset(ds.to_string()))?;
+        super::typed_dataset(ds, r)
+    }
+    fn dataset_or_empty<'a, M, V>(&'a self, ds: &str) -> Result<Dataset<'a, M, V>, Error>
+    where M: FromNoms<'a> + IntoNoms + NomsStruct<'a>, V: FromNoms<'a> + IntoNoms, Self: Sized {
+        match self.dataset(ds) {
+            Err(Error::NoDataset(_)) => Ok(Dataset::empty(self, ds)),
+            result => result,
+        }
+    }
+    fn rebase(&self) { super::Database::rebase(&self.backend) }
+    fn commit<I>(&self, ds: Dataset, v: I, o: CommitOptions) -> Result<Dataset, Error>
+    where I: IntoNoms, Self: Sized {
+        super::commit_dataset(self, ds, v, o)
+    }
+    fn delete(&self, ds: Dataset) -> Result<Dataset, Error> { super::move_head(self, ds.id(), None) }
+    fn set_head(&self, ds: Dataset, head: Ref<Commit>) -> Result<Dataset, Error> { super::move_head(self, ds.id(), Some(&head)) }
+    fn fast_forward(&self, ds: Dataset, head: Ref<Commit>) -> Result<Dataset, Error> { super::fast_forward_dataset(self, ds, head) }
+    fn root_hash(&self) -> Result<Hash, Error> { ChunkStore::root(self) }
+
+    fn stats(&self) -> Result<ServerStats, Error> { super::Database::stats(&self.backend) }
+    /// The counters of the disk cache, followed by the summary of the database it wraps
+    fn stats_summary(&self) -> String {
+        format!(
+            "DiskCacheHits: {}\nDiskCacheHitRate: {}\n{}",
+            self.disk_hits.get(),
+            hit_rate(self.disk_hits.get(), self.disk_misses.get()),
+            super::Database::stats_summary(&self.backend),
+        )
+    }
+    fn graphql(&self, dataset: &str, query: &str, variables: &Json) -> Result<Json, Error> {
+        super::Database::graphql(&self.backend, dataset, query, variables)
+    }
+
+    fn value_from<'a, I>(&'a self, value: I) -> NomsValue<'a>
+    where I: IntoNoms, Self: Sized {
+        Value::from_noms(&Chunk::new(self, value.into_noms())).export()
+    }
+    fn write_value<I>(&self, value: I) -> Result<Hash, Error>
+    where I: IntoNoms, Self: Sized {
+        ChunkStore::put(self, value)
+    }
+    fn read_values<'a>(&'a self, hashes: HashSet<Hash>) -> Result<HashMap<Hash, NomsValue<'a>>, Error>
+    where Self: Sized {
+        Ok(self.get_many(hashes)?.into_iter().map(|(h, v)| (h, v.export())).collect())
+    }
+    fn pull<S: super::Database>(&self, source: &S, hash: Hash) -> Result<(), Error>
+    where Self: Sized {
+        super::pull_chunks(source, self, hash)
+    }
+}
+
+impl ChunkStore for CachingChunkStore {
+    fn get_many(&self, hashes: HashSet<Hash>) -> Result<HashMap<Hash, Value>, Error> {
+        let mut found = HashMap::with_capacity(hashes.len());
+        let mut missing = HashSet::new();
+        for h in hashes {
+            match self.disk.get(h)? {
+                Some(data) => { found.insert(h, data); }
+                None => { missing.insert(h); }
+            }
+        }
+        count(&self.disk_hits, found.len() as u64);
+        count(&self.disk_misses, missing.len() as u64);
+        if !missing.is_empty() {
+            for (h, data) in self.backend.get_raw(missing)? {
+                self.disk.insert(h, &data)?;
+                found.insert(h, data);
+            }
+        }
+        Ok(
+            found
+                .into_iter()
+                .map(|(h, data)| (h, Value::from_noms(&Chunk::new(self, data))))
+                .collect()
+        )
+    }
+
+    fn has_many(&self, hashes: HashSet<Hash>) -> Result<HashMap<Hash, bool>, Error> {
+        let (cached, unknown): (HashSet<Hash>, HashSet<Hash>) = hashes
+            .into_iter()
+            .partition(|h| self.disk.contains(h));
+        let mut exists = if unknown.is_empty() { HashMap::new() } else { self.backend.has_many(unknown)? };
+        exists.extend(cached.into_iter().map(|h| (h, true)));
+        Ok(exists)
+    }
+    fn put<I>(&self, v: I) -> Result<Hash, Error> where I: IntoNoms, Self: Sized { self.backend.put(v) }
+    fn version(&self) -> String { self.backend.version() }
+    fn rebase(&self) { ChunkStore::rebase(&self.backend) }
+    fn root(&self) -> Result<Hash, Error> { self.backend.root() }
+    fn commit(&self, current: Hash, last: Hash) -> Result<bool, Error> {
+        ChunkStore::commit(&self.backend, current, last)
+    }
+}
+
+/// The chunks stored on disk, one file per chunk, named by the chunk's hash. Usage is tracked in
+/// memory, so within a process the least recently used chunks are evicted first. Chunks left by a
+/// previous process are ordered by the time they were written.
+struct DiskCache {
+    directory: PathBuf,
+    max_bytes: u64,
+    size: Cell<u64>,
+    clock: Cell<u64>,
+    /// The size and last use of each chunk
+    entries: RefCell<HashMap<Hash, (u64, u64)>>,
+    /// The chunks, ordered by their last use
+    usage: RefCell<BTreeMap<u64, Hash>>,
+}
+
+impl DiskCache {
+    fn open(directory: PathBuf, max_bytes: u64) -> Result<Self, Error> {
+        fs::create_dir_all(&directory)?;
+        let mut found = vec![];
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().map_or(false, |ext| ext == TEMP_EXTENSION) {
+                // left behind by a process that was interrupted while writing, unless it is recent
+                let age = entry.metadata()?.modified()?.elapsed().unwrap_or_default();
+                if age > STALE_TEMP_AGE {
+                    match fs::remove_file(&path) {
+                        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+                        result => result?,
+                    }
+                }
+                continue;
+            }
+            let hash = match entry.file_name().to_str() {
+                Some(name) if name.len() == STRING_LEN => match Hash::from_string(name) {
+                    Ok(hash) => hash,
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            let metadata = entry.metadata()?;
+            let written = metadata.modified()?.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            found.push((written, hash, metadata.len()));
+        }
+        found.sort();
+
+        let cache = DiskCache {
+            directory,
+            max_bytes,
+            size: Cell::new(0),
+            clock: Cell::new(0),
+            entries: RefCell::new(HashMap::with_capacity(found.len())),
+            usage: RefCell::new(BTreeMap::new()),
+        };
+        for (_, hash, len) in found {
+            cache.record(hash, len);
+        }
+        cache.evict()?;
+        Ok(cache)
+    }
+
+    fn path_for(&self, h: Hash) -> PathBuf {
+        self.directory.join(h.to_string())
+    }
+
+    fn tick(&self) -> u64 {
+        let time = self.clock.get();
+        self.clock.set(time + 1);
+        time
+    }
+
+    fn contains(&self, h: &Hash) -> bool {
+        self.entries.borrow().contains_key(h)
+    }
+
+    fn record(&self, h: Hash, len: u64) {
+        let time = self.tick();
+        self.entries.borrow_mut().insert(h, (len, time));
+        self.usage.borrow_mut().insert(time, h);
+        self.size.set(self.size.get() + len);
+    }
+
+    fn forget(&self, h: Hash) -> Option<u64> {
+        let (len, time) = self.entries.borrow_mut().remove(&h)?;
+        self.usage.borrow_mut().remove(&time);
+        self.size.set(self.size.get() - len);
+        Some(len)
+    }
+
+    fn touch(&self, h: Hash) {
+        let time = self.tick();
+        if let Some(entry) = self.entries.borrow_mut().get_mut(&h) {
+            let mut usage = self.usage.borrow_mut();
+            usage.remove(&entry.1);
+            usage.insert(time, h);
+            entry.1 = time;
+        }
+    }
+
+    fn get(&self, h: Hash) -> Result<Option<Vec<u8>>, Error> {
+        let data = self.read(h)?;
+        if data.is_some() {
+            self.touch(h);
+        }
+        Ok(data)
+    }
+
+    /// Reads the chunk like `get`, but without counting it as used.
+    fn read(&self, h: Hash) -> Result<Option<Vec<u8>>, Error> {
+        if !self.contains(&h) {
+            return Ok(None);
+        }
+        let mut data = vec![];
+        match File::open(self.path_for(h)).and_then(|mut file| file.read_to_end(&mut data)) {
+            Ok(_) => Ok(Some(data)),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                // removed by someone else, so just fetch it again
+                self.forget(h);
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn insert(&self, h: Hash, data: &[u8]) -> Result<(), Error> {
+        if self.contains(&h) {
+            self.touch(h);
+            return Ok(());
+        }
+        // written to a temporary file first so that an interrupted write never leaves a partial
+        // chunk under a valid name
+        // named by the process too, so that processes sharing the directory never write the same
+        // temporary file
+        let path = self.path_for(h);
+        let temp = self.directory.join(format!("{}.{}.{}", h.to_string(), process::id(), TEMP_EXTENSION));
+        File::create(&temp)?.write_all(data)?;
+        fs::rename(&temp, &path)?;
+        self.record(h, data.len() as u64);
+        self.evict()
+    }
+
+    fn evict(&self) -> Result<(), Error> {
+        while self.size.get() > self.max_bytes {
+            let oldest = match self.usage.borrow().values().next() {
+                Some(h) => *h,
+                None => break,
+            };
+            self.remove(oldest)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, h: Hash) -> Result<(), Error> {
+        self.forget(h);
+        match fs::remove_file(self.path_for(h)) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hash::hash;
+    use std::env::temp_dir;
+    use std::time::SystemTime;
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = temp_dir().join(format!("nomrs-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn disk_cache_persists() {
+        let dir = directory("disk-cache-persists");
+        let h = hash(b"chunk");
+        {
+            let cache = DiskCache::open(dir.clone(), 1024).unwrap();
+            cache.insert(h, b"chunk").unwrap();
+        }
+        let cache = DiskCache::open(dir.clone(), 1024).unwrap();
+        assert!(cache.contains(&h));
+        assert_eq!(cache.get(h).unwrap(), Some(b"chunk".to_vec()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn disk_cache_evicts_least_recently_used() {
+        let dir = directory("disk-cache-evicts");
+        let (a, b, c) = (hash(b"a"), hash(b"b"), hash(b"c"));
+        let cache = DiskCache::open(dir.clone(), 8).unwrap();
+        cache.insert(a, b"aaaa").unwrap();
+        cache.insert(b, b"bbbb").unwrap();
+        cache.get(a).unwrap();
+        cache.insert(c, b"cccc").unwrap();
+        assert!(cache.contains(&a));
+        assert!(!cache.contains(&b));
+        assert!(cache.contains(&c));
+        assert!(!cache.path_for(b).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn disk_cache_only_removes_stale_temporary_files() {
+        let dir = directory("disk-cache-temporary-files");
+        fs::create_dir_all(&dir).unwrap();
+        let (stale, writing) = (dir.join("stale.1.tmp"), dir.join("writing.2.tmp"));
+        File::create(&stale).unwrap().set_modified(SystemTime::now() - STALE_TEMP_AGE * 2).unwrap();
+        File::create(&writing).unwrap();
+        DiskCache::open(dir.clone(), 1024).unwrap();
+        assert!(!stale.exists());
+        assert!(writing.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,25 +1,30 @@
 //! Defines a database that is backed by a Noms HTTP database
 
 use std::collections::{HashMap, HashSet};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
-use super::{CommitOptions, ChunkStore};
-use value::{NomsValue, NomsStruct, Value, Ref, FromNoms, IntoNoms, NomsMap};
+use super::{CommitOptions, ChunkStore, Protocol, ServerStats, UNSUPPORTED};
+use super::stats::count;
+use super::buffer::WriteBuffer;
+use value::{NomsValue, NomsStruct, Value, Ref, FromNoms, IntoNoms, NomsMap, Commit};
 use dataset::Dataset;
 use error::Error;
-use http::Client;
-use hash::Hash;
+use http::{Client, Middleware};
+use hash::{self, Hash};
 use InnerNoms;
 use chunk::{Chunk};
+use openssl::ssl::SslConnector;
+use serde_json::Value as Json;
 
 #[derive(Clone)]
 pub struct Database {
     database: String,
     version: String,
     client: Client,
-    root: Hash,
+    root: Cell<Hash>,
     noms: Rc<RefCell<InnerNoms>>,
     cache: RefCell<HashMap<Hash, Vec<u8>>>,
+    pending: RefCell<WriteBuffer>,
 }
 impl ::std::fmt::Debug for Database {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
@@ -28,11 +33,19 @@ impl ::std::fmt::Debug for Database {
 }
 
 impl Database {
-    pub(crate) fn new(noms: Rc<RefCell<InnerNoms>>, database: String, version: String) -> Result<Self, Error> {
-        let client = Client::new(database.clone(), version.clone(), &noms.borrow().event_loop.handle());
+    pub(crate) fn new(noms: Rc<RefCell<InnerNoms>>, protocol: Protocol, database: String, version: String, tls: Option<SslConnector>, middleware: Vec<Rc<dyn Middleware>>) -> Result<Self, Error> {
+        let client = Client::new(protocol, database.clone(), version.clone(), tls, middleware, &noms.borrow().event_loop.handle());
         let get_root = client.get_root();
         let root = noms.borrow_mut().event_loop.run(get_root)?;
-        Ok(Self{ database, version, client, root, noms: noms.clone(), cache: RefCell::new(HashMap::new()) })
+        Ok(Self{
+            database,
+            version,
+            client,
+            root: Cell::new(root),
+            noms: noms.clone(),
+            cache: RefCell::new(HashMap::new()),
+            pending: RefCell::new(WriteBuffer::new()),
+        })
     }
 }
 
@@ -41,71 +54,205 @@ impl Database {
         self.cache.borrow_mut().insert(h, v.into_noms());
         v
     }
+
+    /// Retrieves the raw data of the chunks with the given hashes, fetching only those which have
+    /// not been seen before from the server.
+    /// Chunks which have been put but not yet flushed are served from the write buffer.
+    pub(crate) fn get_raw(&self, hashes: HashSet<Hash>) -> Result<HashMap<Hash, Vec<u8>>, Error> {
+        let lookups: HashSet<Hash> = hashes
+            .iter()
+            .filter(|h| !self.cache.borrow().contains_key(h) && !self.pending.borrow().contains(h))
+            .cloned()
+            .collect();
+        let stats = self.client.stats();
+        count(&stats.cache_hits, (hashes.len() - lookups.len()) as u64);
+        count(&stats.chunks_fetched, lookups.len() as u64);
+        if !lookups.is_empty() {
+            for (key, value) in
+                self.noms.borrow_mut()
+                    .event_loop
+                    .run(self.client.post_get_refs(self, lookups))? {
+                self.add_to_cache(key.clone(), value);
+            }
+        }
+        let cache = self.cache.borrow();
+        let pending = self.pending.borrow();
+        hashes
+            .into_iter()
+            .map(|k| cache
+                .get(&k)
+                .or_else(|| pending.get(&k))
+                .map(|v| (k, v.clone()))
+                .ok_or(Error::NoValueForRef(k))
+            )
+            .collect()
+    }
+
+    /// Ensures that every chunk referred to by a chunk written since the last commit, as well as
+    /// the new root itself, exists either in the database or among the written chunks. Chunks
+    /// which have been read from or written to the server are known to exist already.
+    fn validate(&self, root: Hash) -> Result<(), Error> {
+        let mut lookups = self.pending.borrow().unresolved().clone();
+        if !self.pending.borrow().was_written(&root) {
+            lookups.insert(root);
+        }
+        lookups.retain(|h| !self.cache.borrow().contains_key(h));
+        if lookups.is_empty() {
+            return Ok(());
+        }
+        let exists = self.noms.borrow_mut()
+            .event_loop
+            .run(self.client.post_has_refs(self, lookups))?;
+        let mut missing: Vec<Hash> = exists
+            .into_iter()
+            .filter(|&(_, exists)| !exists)
+            .map(|(h, _)| h)
+            .collect();
+        missing.sort();
+        match missing.first() {
+            Some(&h) => Err(Error::DanglingRef(h)),
+            None => Ok(()),
+        }
+    }
+
+    /// Sends every buffered chunk to the server. Chunks stay in the buffer until the server has
+    /// them, so if a batch fails, it and every later batch are sent again by the next flush.
+    pub(crate) fn flush(&self) -> Result<(), Error> {
+        loop {
+            let (len, request) = {
+                let pending = self.pending.borrow();
+                let batch = pending.batch();
+                if batch.is_empty() {
+                    return Ok(());
+                }
+                (batch.len(), self.client.post_write_value(batch))
+            };
+            self.noms.borrow_mut().event_loop.run(request)?;
+            // the server has the chunks now, but there is no need to fetch them back from it
+            for (h, data) in self.pending.borrow_mut().sent(len) {
+                self.add_to_cache(h, data);
+            }
+        }
+    }
 }
 
 impl super::Database for Database {
-    fn datasets(&self) -> Result<NomsMap<String, Ref>, Error> {
-        if self.root.is_empty() {
+    fn datasets(&self) -> Result<NomsMap<String, Ref<Commit>>, Error> {
+        if self.root.get().is_empty() {
             Ok(NomsMap::new(self))
         } else {
-            self.get(self.root)
+            self.get(self.root.get())
                 .and_then(|v| v.to_map().ok_or(Error::ConversionError("Value is not a map".to_string())))
         }
     }
     fn dataset<'a, M, V>(&'a self, ds: &str) -> Result<Dataset<'a, M, V>, Error>
     where M: FromNoms<'a> + IntoNoms + NomsStruct<'a>, V: FromNoms<'a> + IntoNoms, Self: Sized {
         let r = self.datasets()?
-            .get(ds)
-            .ok_or_else(|| Error::NoDataset(ds.to_string()))?
-            .clone();
-        Ok(Dataset::new(self, ds, r))
+            .get(ds)?
+            .ok_or_else(|| Error::NoDataset(ds.to_string()))?;
+        super::typed_dataset(ds, r)
+    }
+    fn dataset_or_empty<'a, M, V>(&'a self, ds: &str) -> Result<Dataset<'a, M, V>, Error>
+    where M: FromNoms<'a> + IntoNoms + NomsStruct<'a>, V: FromNoms<'a> + IntoNoms, Self: Sized {
+        match self.dataset(ds) {
+            Err(Error::NoDataset(_)) => Ok(Dataset::empty(self, ds)),
+            result => result,
+        }
     }
     fn rebase(&self) { unimplemented!() }
     fn commit<I>(&self, ds: Dataset, v: I, o: CommitOptions) -> Result<Dataset, Error>
     where I: IntoNoms, Self: Sized {
-        unimplemented!();
+        super::commit_dataset(self, ds, v, o)
+    }
+    fn delete(&self, ds: Dataset) -> Result<Dataset, Error> { super::move_head(self, ds.id(), None) }
+    fn set_head(&self, ds: Dataset, head: Ref<Commit>) -> Result<Dataset, Error> { super::move_head(self, ds.id(), Some(&head)) }
+    fn fast_forward(&self, ds: Dataset, head: Ref<Commit>) -> Result<Dataset, Error> { super::fast_forward_dataset(self, ds, head) }
+    fn root_hash(&self) -> Result<Hash, Error> { ChunkStore::root(self) }
+
+    fn stats(&self) -> Result<ServerStats, Error> {
+        self.noms.borrow_mut().event_loop.run(self.client.get_stats())
+    }
+    /// The counters of this client, followed by the statistics of the server. If they cannot be
+    /// fetched, the server's are reported as unsupported.
+    fn stats_summary(&self) -> String {
+        let server = super::Database::stats(self).map(|stats| stats.summary).unwrap_or_else(|_| UNSUPPORTED.to_string());
+        format!("{}\n{}", self.client.stats(), server.trim_end())
+    }
+    fn graphql(&self, dataset: &str, query: &str, variables: &Json) -> Result<Json, Error> {
+        self.noms.borrow_mut().event_loop.run(self.client.post_graphql(dataset, query, variables))
     }
-    fn delete(&self, ds: Dataset) -> Result<Dataset, Error> { unimplemented!() }
-    fn set_head(&self, ds: Dataset, head: Ref) -> Result<Dataset, Error> { unimplemented!() }
-    fn fast_forward(&self, ds: Dataset, head: Ref) -> Result<Dataset, Error> { unimplemented!() }
 
     fn value_from<'a, I>(&'a self, value: I) -> NomsValue<'a>
     where I: IntoNoms, Self: Sized {
         Value::from_noms(&Chunk::new(self, value.into_noms())).export()
     }
+    fn write_value<I>(&self, value: I) -> Result<Hash, Error>
+    where I: IntoNoms, Self: Sized {
+        ChunkStore::put(self, value)
+    }
+    fn read_values<'a>(&'a self, hashes: HashSet<Hash>) -> Result<HashMap<Hash, NomsValue<'a>>, Error>
+    where Self: Sized {
+        Ok(self.get_many(hashes)?.into_iter().map(|(h, v)| (h, v.export())).collect())
+    }
+    fn pull<S: super::Database>(&self, source: &S, hash: Hash) -> Result<(), Error>
+    where Self: Sized {
+        super::pull_chunks(source, self, hash)
+    }
 }
 
 impl super::ChunkStore for Database {
     fn get_many(&self, hashes: HashSet<Hash>) -> Result<HashMap<Hash, Value>, Error> {
-        let lookups = hashes.iter().filter(|h| !self.cache.borrow().contains_key(h)).cloned().collect();
-        for (key, value) in
-            self.noms.borrow_mut()
-                .event_loop
-                .run(self.client.post_get_refs(self, lookups))? {
-            self.add_to_cache(key.clone(), value);
-        }
-        let cache = self.cache.borrow();
         Ok(
-            hashes
+            self.get_raw(hashes)?
                 .into_iter()
-                .map(|k| cache
-                    .get(&k)
-                    .clone()
-                    .map(|v| (k, Value::from_noms(&Chunk::new(self, v.clone()))))
-                    .unwrap()
-                )
+                .map(|(k, v)| (k, Value::from_noms(&Chunk::new(self, v))))
                 .collect()
         )
     }
 
     fn has_many(&self, h: HashSet<Hash>) -> Result<HashMap<Hash, bool>, Error> {
-        self.noms.borrow_mut()
-            .event_loop
-            .run(self.client.post_has_refs(self, h))
+        let (pending, unknown): (HashSet<Hash>, HashSet<Hash>) = {
+            let buffer = self.pending.borrow();
+            h.into_iter().partition(|h| buffer.contains(h))
+        };
+        let mut exists = if unknown.is_empty() {
+            HashMap::new()
+        } else {
+            self.noms.borrow_mut()
+                .event_loop
+                .run(self.client.post_has_refs(self, unknown))?
+        };
+        exists.extend(pending.into_iter().map(|h| (h, true)));
+        Ok(exists)
     }
-    fn put<I>(&self, v: I) where I: IntoNoms, Self: Sized { unimplemented!() }
-    fn version(&self) -> String { unimplemented!() }
+    fn put<I>(&self, v: I) -> Result<Hash, Error> where I: IntoNoms, Self: Sized {
+        let data = v.into_noms();
+        let h = hash::hash(&data);
+        if !self.cache.borrow().contains_key(&h) {
+            let full = {
+                let mut pending = self.pending.borrow_mut();
+                pending.insert(h, data)?;
+                pending.is_full()
+            };
+            if full {
+                self.flush()?;
+            }
+        }
+        Ok(h)
+    }
+    fn version(&self) -> String { self.version.clone() }
     fn rebase(&self) { unimplemented!() }
-    fn root(&self) -> Result<Hash, Error> { unimplemented!() }
-    fn commit(&self) -> Result<(), Error> { unimplemented!() }
+    fn root(&self) -> Result<Hash, Error> { Ok(self.root.get()) }
+    fn commit(&self, current: Hash, last: Hash) -> Result<bool, Error> {
+        self.validate(current)?;
+        self.flush()?;
+        let moved = self.noms.borrow_mut()
+            .event_loop
+            .run(self.client.post_root(last, current))?;
+        if moved {
+            self.root.set(current);
+            self.pending.borrow_mut().committed();
+        }
+        Ok(moved)
+    }
 }
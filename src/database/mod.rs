@@ -1,21 +1,34 @@
 //! Manages connections to a database
 
-mod http;
+pub(crate) mod http;
+mod cache;
+mod buffer;
+pub(crate) mod stats;
+
+pub use self::cache::{CachingChunkStore, GcReport};
+pub use self::stats::ServerStats;
 
 use std::cell::RefCell;
 use std::rc::Rc;
 use dataset::Dataset;
-use value::{NomsValue, NomsStruct, Value, Ref, NomsMap, FromNoms, IntoNoms};
+use value::{NomsValue, NomsStruct, Value, Ref, NomsMap, FromNoms, IntoNoms, Empty, Commit};
+use value::{encode_struct, encode_map_leaf, encode_set_leaf, commit_type, height_of, type_of_encoded, COMMIT_NAME};
 use error::Error;
 use hash::Hash;
+use chunk::{Chunk, ChunkReader};
+use http::{Middleware, BearerAuth, BasicAuth};
 use InnerNoms;
 use std::collections::{HashMap, HashSet};
+use openssl::ssl::{SslConnector, SslMethod};
+use openssl::x509::X509;
+use openssl::pkcs12::Pkcs12;
+use serde_json::Value as Json;
 
 const DEFAULT_VERSION: &'static str = "7.18";
 const UNSUPPORTED: &'static str = "Unsupported";
 
 /// The protocol to use to connect to the database
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Protocol {
     Http,
     Https,
@@ -23,6 +36,14 @@ pub enum Protocol {
 impl Default for Protocol {
     fn default() -> Self { Protocol::Http }
 }
+impl Protocol {
+    pub(crate) fn scheme(self) -> &'static str {
+        match self {
+            Protocol::Http => "http",
+            Protocol::Https => "https",
+        }
+    }
+}
 
 pub struct CommitOptions<'a> {
     pub parents: NomsValue<'a>,
@@ -37,6 +58,121 @@ impl<'a> Default for CommitOptions<'a> {
     }
 }
 
+/// Writes a commit holding `value` to the store, followed by a new datasets map in which the dataset
+/// refers to that commit, and then moves the root of the database to the new map.
+///
+/// Unless other parents are given, the parent of the commit is the current head of the dataset.
+/// The datasets map is written as a single chunk.
+pub(crate) fn commit_dataset<'a, 'b, S, I>(store: &'a S, ds: Dataset<'b>, value: I, options: CommitOptions<'b>) -> Result<Dataset<'a>, Error>
+where S: ChunkStore, I: IntoNoms {
+    let parents = match options.parents.import() {
+        Value::Nil if ds.head_ref().is_empty() => encode_set_leaf(vec![]),
+        Value::Nil => encode_set_leaf(vec![ds.head_ref().into_noms()]),
+        parents => parents.into_noms(),
+    };
+    let meta = match options.meta.import() {
+        Value::Nil => Empty.into_noms(),
+        meta => meta.into_noms(),
+    };
+    let value = value.into_noms();
+    // like Go Noms, the commit type holds the types of the meta and value which were committed
+    let commit_type = commit_type(type_of_encoded(&meta)?, type_of_encoded(&value)?);
+    let mut props = HashMap::new();
+    props.insert("meta".to_string(), meta);
+    props.insert("parents".to_string(), parents);
+    props.insert("value".to_string(), value);
+    let commit = encode_struct(COMMIT_NAME, props);
+
+    let height = height_of(&commit)?;
+    let commit_ref = Ref::new(store, store.put(commit)?, commit_type, height);
+    move_head(store, ds.id(), Some(&commit_ref))
+}
+
+/// Opens the dataset whose head is `head`, checking that the type of the head commit, which its
+/// ref holds, fits `Commit<M, V>`.
+pub(crate) fn typed_dataset<'a, M, V>(id: &str, head: Ref<'a, Commit<'a>>) -> Result<Dataset<'a, M, V>, Error>
+where M: FromNoms<'a> + IntoNoms + NomsStruct<'a>, V: FromNoms<'a> + IntoNoms {
+    let differences = <Commit<M, V> as NomsStruct>::noms_type().differences(head.target_type());
+    if !differences.is_empty() {
+        return Err(Error::SchemaMismatch(id.to_string(), differences));
+    }
+    Ok(Dataset::new(id, head.cast()))
+}
+
+/// Writes a new datasets map in which the dataset refers to `head`, or does not exist if there is
+/// no head, and then moves the root of the database to the new map.
+pub(crate) fn move_head<'a, S: ChunkStore>(store: &'a S, id: &str, head: Option<&Ref<Commit>>) -> Result<Dataset<'a>, Error> {
+    let last = store.root()?;
+    let mut entries: Vec<_> = store.datasets()?
+        .to_map()
+        .into_iter()
+        .filter(|(ds, _)| ds.as_str() != id)
+        .map(|(ds, r)| (ds.into_noms(), r.into_noms()))
+        .collect();
+    if let Some(head) = head {
+        entries.push((id.into_noms(), head.into_noms()));
+    }
+    let root = store.put(encode_map_leaf(entries))?;
+    if !ChunkStore::commit(store, root, last)? {
+        return Err(Error::OptimisticLockFailed);
+    }
+    match head {
+        // read back, so that the ref belongs to this store
+        Some(head) => Ok(Dataset::new(id, Chunk::new(store, head.into_noms()).reader().read_ref().cast())),
+        None => Ok(Dataset::empty(store, id)),
+    }
+}
+
+/// Moves the head of the dataset to `head`, as long as the current head is one of its ancestors,
+/// so that no commits are lost.
+pub(crate) fn fast_forward_dataset<'a, S: ChunkStore>(store: &'a S, ds: Dataset, head: Ref<Commit>) -> Result<Dataset<'a>, Error> {
+    if !ds.head_ref().is_empty() && !descends_from(&head, ds.head_ref())? {
+        return Err(Error::MergeNeeded);
+    }
+    move_head(store, ds.id(), Some(&head))
+}
+
+/// Whether `ancestor` can be reached by following the parents of the commit `head`. Commits are
+/// always higher than their parents, so commits lower than `ancestor` are not followed.
+fn descends_from(head: &Ref<Commit>, ancestor: &Ref<Commit>) -> Result<bool, Error> {
+    let mut pending = vec![head.clone()];
+    let mut seen = HashSet::new();
+    while let Some(commit) = pending.pop() {
+        if &commit == ancestor {
+            return Ok(true);
+        }
+        if commit.height() <= ancestor.height() || !seen.insert(commit.target_hash()) {
+            continue;
+        }
+        pending.extend(commit.load()?.parents().to_set());
+    }
+    Ok(false)
+}
+
+/// Copies the chunk with the given hash from `source` to `sink`, along with every chunk it refers
+/// to, directly or not, that the sink does not have yet. Chunks are fetched a level at a time.
+pub(crate) fn pull_chunks<S: Database, D: ChunkStore>(source: &S, sink: &D, hash: Hash) -> Result<(), Error> {
+    let mut wanted = HashSet::new();
+    wanted.insert(hash);
+    while !wanted.is_empty() {
+        let exists = sink.has_many(wanted.clone())?;
+        let missing: HashSet<Hash> = wanted
+            .into_iter()
+            .filter(|h| !exists.get(h).cloned().unwrap_or(false))
+            .collect();
+        wanted = HashSet::new();
+        if missing.is_empty() {
+            break;
+        }
+        for (_, value) in source.read_values(missing)? {
+            let chunk = value.into_noms();
+            wanted.extend(ChunkReader::new(None, &chunk).read_refs()?);
+            sink.put(chunk)?;
+        }
+    }
+    Ok(())
+}
+
 /// A trait providing full access to the underlying Noms database.
 // TODO: is this necessary? or just use the chunk store and dataset APIs?
 //       maybe should spend some time learning how original Noms is used in practice
@@ -46,10 +182,15 @@ pub trait Database {
 
     /// Returns the root of the database, which is a Map<String, Ref<Commit>>, where the key is the
     /// ID of the dataset.
-    fn datasets(&self) -> Result<NomsMap<String, Ref>, Error>;
-    /// Gets the Dataset corresponding to the given ds dataset ID from the datasets map.
+    fn datasets(&self) -> Result<NomsMap<String, Ref<Commit>>, Error>;
+    /// Gets the Dataset corresponding to the given ds dataset ID from the datasets map. The type
+    /// of its head commit must fit `Commit<M, V>`, otherwise `Error::SchemaMismatch` is returned.
     fn dataset<'a, M, V>(&'a self, ds: &str) -> Result<Dataset<M, V>, Error>
     where M: FromNoms<'a> + IntoNoms + NomsStruct<'a>, V: FromNoms<'a> + IntoNoms, Self: Sized;
+    /// Gets the Dataset like `dataset`, but returns an empty Dataset with no head if there is no
+    /// dataset with that ID yet, so that it can be created by committing to it.
+    fn dataset_or_empty<'a, M, V>(&'a self, ds: &str) -> Result<Dataset<M, V>, Error>
+    where M: FromNoms<'a> + IntoNoms + NomsStruct<'a>, V: FromNoms<'a> + IntoNoms, Self: Sized;
     fn rebase(&self);
     fn commit<I>(&self, ds: Dataset, v: I, o: CommitOptions) -> Result<Dataset, Error>
     where I: IntoNoms, Self: Sized;
@@ -57,16 +198,51 @@ pub trait Database {
     where I: IntoNoms, Self: Sized {
         self.commit(ds, v, CommitOptions::default())
     }
+    /// Removes the dataset from the database, returning it without a head. The commits it held
+    /// are left in the database.
     fn delete(&self, ds: Dataset) -> Result<Dataset, Error>;
-    fn set_head(&self, ds: Dataset, head: Ref) -> Result<Dataset, Error>;
-    fn fast_forward(&self, ds: Dataset, head: Ref) -> Result<Dataset, Error>;
+    /// Moves the head of the dataset to the given commit, even if commits are lost in doing so.
+    fn set_head(&self, ds: Dataset, head: Ref<Commit>) -> Result<Dataset, Error>;
+    /// Moves the head of the dataset to the given commit, which must descend from its current head.
+    /// Otherwise `Error::MergeNeeded` is returned.
+    fn fast_forward(&self, ds: Dataset, head: Ref<Commit>) -> Result<Dataset, Error>;
+    /// The hash of the root of the database, which is the hash of the datasets map.
+    fn root_hash(&self) -> Result<Hash, Error>;
 
-    // TODO: implement stats at another time?
-    fn stats(&self) -> Option<()> { None }
+    /// The statistics the server keeps about its store.
+    fn stats(&self) -> Result<ServerStats, Error> { Err(Error::Unimplemented("Statistics".to_string())) }
+    /// Describes the statistics of the server, along with what this client has asked of it, one
+    /// statistic per line.
     fn stats_summary(&self) -> String { UNSUPPORTED.to_string() }
+    /// Runs a GraphQL query against the head of the dataset on the server, which can filter and
+    /// page collections without sending them whole. The `variables` may be `Json::Null`. The
+    /// response is returned as it is, along with any errors the query raised. The `graphql`
+    /// module has helpers for common queries.
+    fn graphql(&self, _dataset: &str, _query: &str, _variables: &Json) -> Result<Json, Error> {
+        Err(Error::Unimplemented("GraphQL queries".to_string()))
+    }
 
     fn value_from<'a, I>(&'a self, value: I) -> NomsValue<'a>
     where I: IntoNoms, Self: Sized;
+    /// Writes a value to the database as a chunk of its own, returning its hash. The chunk can be
+    /// read back immediately, but is only persisted once a commit succeeds.
+    fn write_value<I>(&self, value: I) -> Result<Hash, Error>
+    where I: IntoNoms, Self: Sized;
+    /// Reads the values held by the chunks with the given hashes.
+    fn read_values<'a>(&'a self, hashes: HashSet<Hash>) -> Result<HashMap<Hash, NomsValue<'a>>, Error>
+    where Self: Sized;
+    /// Reads the value held by the chunk with the given hash.
+    fn read_value<'a>(&'a self, hash: Hash) -> Result<NomsValue<'a>, Error>
+    where Self: Sized {
+        let mut hashes = HashSet::with_capacity(1);
+        hashes.insert(hash);
+        self.read_values(hashes)?.remove(&hash).ok_or(Error::NoValueForRef(hash))
+    }
+    /// Copies the value with the given hash from another database, along with every value it
+    /// refers to. The copied chunks are persisted by the next commit, such as a `fast_forward`
+    /// which makes a synced commit the head of a dataset.
+    fn pull<S: Database>(&self, source: &S, hash: Hash) -> Result<(), Error>
+    where Self: Sized;
 }
 
 /// Basically the a Rust ChunkStore
@@ -85,14 +261,21 @@ pub(crate) trait ChunkStore: Database + ::std::fmt::Debug {
         self.has_many(hs).map(|mut v| v.remove(&h).unwrap_or(false))
     }
     fn has_many(&self, h: HashSet<Hash>) -> Result<HashMap<Hash, bool>, Error>;
-    fn put<I>(&self, v: I) where I: IntoNoms, Self: Sized;
+    /// Writes a value to the store, returning the hash of its chunk. Writes may be buffered, so
+    /// the chunk is only guaranteed to be persisted once `commit` succeeds, but it can be read
+    /// back immediately.
+    fn put<I>(&self, v: I) -> Result<Hash, Error> where I: IntoNoms, Self: Sized;
     fn version(&self) -> String;
     fn rebase(&self);
     fn root(&self) -> Result<Hash, Error>;
-    fn commit(&self) -> Result<(), Error>;
+    /// Persists every buffered chunk, then moves the root from `last` to `current`. Returns false
+    /// if the root was no longer `last`, in which case the root is left unchanged.
+    ///
+    /// Before anything is sent, every ref held by the chunks written since the last commit is
+    /// checked, and `Error::DanglingRef` is returned if its target is not in the database.
+    fn commit(&self, current: Hash, last: Hash) -> Result<bool, Error>;
 
-    // TODO: implement stats at another time?
-    fn stats(&self) -> Option<()> { Database::stats(self) }
+    fn stats(&self) -> Result<ServerStats, Error> { Database::stats(self) }
     fn stats_summary(&self) -> String { Database::stats_summary(self) }
 }
 
@@ -100,23 +283,85 @@ pub(crate) trait ChunkStore: Database + ::std::fmt::Debug {
 pub struct DatabaseBuilder {
     version: String,
     noms: Rc<RefCell<InnerNoms>>,
+    ca_certificates: Vec<Vec<u8>>,
+    identity: Option<(Vec<u8>, String)>,
+    middleware: Vec<Rc<dyn Middleware>>,
 }
 
 impl DatabaseBuilder {
     pub(crate) fn new(noms: Rc<RefCell<InnerNoms>>) -> Self {
-        DatabaseBuilder{ noms, version: DEFAULT_VERSION.to_string() }
+        DatabaseBuilder{
+            noms,
+            version: DEFAULT_VERSION.to_string(),
+            ca_certificates: vec![],
+            identity: None,
+            middleware: vec![],
+        }
     }
     /// Creates a new connection to an HTTP database
     pub fn http(self, database: &str) -> Result<http::Database, Error> {
-        Ok(http::Database::new(self.noms, database.to_string(), self.version)?)
+        Ok(http::Database::new(self.noms, Protocol::Http, database.to_string(), self.version, None, self.middleware)?)
     }
-    /// Creates a new connection to an HTTPS database
+    /// Creates a new connection to an HTTPS database. The server's certificate is verified using
+    /// the system's root certificates, along with any added using `ca_certificate`.
     pub fn https(self, database: &str) -> Result<http::Database, Error> {
-        Err(Error::Unimplemented("HTTPS connections are not implemented".to_string()))
+        let tls = self.tls_connector()?;
+        Ok(http::Database::new(self.noms, Protocol::Https, database.to_string(), self.version, Some(tls), self.middleware)?)
     }
 
     /// Sets the Noms version number, required for the request header
     pub fn noms_version(self, version: &str) -> Self {
         Self{ version: version.to_string(), ..self }
     }
+
+    /// Sends the token in a bearer `Authorization` header with every request
+    pub fn bearer_token(self, token: &str) -> Self {
+        self.middleware(BearerAuth(token.to_string()))
+    }
+
+    /// Sends the credentials in a basic `Authorization` header with every request
+    pub fn basic_auth(self, username: &str, password: Option<&str>) -> Self {
+        self.middleware(BasicAuth(username.to_string(), password.map(|p| p.to_string())))
+    }
+
+    /// Runs the middleware around every request. Middleware is run in the order it was added.
+    pub fn middleware<M: Middleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Rc::new(middleware));
+        self
+    }
+
+    /// Trusts the certificates in a PEM encoded bundle when connecting over HTTPS, in addition to
+    /// the system's root certificates
+    pub fn ca_certificate(mut self, pem: &[u8]) -> Self {
+        self.ca_certificates.push(pem.to_vec());
+        self
+    }
+
+    /// Authenticates using the certificate and private key in a DER encoded PKCS #12 archive when
+    /// connecting over HTTPS
+    pub fn client_certificate(self, pkcs12: &[u8], password: &str) -> Self {
+        Self{ identity: Some((pkcs12.to_vec(), password.to_string())), ..self }
+    }
+
+    fn tls_connector(&self) -> Result<SslConnector, Error> {
+        let mut builder = SslConnector::builder(SslMethod::tls())?;
+        for pem in &self.ca_certificates {
+            for cert in X509::stack_from_pem(pem)? {
+                builder.cert_store_mut().add_cert(cert)?;
+            }
+        }
+        if let Some((ref der, ref password)) = self.identity {
+            let identity = Pkcs12::from_der(der)?.parse2(password)?;
+            if let Some(cert) = identity.cert {
+                builder.set_certificate(&cert)?;
+            }
+            if let Some(key) = identity.pkey {
+                builder.set_private_key(&key)?;
+            }
+            for cert in identity.ca.into_iter().flat_map(|chain| chain) {
+                builder.add_extra_chain_cert(cert)?;
+            }
+        }
+        Ok(builder.build())
+    }
 }
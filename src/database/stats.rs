@@ -0,0 +1,101 @@
+//! Statistics about a database: those reported by the server, and those counted by the client.
+
+use std::cell::Cell;
+use std::fmt;
+
+/// What a Noms server reports about its chunk store at `/stats/`. The server describes the store
+/// in text, usually with a line for each statistic, such as `GetLatency: ...`. Stores which do not
+/// keep statistics report `Unsupported`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerStats {
+    /// The text the server responded with
+    pub summary: String,
+    /// The name and value of each `name: value` line of the summary, in order
+    pub values: Vec<(String, String)>,
+}
+
+impl ServerStats {
+    pub(crate) fn parse(summary: String) -> Self {
+        let values = summary
+            .lines()
+            .filter_map(|line| {
+                let mut parts = line.splitn(2, ':');
+                match (parts.next(), parts.next()) {
+                    (Some(name), Some(value)) if !name.trim().is_empty() =>
+                        Some((name.trim().to_string(), value.trim().to_string())),
+                    _ => None,
+                }
+            })
+            .collect();
+        ServerStats{ summary, values }
+    }
+
+    /// The value of the statistic with the given name
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref value)| value.as_str())
+    }
+
+    /// Whether the server's store keeps statistics at all
+    pub fn is_supported(&self) -> bool {
+        self.summary.trim() != "Unsupported"
+    }
+}
+
+/// Counts what a client asks of a database, and how much of it the client could answer itself.
+#[derive(Debug, Default)]
+pub(crate) struct ClientStats {
+    pub requests: Cell<u64>,
+    pub bytes_sent: Cell<u64>,
+    pub bytes_received: Cell<u64>,
+    /// Chunks which were fetched from the server
+    pub chunks_fetched: Cell<u64>,
+    /// Chunks which were read without asking the server, as they had been fetched or written before
+    pub cache_hits: Cell<u64>,
+}
+
+impl fmt::Display for ClientStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Requests: {}", self.requests.get())?;
+        writeln!(f, "BytesSent: {}", self.bytes_sent.get())?;
+        writeln!(f, "BytesReceived: {}", self.bytes_received.get())?;
+        writeln!(f, "ChunksFetched: {}", self.chunks_fetched.get())?;
+        writeln!(f, "CacheHits: {}", self.cache_hits.get())?;
+        write!(f, "CacheHitRate: {}", hit_rate(self.cache_hits.get(), self.chunks_fetched.get()))
+    }
+}
+
+/// Adds `n` to a counter.
+pub(crate) fn count(counter: &Cell<u64>, n: u64) {
+    counter.set(counter.get() + n);
+}
+
+/// Formats the share of reads which were hits as a percentage, or `-` if there were no reads.
+pub(crate) fn hit_rate(hits: u64, misses: u64) -> String {
+    match hits + misses {
+        0 => "-".to_string(),
+        reads => format!("{:.1}%", hits as f64 * 100.0 / reads as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_stats() {
+        let stats = ServerStats::parse("Root: abc\nGetLatency: Mean 1.5ms\n\ntrailing text".to_string());
+        assert_eq!(stats.get("GetLatency"), Some("Mean 1.5ms"));
+        assert_eq!(stats.values.len(), 2);
+        assert!(stats.is_supported());
+        assert!(!ServerStats::parse("Unsupported".to_string()).is_supported());
+    }
+
+    #[test]
+    fn client_stats() {
+        let stats = ClientStats::default();
+        assert!(stats.to_string().ends_with("CacheHitRate: -"));
+        count(&stats.chunks_fetched, 3);
+        count(&stats.cache_hits, 1);
+        assert!(stats.to_string().ends_with("CacheHitRate: 25.0%"));
+    }
+}
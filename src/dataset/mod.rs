@@ -1,41 +1,39 @@
 use database::ChunkStore;
 use value::{NomsValue, Empty, Commit, Ref, IntoNoms, FromNoms, NomsStruct};
 use std::fmt::{Debug, Formatter};
-use std::marker::PhantomData;
 
 pub struct Dataset<'a, M = Empty, V = NomsValue<'a>>
 where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
     dataset: String,
-    database: &'a ChunkStore,
-    reference: Ref<'a>,
-    phantom_meta: PhantomData<M>,
-    phantom_value: PhantomData<V>,
+    reference: Ref<'a, Commit<'a, M, V>>,
 }
 
 impl<'a, M, V> Dataset<'a, M, V>
 where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
-    pub(crate) fn new(database: &'a ChunkStore, dataset: &str, reference: Ref<'a>) -> Self {
+    pub(crate) fn new(dataset: &str, reference: Ref<'a, Commit<'a, M, V>>) -> Self {
         Self {
             dataset: dataset.to_string(),
-            database,
             reference,
-            phantom_meta: PhantomData,
-            phantom_value: PhantomData,
         }
     }
 
+    /// A dataset which has not been committed to yet
+    pub(crate) fn empty(database: &'a ChunkStore, dataset: &str) -> Self {
+        Self::new(dataset, Ref::empty(database))
+    }
+
     pub fn id(&self) -> &str { &self.dataset }
 
     pub fn head(&self) -> Option<Commit<'a, M, V>> {
-        self.database
-            .get(self.reference.hash())
-            .ok()
-            .and_then(|v| v.to_struct())
+        if self.reference.is_empty() {
+            return None;
+        }
+        self.reference.load().ok()
     }
     pub fn head_value(&self) -> Option<V> {
         self.head().map(|c| c.into_value())
     }
-    pub fn head_ref(&self) -> &Ref { &self.reference }
+    pub fn head_ref(&self) -> &Ref<'a, Commit<'a, M, V>> { &self.reference }
 }
 
 impl<'a, M, V> Debug for Dataset<'a, M, V>
@@ -5,17 +5,82 @@ use hash::Hash;
 pub enum Error {
     Hyper(::hyper::Error),
     Http(::hyper::StatusCode),
+    Io(::std::io::Error),
+    Tls(::openssl::error::ErrorStack),
+    Json(::serde_json::Error),
+    Csv(::csv_crate::Error),
     Hash(String),
     NoDataset(String),
     NoValueForRef(Hash),
+    DanglingRef(Hash),
+    OptimisticLockFailed,
+    MergeNeeded,
+    InvalidSpec(String),
+    InvalidType(String),
+    /// The dataset's commits do not fit the types it was read as, with each difference
+    SchemaMismatch(String, Vec<String>),
+    /// An index, and the length of the list it is out of bounds for
+    IndexOutOfBounds(u64, u64),
     ConversionError(String),
+    /// The messages of the errors a GraphQL query raised
+    GraphQL(Vec<String>),
     Unimplemented(String),
 }
 
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match self {
+            &Error::Hyper(ref err) => write!(f, "HTTP request failed: {}", err),
+            &Error::Http(status) => write!(f, "Server responded with {}", status),
+            &Error::Io(ref err) => write!(f, "{}", err),
+            &Error::Tls(ref err) => write!(f, "TLS error: {}", err),
+            &Error::Json(ref err) => write!(f, "Invalid JSON: {}", err),
+            &Error::Csv(ref err) => write!(f, "Invalid CSV: {}", err),
+            &Error::Hash(ref msg) => write!(f, "{}", msg),
+            &Error::NoDataset(ref ds) => write!(f, "There is no dataset named {}", ds),
+            &Error::NoValueForRef(ref h) => write!(f, "There is no value for the ref {}", h.to_string().trim()),
+            &Error::DanglingRef(ref h) => write!(f, "The chunk {} is referred to, but is not in the database", h.to_string().trim()),
+            &Error::OptimisticLockFailed => write!(f, "The root of the database was changed by someone else"),
+            &Error::MergeNeeded => write!(f, "The new head does not descend from the current head of the dataset"),
+            &Error::InvalidSpec(ref msg) => write!(f, "Invalid spec: {}", msg),
+            &Error::InvalidType(ref msg) => write!(f, "Invalid type: {}", msg),
+            &Error::SchemaMismatch(ref ds, ref differences) => {
+                write!(f, "The dataset {} does not fit the type it is read as:", ds)?;
+                for difference in differences {
+                    write!(f, "\n  {}", difference)?;
+                }
+                Ok(())
+            }
+            &Error::IndexOutOfBounds(index, len) => write!(f, "The index {} is out of bounds for a list of length {}", index, len),
+            &Error::ConversionError(ref msg) => write!(f, "{}", msg),
+            &Error::GraphQL(ref messages) => write!(f, "The GraphQL query failed: {}", messages.join("; ")),
+            &Error::Unimplemented(ref msg) => write!(f, "Not implemented: {}", msg),
+        }
+    }
+}
+
+impl ::std::error::Error for Error {}
+
 impl From<::hyper::Error> for Error {
     fn from(err: ::hyper::Error) -> Self { Error::Hyper(err) }
 }
 
+impl From<::std::io::Error> for Error {
+    fn from(err: ::std::io::Error) -> Self { Error::Io(err) }
+}
+
+impl From<::openssl::error::ErrorStack> for Error {
+    fn from(err: ::openssl::error::ErrorStack) -> Self { Error::Tls(err) }
+}
+
+impl From<::serde_json::Error> for Error {
+    fn from(err: ::serde_json::Error) -> Self { Error::Json(err) }
+}
+
+impl From<::csv_crate::Error> for Error {
+    fn from(err: ::csv_crate::Error) -> Self { Error::Csv(err) }
+}
+
 impl From<::data_encoding::DecodePartial> for Error {
     fn from(err: ::data_encoding::DecodePartial) -> Self { Error::Hash(format!("Could not decode hash: {:?}", err)) }
 }
@@ -0,0 +1,64 @@
+//! Helpers for the common GraphQL queries of a Noms server, which filter and page collections on
+//! the server so that only the requested items are sent.
+//!
+//! The server exposes the head commit of a dataset as `root`. Every helper takes a `selection`
+//! for the values it reads, which is empty for primitive values, or the fields to read of a
+//! struct, such as `"{ name age }"`. The selected fields are then decoded using serde.
+
+use serde_crate::Serialize;
+use serde_crate::de::DeserializeOwned;
+use serde_json::{self, Value as Json};
+use database::Database;
+use error::Error;
+
+/// Extracts the data of a GraphQL response, failing with `Error::GraphQL` if the query raised
+/// any errors.
+pub fn data(mut response: Json) -> Result<Json, Error> {
+    match response["errors"].take() {
+        Json::Array(ref errors) if !errors.is_empty() => Err(Error::GraphQL(
+            errors
+                .iter()
+                .map(|error| error["message"].as_str().map_or_else(|| error.to_string(), str::to_string))
+                .collect()
+        )),
+        _ => Ok(response["data"].take()),
+    }
+}
+
+/// Queries the fields of the value of the dataset's head.
+fn root_value<D: Database>(database: &D, dataset: &str, fields: &str) -> Result<Json, Error> {
+    let query = format!("{{ root {{ value {} }} }}", fields);
+    let mut data = data(database.graphql(dataset, &query, &Json::Null)?)?;
+    match data["root"].take() {
+        Json::Null => Err(Error::NoDataset(dataset.to_string())),
+        mut root => Ok(root["value"].take()),
+    }
+}
+
+/// Reads the value of the dataset's head.
+pub fn head_value<T, D>(database: &D, dataset: &str, selection: &str) -> Result<T, Error>
+where T: DeserializeOwned, D: Database {
+    Ok(serde_json::from_value(root_value(database, dataset, selection)?)?)
+}
+
+/// Reads at most `count` items of the list which is the value of the dataset's head, starting at
+/// the index `at`.
+pub fn list_page<T, D>(database: &D, dataset: &str, at: u64, count: u64, selection: &str) -> Result<Vec<T>, Error>
+where T: DeserializeOwned, D: Database {
+    let fields = format!("{{ elements(at: {}, count: {}) {} }}", at, count, selection);
+    Ok(serde_json::from_value(root_value(database, dataset, &fields)?["elements"].take())?)
+}
+
+/// Looks up keys in the map which is the value of the dataset's head, returning the entries which
+/// were found, in the order of the map. Keys must be primitive values, such as `&str`s looking up
+/// `String` keys.
+pub fn map_lookup<Q, K, V, D>(database: &D, dataset: &str, keys: &[Q], selection: &str) -> Result<Vec<(K, V)>, Error>
+where Q: Serialize, K: DeserializeOwned, V: DeserializeOwned, D: Database {
+    // a JSON list of strings, numbers or bools is written the same way in GraphQL
+    let fields = format!("{{ entries(keys: {}) {{ key value {} }} }}", serde_json::to_string(keys)?, selection);
+    let entries: Vec<Json> = serde_json::from_value(root_value(database, dataset, &fields)?["entries"].take())?;
+    entries
+        .into_iter()
+        .map(|mut entry| Ok((serde_json::from_value(entry["key"].take())?, serde_json::from_value(entry["value"].take())?)))
+        .collect()
+}
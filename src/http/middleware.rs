@@ -0,0 +1,83 @@
+//! Hooks which are run around every request sent to the Noms server
+
+use hyper::{Request, Method, StatusCode, Uri};
+use hyper::header::{Authorization, Bearer, Basic};
+use std::time::Duration;
+
+/// A hook into every request made to a Noms server, which can be used to add headers to the
+/// requests, or to log and time them.
+pub trait Middleware {
+    /// Called before the request is sent. Any changes made to the request are sent along with it.
+    fn before(&self, _req: &mut Request) {}
+    /// Called once the response to a request arrives, with its status and the time since it was
+    /// sent. The status is `None` if the request failed without a response.
+    fn after(&self, _method: &Method, _uri: &Uri, _status: Option<StatusCode>, _elapsed: Duration) {}
+}
+
+/// Authenticates requests using a bearer token
+pub(crate) struct BearerAuth(pub String);
+impl Middleware for BearerAuth {
+    fn before(&self, req: &mut Request) {
+        req.headers_mut().set(Authorization(Bearer{ token: self.0.clone() }));
+    }
+}
+
+/// Authenticates requests using HTTP basic authentication
+pub(crate) struct BasicAuth(pub String, pub Option<String>);
+impl Middleware for BasicAuth {
+    fn before(&self, req: &mut Request) {
+        req.headers_mut().set(Authorization(Basic{ username: self.0.clone(), password: self.1.clone() }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::Client;
+    use database::Protocol;
+    use hash::EMPTY_HASH;
+    use std::cell::RefCell;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::rc::Rc;
+    use std::thread;
+    use tokio_core::reactor::Core;
+
+    struct Recorder(Rc<RefCell<Vec<(Method, String, Option<StatusCode>)>>>);
+    impl Middleware for Recorder {
+        fn after(&self, method: &Method, uri: &Uri, status: Option<StatusCode>, _: Duration) {
+            self.0.borrow_mut().push((method.clone(), uri.path().to_string(), status));
+        }
+    }
+
+    #[test]
+    fn middleware_sees_every_request() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let len = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..len]);
+            }
+            let root = EMPTY_HASH.to_string();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", root.len(), root).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let mut core = Core::new().unwrap();
+        let requests = Rc::new(RefCell::new(vec![]));
+        let middleware: Vec<Rc<dyn Middleware>> = vec![
+            Rc::new(BearerAuth("secret".to_string())),
+            Rc::new(Recorder(requests.clone())),
+        ];
+        let client = Client::new(Protocol::Http, format!("127.0.0.1:{}", port), "7.18".to_string(), None, middleware, &core.handle());
+        assert_eq!(core.run(client.get_root()).unwrap(), EMPTY_HASH);
+
+        let request = server.join().unwrap();
+        assert!(request.contains("Authorization: Bearer secret\r\n"));
+        assert_eq!(*requests.borrow(), vec![(Method::Get, "/root/".to_string(), Some(StatusCode::Ok))]);
+    }
+}
@@ -1,15 +1,28 @@
 //! Handles the actual HTTP(S) requests to be sent to the Noms server
 
+mod tls;
+mod middleware;
+
+pub use self::middleware::Middleware;
+pub(crate) use self::middleware::{BearerAuth, BasicAuth};
+
 use hyper;
 use hyper::{Request, Response, Method, StatusCode};
-use hyper::client::HttpConnector;
+use hyper::header::{ContentEncoding, ContentLength, ContentType, Encoding};
+use openssl::ssl::SslConnector;
 use tokio_core::reactor::Handle;
 use futures::{Future, Stream, future};
 use error::Error;
 use hash::{Hash, BYTE_LEN};
 use std::collections::{HashSet, HashMap};
 use byteorder::{NetworkEndian, ByteOrder};
-use database::ChunkStore;
+use database::{ChunkStore, Protocol, ServerStats};
+use database::stats::{ClientStats, count};
+use snap::write::FrameEncoder;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::Instant;
+use serde_json::{self, Value as Json};
 
 const ROOT_PATH: &'static str           = "/root/";
 const GET_REFS_PATH: &'static str       = "/getRefs/";
@@ -21,51 +34,102 @@ const GRAPHQL_PATH: &'static str        = "/graphql/";
 const STATS_PATH: &'static str          = "/stats/";
 
 const NOMS_VERSION_HEADER: &'static str = "X-Noms-Vers";
+const SNAPPY_ENCODING: &'static str     = "x-snappy-framed";
 
 header! { (XNomsVersion, NOMS_VERSION_HEADER) => [String] }
 
 #[derive(Clone)]
 pub(crate) struct Client {
-    client: hyper::Client<HttpConnector>,
+    client: hyper::Client<tls::Connector>,
+    protocol: Protocol,
     database: String,
     version: String,
+    middleware: Vec<Rc<dyn Middleware>>,
+    stats: Rc<ClientStats>,
 }
 
 impl Client {
-    pub fn new(database: String, version: String, handle: &Handle) -> Self {
-        Self{ database, version: version, client: hyper::Client::new(handle) }
+    /// Creates a client for the given database. HTTPS requests require a `tls` connector. Every
+    /// request is passed through the `middleware`, in order.
+    pub fn new(protocol: Protocol, database: String, version: String, tls: Option<SslConnector>, middleware: Vec<Rc<dyn Middleware>>, handle: &Handle) -> Self {
+        let client = hyper::Client::configure()
+            .connector(tls::Connector::new(tls, handle))
+            .build(handle);
+        Self{ database, version: version, protocol, client, middleware, stats: Rc::new(ClientStats::default()) }
+    }
+
+    /// What this client, and every clone of it, has asked of the server.
+    pub fn stats(&self) -> &ClientStats {
+        &self.stats
+    }
+
+    /// Sends a request, running the middleware before it is sent and once the response arrives.
+    fn send(&self, req: hyper::Result<Request>) -> Box<dyn Future<Item = Response, Error = Error>> {
+        let mut req = match req {
+            Ok(req) => req,
+            Err(err) => return Box::new(future::err(Error::Hyper(err))),
+        };
+        for m in &self.middleware {
+            m.before(&mut req);
+        }
+        count(&self.stats.requests, 1);
+        let middleware = self.middleware.clone();
+        let method = req.method().clone();
+        let uri = req.uri().clone();
+        let start = Instant::now();
+        Box::new(
+            self.client.request(req)
+                .then(move |res| {
+                    let elapsed = start.elapsed();
+                    let status = res.as_ref().ok().map(|res| res.status());
+                    for m in &middleware {
+                        m.after(&method, &uri, status, elapsed);
+                    }
+                    res.map_err(|err| Error::Hyper(err))
+                })
+        )
     }
 
     fn request_for(&self, method: Method, path: &str) -> hyper::Result<Request> {
-        let mut req = Request::new(method, format!("http://{}{}", self.database, path).parse()?);
+        let mut req = Request::new(method, format!("{}://{}{}", self.protocol.scheme(), self.database, path).parse()?);
         req.headers_mut().set(XNomsVersion(self.version.clone()));
         Ok(req)
     }
 
+    fn request_with_body(&self, method: Method, path: &str, body: Vec<u8>) -> hyper::Result<Request> {
+        count(&self.stats.bytes_sent, body.len() as u64);
+        let mut req = self.request_for(method, path)?;
+        req.headers_mut().set(ContentLength(body.len() as u64));
+        req.set_body(body);
+        Ok(req)
+    }
+
+    /// Collects the body of a successful response, counting the bytes received.
+    fn receive_body(&self) -> impl FnOnce(Response) -> Box<Future<Item = hyper::Chunk, Error = Error>> {
+        let stats = self.stats.clone();
+        move |res| Box::new(retrieve_body(res).map(move |chunk| {
+            count(&stats.bytes_received, chunk.len() as u64);
+            chunk
+        }))
+    }
+
     fn request_with_query(&self, method: Method, path: &'static str, query: &str) -> hyper::Result<Request> {
         self.request_for(method, &format!("{}?{}", path, query))
     }
 
     pub fn get_root(&self) -> Box<Future<Item = Hash, Error = Error>> {
-        let client = self.client.clone();
         Box::new(
-            future::result(self.request_for(Method::Get, ROOT_PATH))
-                .and_then(move |req| client.request(req))
-                .map_err(|err| Error::Hyper(err))
-                .and_then(retrieve_body)
+            self.send(self.request_for(Method::Get, ROOT_PATH))
+                .and_then(self.receive_body())
                 .map(|chunk| Hash::from_string(&String::from_utf8(chunk.to_vec()).unwrap()).unwrap())
         )
     }
 
     pub fn post_get_refs<'a>(&self, database: &'a ChunkStore, refs: HashSet<Hash>) -> Box<Future<Item = HashMap<Hash, Vec<u8>>, Error = Error>> {
-        let client = self.client.clone();
         let body = serialize_hashes(&refs);
         Box::new(
-            future::result(self.request_for(Method::Post, GET_REFS_PATH))
-                .map(|mut req| { req.set_body(body); req })
-                .and_then(move |req| client.request(req))
-                .map_err(|err| Error::Hyper(err))
-                .and_then(retrieve_body)
+            self.send(self.request_with_body(Method::Post, GET_REFS_PATH, body))
+                .and_then(self.receive_body())
                 .map(|c| c.to_vec())
                 .map(move |chunk| {
                     let mut values = HashMap::with_capacity(refs.len());
@@ -85,24 +149,118 @@ impl Client {
     }
 
     pub fn post_has_refs<'a>(&self, database: &'a ChunkStore, refs: HashSet<Hash>) -> Box<Future<Item = HashMap<Hash, bool>, Error = Error>> {
-        let client = self.client.clone();
         let body = serialize_hashes(&refs);
         Box::new(
-            future::result(self.request_for(Method::Post, HAS_REFS_PATH))
-                .map(|mut req| { req.set_body(body); req }) // TODO: request_with_body?
-                .and_then(move |req| client.request(req))
-                .map_err(|err| Error::Hyper(err))
-                .and_then(retrieve_body)
+            self.send(self.request_with_body(Method::Post, HAS_REFS_PATH, body))
+                .and_then(self.receive_body())
                 .and_then(|c| String::from_utf8(c.to_vec()).map_err(|e| e.into()))
                 .and_then(move |strs| {
                     let mut exists: HashMap<Hash, bool> = refs.iter().map(|r| (r.clone(), false)).collect();
+                    // each line is a hash, followed by whether the server has it
                     for line in strs.lines() {
-                        exists.insert(Hash::from_string(line)?, true);
+                        let mut words = line.split_whitespace();
+                        let hash = match words.next() {
+                            Some(hash) => Hash::from_string(hash)?,
+                            None => continue,
+                        };
+                        exists.insert(hash, words.next().map_or(true, |present| present == "true"));
                     }
                     Ok(exists)
                 })
         )
     }
+
+    pub fn post_write_value(&self, chunks: &[(Hash, Vec<u8>)]) -> Box<Future<Item = (), Error = Error>> {
+        let body = match serialize_chunks(chunks) {
+            Ok(body) => body,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let req = self.request_with_body(Method::Post, WRITE_VALUE_PATH, body)
+            .map(|mut req| {
+                req.headers_mut().set(ContentEncoding(vec![Encoding::EncodingExt(SNAPPY_ENCODING.to_string())]));
+                req
+            });
+        Box::new(
+            self.send(req)
+                .and_then(self.receive_body())
+                .map(|_| ())
+        )
+    }
+
+    /// Fetches the statistics the server keeps about its chunk store.
+    pub fn get_stats(&self) -> Box<Future<Item = ServerStats, Error = Error>> {
+        Box::new(
+            self.send(self.request_for(Method::Get, STATS_PATH))
+                .and_then(self.receive_body())
+                .and_then(|c| String::from_utf8(c.to_vec()).map_err(|e| e.into()))
+                .map(ServerStats::parse)
+        )
+    }
+
+    /// Runs a GraphQL query against the head of a dataset, resolving to the JSON the server
+    /// responds with. The `variables` are only sent if they are not null.
+    pub fn post_graphql(&self, dataset: &str, query: &str, variables: &Json) -> Box<Future<Item = Json, Error = Error>> {
+        let mut form = vec![("ds", dataset.to_string()), ("query", query.to_string())];
+        if !variables.is_null() {
+            form.push(("vars", variables.to_string()));
+        }
+        let body = form
+            .iter()
+            .map(|&(name, ref value)| format!("{}={}", name, form_encode(value)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let req = self.request_with_body(Method::Post, GRAPHQL_PATH, body.into_bytes())
+            .map(|mut req| {
+                req.headers_mut().set(ContentType::form_url_encoded());
+                req
+            });
+        Box::new(
+            self.send(req)
+                .and_then(self.receive_body())
+                .and_then(|c| serde_json::from_slice(&c).map_err(|e| e.into()))
+        )
+    }
+
+    /// Attempts to move the root from `last` to `current`. Resolves to false if the root of the
+    /// database was no longer `last`.
+    pub fn post_root(&self, last: Hash, current: Hash) -> Box<Future<Item = bool, Error = Error>> {
+        let query = format!("last={}&current={}", last.to_string(), current.to_string());
+        Box::new(
+            self.send(self.request_with_query(Method::Post, ROOT_PATH, &query))
+                .and_then(|res| match res.status() {
+                    StatusCode::Ok => Ok(true),
+                    StatusCode::Conflict => Ok(false),
+                    status => Err(Error::Http(status)),
+                })
+        )
+    }
+}
+
+/// Serializes chunks the way the Noms server expects them: each chunk is its hash, followed by its
+/// length and data. The whole stream is then compressed using snappy.
+fn serialize_chunks(chunks: &[(Hash, Vec<u8>)]) -> Result<Vec<u8>, Error> {
+    let mut encoder = FrameEncoder::new(vec![]);
+    let mut len = [0; 4];
+    for &(ref hash, ref data) in chunks {
+        NetworkEndian::write_u32(&mut len, data.len() as u32);
+        encoder.write_all(&hash.raw_bytes())?;
+        encoder.write_all(&len)?;
+        encoder.write_all(data)?;
+    }
+    encoder.into_inner().map_err(|err| err.into_error().into())
+}
+
+/// Encodes a value of an `application/x-www-form-urlencoded` body.
+fn form_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for &byte in value.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => encoded.push(byte as char),
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
 }
 
 fn serialize_hashes(hashes: &HashSet<Hash>) -> Vec<u8> {
@@ -116,7 +274,7 @@ fn serialize_hashes(hashes: &HashSet<Hash>) -> Vec<u8> {
 
 fn retrieve_body(res: Response) -> Box<Future<Item = hyper::Chunk, Error = Error>> {
     match res.status() {
-        StatusCode::Ok => Box::new(res.body().concat2().map_err(|err| Error::Hyper(err))),
+        status if status.is_success() => Box::new(res.body().concat2().map_err(|err| Error::Hyper(err))),
         status => Box::new(future::result(Err(Error::Http(status)))),
     }
 }
@@ -0,0 +1,234 @@
+//! Establishes the TLS connections used to talk to HTTPS servers, using OpenSSL
+
+use std::io::{self, Read, Write};
+use futures::{Future, Poll, Async, future};
+use hyper::Uri;
+use hyper::client::{HttpConnector, Service};
+use openssl::ssl::{SslConnector, SslStream, HandshakeError, ErrorCode};
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+const DNS_THREADS: usize = 4;
+
+/// Connects to either HTTP or HTTPS servers, depending on the scheme of the requested URI. HTTPS
+/// is only available if the connector was created with a `SslConnector`.
+#[derive(Clone)]
+pub(crate) struct Connector {
+    http: HttpConnector,
+    tls: Option<SslConnector>,
+}
+
+impl Connector {
+    pub fn new(tls: Option<SslConnector>, handle: &Handle) -> Self {
+        let mut http = HttpConnector::new(DNS_THREADS, handle);
+        http.enforce_http(false);
+        Connector{ http, tls }
+    }
+}
+
+impl Service for Connector {
+    type Request = Uri;
+    type Response = Stream;
+    type Error = io::Error;
+    type Future = Box<Future<Item = Stream, Error = io::Error>>;
+
+    fn call(&self, uri: Uri) -> Self::Future {
+        let tls = match (uri.scheme() == Some("https"), self.tls.clone()) {
+            (false, _) => return Box::new(self.http.call(uri).map(Stream::Plain)),
+            (true, None) => return Box::new(future::err(io::Error::new(
+                io::ErrorKind::Other,
+                "HTTPS is not enabled for this connection",
+            ))),
+            (true, Some(tls)) => tls,
+        };
+        let host = uri.host().unwrap_or("").to_string();
+        Box::new(
+            self.http.call(uri)
+                .and_then(move |tcp| Handshake(Some(tls.connect(&host, tcp))))
+        )
+    }
+}
+
+/// Drives a TLS handshake over a non-blocking socket to completion.
+struct Handshake(Option<Result<SslStream<TcpStream>, HandshakeError<TcpStream>>>);
+
+impl Future for Handshake {
+    type Item = Stream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Stream, io::Error> {
+        let result = match self.0.take().expect("Polled a completed handshake") {
+            Err(HandshakeError::WouldBlock(mid)) => mid.handshake(),
+            result => result,
+        };
+        match result {
+            Ok(stream) => Ok(Async::Ready(Stream::Tls(stream))),
+            Err(HandshakeError::WouldBlock(mid)) => {
+                self.0 = Some(Err(HandshakeError::WouldBlock(mid)));
+                Ok(Async::NotReady)
+            }
+            Err(err) => Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+        }
+    }
+}
+
+/// A connection to a server, which may or may not be encrypted.
+pub(crate) enum Stream {
+    Plain(TcpStream),
+    Tls(SslStream<TcpStream>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            &mut Stream::Plain(ref mut s) => s.read(buf),
+            &mut Stream::Tls(ref mut s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            &mut Stream::Plain(ref mut s) => s.write(buf),
+            &mut Stream::Tls(ref mut s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            &mut Stream::Plain(ref mut s) => s.flush(),
+            &mut Stream::Tls(ref mut s) => s.flush(),
+        }
+    }
+}
+
+impl AsyncRead for Stream {}
+
+impl AsyncWrite for Stream {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self {
+            &mut Stream::Plain(ref mut s) => AsyncWrite::shutdown(s),
+            &mut Stream::Tls(ref mut s) => {
+                match s.shutdown() {
+                    Ok(_) => {}
+                    Err(ref err) if err.code() == ErrorCode::ZERO_RETURN => {}
+                    Err(err) => match err.into_io_error() {
+                        Ok(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                        Ok(err) => return Err(err),
+                        Err(err) => return Err(io::Error::new(io::ErrorKind::Other, err.to_string())),
+                    },
+                }
+                AsyncWrite::shutdown(s.get_mut())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Client;
+    use database::Protocol;
+    use error::Error;
+    use hash::EMPTY_HASH;
+    use openssl::asn1::Asn1Time;
+    use openssl::bn::BigNum;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::{PKey, Private};
+    use openssl::ssl::{SslAcceptor, SslConnector, SslMethod};
+    use openssl::x509::{X509, X509Name, X509NameRef};
+    use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use tokio_core::reactor::Core;
+
+    fn key() -> PKey<Private> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+    }
+
+    fn name(cn: &str) -> X509Name {
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, cn).unwrap();
+        name.build()
+    }
+
+    fn certificate(subject: &X509NameRef, key: &PKey<Private>, issuer: Option<(&X509, &PKey<Private>)>) -> X509 {
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap()).unwrap();
+        cert.set_subject_name(subject).unwrap();
+        cert.set_pubkey(key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+        match issuer {
+            None => {
+                cert.set_issuer_name(subject).unwrap();
+                cert.append_extension(BasicConstraints::new().critical().ca().build().unwrap()).unwrap();
+                cert.sign(key, MessageDigest::sha256()).unwrap();
+            }
+            Some((ca, ca_key)) => {
+                cert.set_issuer_name(ca.subject_name()).unwrap();
+                let san = SubjectAlternativeName::new()
+                    .dns("localhost")
+                    .build(&cert.x509v3_context(Some(ca), None))
+                    .unwrap();
+                cert.append_extension(san).unwrap();
+                cert.sign(ca_key, MessageDigest::sha256()).unwrap();
+            }
+        }
+        cert.build()
+    }
+
+    #[test]
+    fn https_with_self_signed_ca() {
+        let ca_key = key();
+        let ca = certificate(&name("nomrs test CA"), &ca_key, None);
+        let server_key = key();
+        let server_cert = certificate(&name("localhost"), &server_key, Some((&ca, &ca_key)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+            acceptor.set_private_key(&server_key).unwrap();
+            acceptor.set_certificate(&server_cert).unwrap();
+            let acceptor = acceptor.build();
+            for stream in listener.incoming().take(2) {
+                let mut stream = match acceptor.accept(stream.unwrap()) {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut request = vec![];
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let len = stream.read(&mut buf).unwrap();
+                    request.extend_from_slice(&buf[..len]);
+                }
+                let root = EMPTY_HASH.to_string();
+                write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", root.len(), root).unwrap();
+            }
+        });
+
+        let mut core = Core::new().unwrap();
+        let database = format!("localhost:{}", port);
+
+        let untrusted = SslConnector::builder(SslMethod::tls()).unwrap().build();
+        let client = Client::new(Protocol::Https, database.clone(), "7.18".to_string(), Some(untrusted), vec![], &core.handle());
+        match core.run(client.get_root()) {
+            Err(Error::Hyper(_)) => {}
+            other => panic!("Expected the certificate to be rejected, but got {:?}", other),
+        }
+
+        let mut trusted = SslConnector::builder(SslMethod::tls()).unwrap();
+        trusted.cert_store_mut().add_cert(ca).unwrap();
+        let client = Client::new(Protocol::Https, database, "7.18".to_string(), Some(trusted.build()), vec![], &core.handle());
+        assert_eq!(core.run(client.get_root()).unwrap(), EMPTY_HASH);
+
+        server.join().unwrap();
+    }
+}
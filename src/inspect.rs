@@ -0,0 +1,385 @@
+//! Annotates the encoding of a chunk, for debugging values which Go Noms and nomrs disagree about.
+//! Every part of the encoding is shown on its own line, after the offset of its first byte:
+//!
+//! ```text
+//!      0  [Kind 9] Struct:
+//!      1    Name: [Length 6] "Commit"
+//!      8    Props: [Count 3]
+//!      9      Name: [Length 4] "meta"
+//!     14      Value:
+//!     14        [Kind 9] Struct:
+//! ```
+//!
+//! Decoding stops at the first byte which cannot be part of a valid encoding, and the reason is
+//! shown there, so that truncated and malformed chunks can be inspected too.
+
+use std::fmt;
+use std::str;
+use database::Database;
+use hash::{Hash, BYTE_LEN};
+use value::{Kind, IntoNoms, number_to_string};
+use error::Error;
+
+/// The number of bytes of a blob which are shown
+const BLOB_BYTES: usize = 32;
+const INDENT: &'static str = "  ";
+
+/// Where and why decoding a chunk stopped
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Failure {
+    /// The offset of the first byte which could not be decoded
+    pub offset: usize,
+    pub reason: String,
+}
+
+/// The annotated encoding of a chunk, which is shown with `Display`
+#[derive(Clone, Debug)]
+pub struct Inspection {
+    nodes: Vec<Node>,
+    failure: Option<Failure>,
+}
+
+/// A line of the annotated encoding, along with the lines for the parts it is made of
+#[derive(Clone, Debug)]
+struct Node {
+    offset: usize,
+    text: String,
+    children: Vec<Node>,
+}
+
+type Decoded<T> = Result<T, Failure>;
+
+impl Inspection {
+    /// Where and why decoding stopped, if the chunk is not a valid encoding
+    pub fn failure(&self) -> Option<&Failure> {
+        self.failure.as_ref()
+    }
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_nodes(f: &mut fmt::Formatter, nodes: &[Node], depth: usize) -> fmt::Result {
+            for node in nodes {
+                writeln!(f, "{:>6}  {}{}", node.offset, INDENT.repeat(depth), node.text)?;
+                write_nodes(f, &node.children, depth + 1)?;
+            }
+            Ok(())
+        }
+        write_nodes(f, &self.nodes, 0)
+    }
+}
+
+/// Annotates the encoding of a value, as it is stored in a chunk.
+pub fn inspect(bytes: &[u8]) -> Inspection {
+    let mut cursor = Cursor{ bytes, offset: 0 };
+    let mut nodes = vec![];
+    let mut result = cursor.value(&mut nodes);
+    if result.is_ok() && cursor.offset < bytes.len() {
+        result = Err(cursor.fail(format!("{} bytes follow the value", bytes.len() - cursor.offset)));
+    }
+    let failure = result.err();
+    if let Some(ref failure) = failure {
+        deepest(&mut nodes).push(Node::new(failure.offset, format!("!! {}", failure.reason)));
+    }
+    Inspection{ nodes, failure }
+}
+
+/// Reads a chunk from the database, and annotates its encoding. The chunk is not decoded when it
+/// is read, so this works for chunks which nomrs cannot read otherwise.
+pub fn inspect_chunk<D: Database>(database: &D, hash: Hash) -> Result<Inspection, Error> {
+    Ok(inspect(&database.read_value(hash)?.into_noms()))
+}
+
+impl Node {
+    fn new(offset: usize, text: String) -> Self {
+        Node{ offset, text, children: vec![] }
+    }
+}
+
+/// Adds a line, returning the list its parts should be added to.
+fn open(nodes: &mut Vec<Node>, offset: usize, text: String) -> &mut Vec<Node> {
+    nodes.push(Node::new(offset, text));
+    &mut nodes.last_mut().unwrap().children
+}
+
+/// The list of lines which the last line added belongs to, which is where decoding stopped.
+fn deepest(nodes: &mut Vec<Node>) -> &mut Vec<Node> {
+    if nodes.last().is_some_and(|node| !node.children.is_empty()) {
+        deepest(&mut nodes.last_mut().unwrap().children)
+    } else {
+        nodes
+    }
+}
+
+fn signed(raw: u64) -> i64 {
+    ((raw >> 1) as i64) ^ -((raw & 1) as i64)
+}
+
+fn kind_label(kind: Kind) -> String {
+    format!("[Kind {}] {:?}", kind as u8, kind)
+}
+
+/// Reads an encoding, checking that every read stays within the chunk.
+struct Cursor<'b> {
+    bytes: &'b [u8],
+    offset: usize,
+}
+
+impl<'b> Cursor<'b> {
+    fn fail(&self, reason: String) -> Failure {
+        Failure{ offset: self.offset, reason }
+    }
+
+    fn take(&mut self, len: u64) -> Decoded<&'b [u8]> {
+        let left = self.bytes.len() - self.offset;
+        if len > left as u64 {
+            return Err(self.fail(format!("{} bytes were expected, but only {} are left", len, left)));
+        }
+        let bytes = &self.bytes[self.offset..self.offset + len as usize];
+        self.offset += len as usize;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Decoded<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Decoded<u64> {
+        let start = self.offset;
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            n |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(Failure{ offset: start, reason: "The varint is longer than 64 bits".to_string() })
+    }
+
+    fn kind(&mut self) -> Decoded<Kind> {
+        let start = self.offset;
+        let byte = self.u8()?;
+        Kind::from_u8(byte).ok_or_else(|| Failure{ offset: start, reason: format!("{} is not a kind", byte) })
+    }
+
+    fn hash(&mut self) -> Decoded<Hash> {
+        Ok(Hash::from_slice(self.take(BYTE_LEN as u64)?))
+    }
+
+    fn utf8(&mut self) -> Decoded<String> {
+        let len = self.varint()?;
+        let start = self.offset;
+        let bytes = self.take(len)?;
+        let text = str::from_utf8(bytes)
+            .map_err(|e| Failure{ offset: start + e.valid_up_to(), reason: "The string is not valid UTF-8".to_string() })?;
+        Ok(format!("[Length {}] {:?}", len, text))
+    }
+
+    /// Adds a line starting with the label, followed by the annotation of what is decoded.
+    fn annotate<F>(&mut self, nodes: &mut Vec<Node>, label: &str, decode: F) -> Decoded<()>
+    where F: FnOnce(&mut Self) -> Decoded<String> {
+        let start = self.offset;
+        self.annotate_from(nodes, start, label, decode)
+    }
+
+    /// Like `annotate`, for a line which starts at an earlier offset, such as that of a kind.
+    fn annotate_from<F>(&mut self, nodes: &mut Vec<Node>, start: usize, label: &str, decode: F) -> Decoded<()>
+    where F: FnOnce(&mut Self) -> Decoded<String> {
+        nodes.push(Node::new(start, label.to_string()));
+        let annotation = decode(self)?;
+        nodes.last_mut().unwrap().text = if label.is_empty() {
+            annotation
+        } else {
+            format!("{} {}", label, annotation)
+        };
+        Ok(())
+    }
+
+    fn value(&mut self, nodes: &mut Vec<Node>) -> Decoded<()> {
+        let start = self.offset;
+        let kind = self.kind()?;
+        let label = kind_label(kind);
+        match kind {
+            Kind::Boolean => self.annotate_from(nodes, start, &format!("{}:", label), |c| match c.u8()? {
+                b @ 0 | b @ 1 => Ok(format!("[Byte {}] {}", b, b == 1)),
+                b => Err(Failure{ offset: c.offset - 1, reason: format!("{} is not a boolean", b) }),
+            }),
+            Kind::Number => {
+                let parts = open(nodes, start, format!("{}:", label));
+                let (mut i, mut e) = (0, 0);
+                self.annotate(parts, "i:", |c| c.varint().map(|raw| { i = signed(raw); format!("[Varint {}] {}", raw, i) }))?;
+                self.annotate(parts, "exp:", |c| c.varint().map(|raw| { e = signed(raw); format!("[Varint {}] {}", raw, e) }))?;
+                nodes.last_mut().unwrap().text = format!("{}: {}", label, number_to_string(i, e));
+                Ok(())
+            }
+            Kind::String => self.annotate_from(nodes, start, &format!("{}:", label), Cursor::utf8),
+            Kind::Ref => self.reference(nodes, start),
+            Kind::Type => self.type_desc(open(nodes, start, format!("{}:", label))),
+            Kind::Struct => {
+                let parts = open(nodes, start, format!("{}:", label));
+                self.annotate(parts, "Name:", Cursor::utf8)?;
+                let count_start = self.offset;
+                let count = self.varint()?;
+                let props = open(parts, count_start, format!("Props: [Count {}]", count));
+                for _ in 0..count {
+                    self.annotate(props, "Name:", Cursor::utf8)?;
+                    let value_start = self.offset;
+                    self.value(open(props, value_start, "Value:".to_string()))?;
+                }
+                Ok(())
+            }
+            Kind::Blob | Kind::List | Kind::Set | Kind::Map => self.sequence(open(nodes, start, format!("{}:", label)), kind),
+            Kind::Value | Kind::Cycle | Kind::Union | Kind::Hash =>
+                Err(Failure{ offset: start, reason: format!("A value cannot be of kind {:?}", kind) }),
+        }
+    }
+
+    fn sequence(&mut self, parts: &mut Vec<Node>, kind: Kind) -> Decoded<()> {
+        let (mut level, mut count) = (0, 0);
+        self.annotate(parts, "", |c| {
+            level = c.varint()?;
+            count = c.varint()?;
+            Ok(format!("[Level {}] [Count {}]", level, count))
+        })?;
+        let start = self.offset;
+        if level > 0 {
+            let tuples = open(parts, start, "MetaTuples:".to_string());
+            for _ in 0..count {
+                self.metatuple(tuples)?;
+            }
+        } else if kind == Kind::Blob {
+            self.annotate(parts, "Bytes:", |c| {
+                let bytes = c.take(count)?;
+                let shown: Vec<String> = bytes.iter().take(BLOB_BYTES).map(u8::to_string).collect();
+                Ok(match bytes.len().checked_sub(BLOB_BYTES) {
+                    Some(more) if more > 0 => format!("[{}, ... {} more]", shown.join(", "), more),
+                    _ => format!("[{}]", shown.join(", ")),
+                })
+            })?;
+        } else if kind == Kind::Map {
+            let entries = open(parts, start, "Entries:".to_string());
+            for _ in 0..count {
+                let key_start = self.offset;
+                self.value(open(entries, key_start, "Key:".to_string()))?;
+                let value_start = self.offset;
+                self.value(open(entries, value_start, "Value:".to_string()))?;
+            }
+        } else {
+            let values = open(parts, start, "Values:".to_string());
+            for _ in 0..count {
+                self.value(values)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn metatuple(&mut self, nodes: &mut Vec<Node>) -> Decoded<()> {
+        let parts = open(nodes, self.offset, "MetaTuple:".to_string());
+        let start = self.offset;
+        match self.kind()? {
+            Kind::Ref => self.reference(parts, start)?,
+            kind => return Err(Failure{ offset: start, reason: format!("A meta tuple must start with a ref, not {:?}", kind) }),
+        }
+        let key_start = self.offset;
+        let key = open(parts, key_start, "OrderedKey:".to_string());
+        if self.bytes.get(key_start) == Some(&(Kind::Hash as u8)) {
+            self.offset += 1;
+            self.annotate_from(key, key_start, &format!("{}:", kind_label(Kind::Hash)), |c| c.hash().map(|h| h.to_string()))?;
+        } else {
+            self.value(key)?;
+        }
+        self.annotate(parts, "NumLeaves:", |c| c.varint().map(|n| format!("[Varint {}]", n)))
+    }
+
+    /// Annotates a ref, whose kind has been read already.
+    fn reference(&mut self, nodes: &mut Vec<Node>, start: usize) -> Decoded<()> {
+        let parts = open(nodes, start, format!("{}:", kind_label(Kind::Ref)));
+        self.annotate(parts, "Hash:", |c| c.hash().map(|h| h.to_string()))?;
+        let type_start = self.offset;
+        self.type_desc(open(parts, type_start, "Type:".to_string()))?;
+        self.annotate(parts, "Height:", |c| c.varint().map(|n| format!("[Varint {}]", n)))
+    }
+
+    /// Annotates a type, as it is encoded within a ref or a `Type` value.
+    fn type_desc(&mut self, nodes: &mut Vec<Node>) -> Decoded<()> {
+        let start = self.offset;
+        let kind = self.kind()?;
+        let label = kind_label(kind);
+        match kind {
+            Kind::Boolean | Kind::Number | Kind::String | Kind::Blob | Kind::Value | Kind::Type => {
+                nodes.push(Node::new(start, label));
+                Ok(())
+            }
+            Kind::List | Kind::Set | Kind::Ref => self.type_desc(open(nodes, start, format!("{}:", label))),
+            Kind::Map => {
+                let parts = open(nodes, start, format!("{}:", label));
+                self.type_desc(parts)?;
+                self.type_desc(parts)
+            }
+            Kind::Union => {
+                let mut count = 0;
+                self.annotate_from(nodes, start, &format!("{}:", label), |c| c.varint().map(|n| { count = n; format!("[Count {}]", n) }))?;
+                let parts = &mut nodes.last_mut().unwrap().children;
+                for _ in 0..count {
+                    self.type_desc(parts)?;
+                }
+                Ok(())
+            }
+            Kind::Cycle => self.annotate_from(nodes, start, &format!("{}:", label), Cursor::utf8),
+            Kind::Struct => {
+                let parts = open(nodes, start, format!("{}:", label));
+                self.annotate(parts, "Name:", Cursor::utf8)?;
+                let count_start = self.offset;
+                let count = self.varint()?;
+                let props = open(parts, count_start, format!("Props: [Count {}]", count));
+                let names = open(props, self.offset, "Names:".to_string());
+                for _ in 0..count {
+                    self.annotate(names, "Name:", Cursor::utf8)?;
+                }
+                let types = open(props, self.offset, "Types:".to_string());
+                for _ in 0..count {
+                    self.type_desc(types)?;
+                }
+                let optional = open(props, self.offset, "Optional:".to_string());
+                for _ in 0..count {
+                    self.annotate(optional, "", |c| match c.u8()? {
+                        b @ 0 | b @ 1 => Ok(format!("[Bool {}] {}", b, b == 1)),
+                        b => Err(Failure{ offset: c.offset - 1, reason: format!("{} is not a boolean", b) }),
+                    })?;
+                }
+                Ok(())
+            }
+            Kind::Hash => Err(Failure{ offset: start, reason: "A type cannot be of kind Hash".to_string() }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers() {
+        let inspection = inspect(&[Kind::Number as u8, 26, 6]);
+        assert_eq!(inspection.failure(), None);
+        assert_eq!(
+            inspection.to_string(),
+            "     0  [Kind 1] Number: 104\n     1    i: [Varint 26] 13\n     2    exp: [Varint 6] 3\n",
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let inspection = inspect(&[Kind::List as u8, 0, 2, Kind::String as u8, 1, b'a', Kind::String as u8, 5, b'b']);
+        assert_eq!(inspection.failure(), Some(&Failure{ offset: 8, reason: "5 bytes were expected, but only 1 are left".to_string() }));
+        assert_eq!(inspection.to_string(), concat!(
+            "     0  [Kind 5] List:\n",
+            "     1    [Level 0] [Count 2]\n",
+            "     3    Values:\n",
+            "     3      [Kind 2] String: [Length 1] \"a\"\n",
+            "     6      [Kind 2] String:\n",
+            "     8      !! 5 bytes were expected, but only 1 are left\n",
+        ));
+    }
+}
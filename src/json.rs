@@ -0,0 +1,198 @@
+//! Imports and exports Noms values as JSON, following the conventions of the `json-import` and
+//! `json-export` commands of Go Noms.
+//!
+//! Arrays become lists, and numbers, strings and booleans become their Noms counterparts. Objects
+//! become either maps from strings to values or structs, depending on the chosen `Objects`. `null`
+//! has no Noms equivalent, so it is left out of the array or object which holds it.
+//!
+//! When exporting, sets become arrays too, and structs become objects, losing their names.
+
+use std::io::{Read, Write};
+use std::collections::HashMap;
+use serde_json::{self, Value as Json};
+use database::Database;
+use value::{NomsValue, Value, Struct, IntoNoms, encode_struct, write_list, write_map};
+use error::Error;
+
+/// How JSON objects are imported
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Objects {
+    /// As a `Map<String, Value>`
+    Maps,
+    /// As an unnamed struct. Keys which are not valid struct field names are escaped as they are
+    /// in Go Noms: each disallowed character is replaced by `Q` and the hex encoding of its UTF-8
+    /// bytes, and `Q` itself is always escaped.
+    Structs,
+}
+
+impl Default for Objects {
+    fn default() -> Self { Objects::Maps }
+}
+
+/// Reads a JSON document, and converts it to a value held by the database.
+pub fn import<'a, D, R>(database: &'a D, reader: R, objects: Objects) -> Result<NomsValue<'a>, Error>
+where D: Database, R: Read {
+    Ok(database.value_from(encode(database, reader, objects)?))
+}
+
+/// Reads a JSON document, and encodes it as Noms data. Large arrays and objects are split into
+/// chunks, which are written to the database; the root is returned.
+pub fn encode<D: Database, R: Read>(database: &D, reader: R, objects: Objects) -> Result<Vec<u8>, Error> {
+    let json: Json = serde_json::from_reader(reader)?;
+    encode_json(database, json, objects)?
+        .ok_or_else(|| Error::ConversionError("A null JSON document cannot be imported".to_string()))
+}
+
+fn encode_json<D: Database>(database: &D, json: Json, objects: Objects) -> Result<Option<Vec<u8>>, Error> {
+    Ok(Some(match json {
+        Json::Null => return Ok(None),
+        Json::Bool(b) => b.into_noms(),
+        Json::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                i.into_noms()
+            } else if let Some(u) = n.as_u64() {
+                u.into_noms()
+            } else {
+                n.as_f64().unwrap().into_noms()
+            }
+        }
+        Json::String(s) => s.into_noms(),
+        Json::Array(items) => {
+            let mut encoded = Vec::with_capacity(items.len());
+            for item in items {
+                if let Some(item) = encode_json(database, item, objects)? {
+                    encoded.push(item);
+                }
+            }
+            write_list(database, encoded)?
+        }
+        Json::Object(props) => match objects {
+            Objects::Maps => {
+                let mut entries = vec![];
+                for (key, value) in props {
+                    if let Some(value) = encode_json(database, value, objects)? {
+                        entries.push((key.into_noms(), value));
+                    }
+                }
+                write_map(database, entries)?
+            }
+            Objects::Structs => {
+                let mut fields = HashMap::new();
+                for (key, value) in props {
+                    if let Some(value) = encode_json(database, value, objects)? {
+                        fields.insert(escape_field(&key), value);
+                    }
+                }
+                encode_struct("", fields)
+            }
+        },
+    }))
+}
+
+/// Escapes a JSON key so that it is a valid struct field name.
+pub(crate) fn escape_field(key: &str) -> String {
+    let mut field = String::with_capacity(key.len());
+    for (i, c) in key.chars().enumerate() {
+        let allowed = (c.is_ascii_alphabetic() && c != 'Q') || (i > 0 && (c.is_ascii_digit() || c == '_'));
+        if allowed {
+            field.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                field.push_str(&format!("Q{:02X}", b));
+            }
+        }
+    }
+    field
+}
+
+/// Writes a value as a JSON document. Lists, sets and maps are read one chunk at a time as they
+/// are written, so large collections are never held in memory at once.
+pub fn export<'a, W: Write>(value: &NomsValue<'a>, mut writer: W) -> Result<(), Error> {
+    export_value(value.clone().import(), &mut writer)
+}
+
+fn export_value<'a, W: Write>(value: Value<'a>, writer: &mut W) -> Result<(), Error> {
+    match value.compile() {
+        Value::Boolean(b) => write!(writer, "{}", b)?,
+        Value::Number(i, e) => {
+            // numbers are stored as i * 2^e, which may not be an integer
+            match 2i64.checked_pow(e as u32).and_then(|p| i.checked_mul(p)) {
+                Some(n) if e >= 0 => write!(writer, "{}", n)?,
+                _ => serde_json::to_writer(&mut *writer, &(i as f64 * 2f64.powi(e as i32)))?,
+            }
+        }
+        Value::String(s) => serde_json::to_writer(&mut *writer, &s)?,
+        l @ Value::List(_) => {
+            let list = l.to_list::<NomsValue>().unwrap();
+            export_items(list.iter(), writer)?;
+        }
+        s @ Value::Set(_) => {
+            let set = s.to_set::<NomsValue>().unwrap();
+            export_items(set.iter(), writer)?;
+        }
+        m @ Value::Map(_) => {
+            // keys are ordered by value, so string keys are in the order of the strings
+            let map = m.to_map::<NomsValue, NomsValue>().unwrap();
+            let entries = map.iter().map(|entry| {
+                let (key, value) = entry?;
+                let key = key.into_string()
+                    .ok_or_else(|| Error::ConversionError("Only maps with string keys can be exported".to_string()))?;
+                Ok((key, value))
+            });
+            export_props(entries, writer)?;
+        }
+        Value::Struct(Struct{ props, .. }) => {
+            let mut props: Vec<_> = props.into_iter().collect();
+            props.sort_by(|a, b| a.0.cmp(&b.0));
+            export_props(props.into_iter().map(Ok), writer)?;
+        }
+        Value::Union(inner) => export_value(*inner, writer)?,
+        Value::Nil => write!(writer, "null")?,
+        v => return Err(Error::ConversionError(format!("{:?} cannot be exported as JSON", v))),
+    }
+    Ok(())
+}
+
+fn export_items<'a, W, I>(items: I, writer: &mut W) -> Result<(), Error>
+where W: Write, I: Iterator<Item = Result<NomsValue<'a>, Error>> {
+    write!(writer, "[")?;
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        export_value(item?.import(), writer)?;
+    }
+    write!(writer, "]")?;
+    Ok(())
+}
+
+fn export_props<'a, W, I>(props: I, writer: &mut W) -> Result<(), Error>
+where W: Write, I: Iterator<Item = Result<(String, NomsValue<'a>), Error>> {
+    write!(writer, "{{")?;
+    for (i, prop) in props.enumerate() {
+        let (key, value) = prop?;
+        if i > 0 {
+            write!(writer, ",")?;
+        }
+        serde_json::to_writer(&mut *writer, &key)?;
+        write!(writer, ":")?;
+        export_value(value.import(), writer)?;
+    }
+    write!(writer, "}}")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_fields() {
+        assert_eq!(escape_field("name"), "name");
+        assert_eq!(escape_field("first_name2"), "first_name2");
+        assert_eq!(escape_field("Quote"), "Q51uote");
+        assert_eq!(escape_field("1st place"), "Q31stQ20place");
+        assert_eq!(escape_field("é"), "QC3QA9");
+    }
+}
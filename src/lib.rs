@@ -9,19 +9,34 @@ extern crate byteorder;
 extern crate crypto;
 #[macro_use] extern crate hyper;
 extern crate tokio_core;
+extern crate tokio_io;
 extern crate futures;
 extern crate data_encoding;
 extern crate either;
+extern crate snap;
+extern crate openssl;
+#[macro_use] extern crate serde as serde_crate;
+extern crate serde_json;
+extern crate csv as csv_crate;
 
 pub mod database;
 pub mod dataset;
 pub mod value;
 pub mod error;
 pub mod util;
+pub mod serde;
+pub mod json;
+pub mod csv;
+pub mod blob;
+pub mod spec;
+pub mod inspect;
+pub mod codegen;
+pub mod graphql;
 
 // TODO: make a prelude of some sort...
 pub use database::Database;
 pub use chunk::Chunk;
+pub use http::Middleware;
 
 mod http;
 mod chunk;
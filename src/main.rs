@@ -1,39 +1,479 @@
-//! This main function serves as a playground to test the library. Soon it will be replaced by
-//! actual test suites
+//! The `nomrs` command line tool, which works with Noms databases like the `noms` command of Go
+//! Noms does. Databases, datasets and values are named by specs, such as
+//! `http://localhost:8000::people` or `localhost:8000::#<hash>` (see `nomrs::spec`).
 //!
-//! The derive crate needs real some tests too.
+//! ```text
+//! nomrs [--json] ds <database>
+//! nomrs [--json] ds -d <dataset>
+//! nomrs [--json] root <database>
+//! nomrs [--json] log <dataset>
+//! nomrs [--json] show <value>
+//! nomrs [--json] type <value>
+//! nomrs [--json] diff <value> <value>
+//! nomrs inspect <value>
+//! nomrs inspect --file <file>
+//! nomrs [--json] sync <dataset> <dataset>
+//! nomrs [--json] commit [-m <message>] <value> <dataset>
+//! nomrs [--json] blob put <file> <dataset>
+//! nomrs blob export <value> [<file>]
+//! nomrs [--json] json import [--structs] <file> <dataset>
+//! nomrs json export <value>
+//! nomrs codegen <dataset>
+//! ```
+//!
+//! With `--json`, the output of every command is JSON, so that it can be read by other programs.
 
 extern crate nomrs;
-#[macro_use] extern crate nomrs_derive;
-extern crate byteorder;
+extern crate serde_json;
 
 use nomrs::{Noms, Database};
-use nomrs::value::NomsList;
+use nomrs::spec::{Spec, PathSpec};
+use nomrs::value::{NomsValue, Ref, Commit, Empty, IntoNoms, Printer, encode_struct};
+use nomrs::database::CommitOptions;
+use nomrs::dataset::Dataset;
+use nomrs::json::{self, Objects};
+use nomrs::blob;
+use nomrs::codegen;
+use nomrs::inspect::{self, Inspection};
+use nomrs::util::date;
+use nomrs::error::Error;
+use serde_json::Value as Json;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process;
+use std::time::SystemTime;
 
-#[derive(Clone, Debug, Noms)]
-struct Row {
-    count_female: String,
-    count_male: String,
-}
+const USAGE: &'static str = "Usage:
+    nomrs [--json] ds <database>
+    nomrs [--json] ds -d <dataset>
+    nomrs [--json] root <database>
+    nomrs [--json] log <dataset>
+    nomrs [--json] show <value>
+    nomrs [--json] type <value>
+    nomrs [--json] diff <value> <value>
+    nomrs inspect <value>
+    nomrs inspect --file <file>
+    nomrs [--json] sync <dataset> <dataset>
+    nomrs [--json] commit [-m <message>] <value> <dataset>
+    nomrs [--json] blob put <file> <dataset>
+    nomrs blob export <value> [<file>]
+    nomrs [--json] json import [--structs] <file> <dataset>
+    nomrs json export <value>
+    nomrs codegen <dataset>
 
-#[derive(Clone, Debug, Noms)]
-struct Meta {
-    date: String,
-    input_file: String,
-}
+Databases are named by URL, such as http://localhost:8000, datasets by
+<database>::<dataset>, and values by <database>::<dataset> (the value of its head)
+or <database>::#<hash>. Given a dataset, inspect shows the chunk of its head commit.
+codegen prints Rust types for the value and meta of a dataset's head commit.";
+
+const META_STRUCT_NAME: &'static str = "Meta";
 
 fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let json_output = match args.iter().position(|arg| arg == "--json") {
+        Some(i) => { args.remove(i); true }
+        None => false,
+    };
+    let out = Output{ json: json_output };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
     let noms = Noms::new();
-    let db = noms.database()
-        .noms_version("7.18")
-        .http("localhost:8000")
-        .unwrap();
-
-    println!("{:?}", db.datasets().unwrap());
-    let commit = db.dataset::<Meta, NomsList<Row>>("test").unwrap().head().unwrap();
-    println!("{:?}", commit.meta());
-    println!("{:?}", commit.parents().to_set());
-    println!("{:?}", commit.value().to_vec());
-    println!("{:?}", db.value_from("Hello world"));
-    println!("{:?}", db.value_from(Row{ count_female: "30".to_string(), count_male: "150".to_string() }));
+    let result = match args.as_slice() {
+        ["ds", "-d", dataset] => delete_dataset(&noms, &out, dataset),
+        ["ds", database] => list_datasets(&noms, &out, database),
+        ["root", database] => root(&noms, &out, database),
+        ["log", dataset] => log(&noms, &out, dataset),
+        ["show", value] => show(&noms, &out, value),
+        ["type", value] => show_type(&noms, &out, value),
+        ["diff", before, after] => diff(&noms, &out, before, after),
+        ["inspect", "--file", file] => inspect_file(file),
+        ["inspect", value] => inspect_chunk(&noms, value),
+        ["sync", source, dest] => sync(&noms, &out, source, dest),
+        ["commit", "-m", message, value, dataset] => commit(&noms, &out, value, dataset, Some(message)),
+        ["commit", value, dataset] => commit(&noms, &out, value, dataset, None),
+        ["blob", "put", file, dataset] => blob_put(&noms, &out, file, dataset),
+        ["blob", "export", value] => blob_export(&noms, value, None),
+        ["blob", "export", value, file] => blob_export(&noms, value, Some(file)),
+        ["json", "import", "--structs", file, dataset] => json_import(&noms, &out, file, dataset, Objects::Structs),
+        ["json", "import", file, dataset] => json_import(&noms, &out, file, dataset, Objects::Maps),
+        ["json", "export", value] => json_export(&noms, value),
+        ["codegen", dataset] => codegen(&noms, dataset),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+/// Prints either text or JSON, depending on `--json`
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    fn print(&self, text: &str, json: Json) {
+        if self.json {
+            println!("{}", json);
+        } else {
+            println!("{}", text);
+        }
+    }
+
+    /// Reports the new head of a dataset, after it has been changed
+    fn head(&self, ds: &Dataset) {
+        let hash = ds.head_ref().target_hash().to_string();
+        let mut json = serde_json::Map::new();
+        json.insert("dataset".to_string(), Json::String(ds.id().to_string()));
+        json.insert("head".to_string(), Json::String(hash.clone()));
+        self.print(&hash, Json::Object(json));
+    }
+}
+
+fn connect(noms: &Noms, spec: &Spec) -> Result<impl Database, Error> {
+    spec.database.connect(noms.database())
+}
+
+/// Parses a spec which must name a dataset
+fn dataset_spec(spec: &str) -> Result<(Spec, String), Error> {
+    let parsed = Spec::parse(spec)?;
+    let dataset = parsed.dataset()
+        .ok_or_else(|| Error::InvalidSpec(format!("{} does not name a dataset", spec)))?
+        .to_string();
+    Ok((parsed, dataset))
+}
+
+/// Reads the value named by a spec
+fn read_value<'a, D: Database>(database: &'a D, spec: &Spec) -> Result<NomsValue<'a>, Error> {
+    match spec.path {
+        Some(PathSpec::Dataset(ref ds)) => database.dataset::<Empty, NomsValue>(ds)?
+            .head_value()
+            .ok_or_else(|| Error::NoDataset(ds.clone())),
+        Some(PathSpec::Hash(hash)) => database.read_value(hash),
+        None => Err(Error::InvalidSpec("A database was given where a value was expected".to_string())),
+    }
+}
+
+fn to_json(value: &NomsValue) -> Result<Json, Error> {
+    let mut bytes = vec![];
+    json::export(value, &mut bytes)?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+fn list_datasets(noms: &Noms, out: &Output, database: &str) -> Result<(), Error> {
+    let spec = Spec::parse(database)?;
+    let db = connect(noms, &spec)?;
+    // the map is ordered by name
+    let names = db.datasets()?.iter().map(|entry| entry.map(|(name, _)| name)).collect::<Result<Vec<_>, Error>>()?;
+    out.print(&names.join("\n"), names.iter().cloned().map(Json::String).collect());
+    Ok(())
+}
+
+fn delete_dataset(noms: &Noms, out: &Output, dataset: &str) -> Result<(), Error> {
+    let (spec, name) = dataset_spec(dataset)?;
+    let db = connect(noms, &spec)?;
+    let ds = db.dataset::<Empty, NomsValue>(&name)?;
+    let was = ds.head_ref().target_hash().to_string();
+    db.delete(ds)?;
+    let mut json = serde_json::Map::new();
+    json.insert("dataset".to_string(), Json::String(name.clone()));
+    json.insert("was".to_string(), Json::String(was.clone()));
+    out.print(&format!("Deleted {} (was #{})", dataset, was), Json::Object(json));
+    Ok(())
+}
+
+fn root(noms: &Noms, out: &Output, database: &str) -> Result<(), Error> {
+    let spec = Spec::parse(database)?;
+    let db = connect(noms, &spec)?;
+    let root = db.root_hash()?.to_string();
+    out.print(&format!("#{}", root), Json::String(root.clone()));
+    Ok(())
+}
+
+/// Prints every commit of the dataset, newest first. Commits are ordered by height, so that a
+/// commit always comes before its parents.
+fn log(noms: &Noms, out: &Output, dataset: &str) -> Result<(), Error> {
+    let (spec, name) = dataset_spec(dataset)?;
+    let db = connect(noms, &spec)?;
+    let ds = db.dataset::<Empty, NomsValue>(&name)?;
+    let mut pending = BinaryHeap::new();
+    let mut seen = HashSet::new();
+    pending.push((ds.head_ref().height(), ds.head_ref().target_hash()));
+    let mut commits = vec![];
+    while let Some((_, hash)) = pending.pop() {
+        if !seen.insert(hash) {
+            continue;
+        }
+        let value = db.read_value(hash)?;
+        let meta = value.clone()
+            .to_struct_props()
+            .and_then(|(_, mut props)| props.remove("meta"))
+            .ok_or_else(|| Error::ConversionError(format!("#{} is not a commit", hash.to_string())))?;
+        let commit: Commit = value.transform_struct();
+        let mut parents = commit.parents().iter().collect::<Result<Vec<Ref<Commit>>, Error>>()?;
+        parents.sort_by_key(|r| r.target_hash());
+        for parent in &parents {
+            pending.push((parent.height(), parent.target_hash()));
+        }
+
+        let parents: Vec<String> = parents.iter().map(|r| r.target_hash().to_string()).collect();
+        let meta = to_json(&meta)?;
+        if out.json {
+            let mut json = serde_json::Map::new();
+            json.insert("hash".to_string(), Json::String(hash.to_string()));
+            json.insert("parents".to_string(), parents.into_iter().map(Json::String).collect());
+            json.insert("meta".to_string(), meta);
+            commits.push(Json::Object(json));
+        } else {
+            println!("commit #{}", hash.to_string());
+            if parents.is_empty() {
+                println!("Parent: None");
+            } else {
+                let parents: Vec<String> = parents.into_iter().map(|p| format!("#{}", p)).collect();
+                println!("Parent: {}", parents.join(" "));
+            }
+            if let Json::Object(fields) = meta {
+                for (key, value) in fields {
+                    println!("{}: {}", key, value);
+                }
+            }
+            println!();
+        }
+    }
+    if out.json {
+        println!("{}", Json::Array(commits));
+    }
+    Ok(())
+}
+
+fn show(noms: &Noms, out: &Output, value: &str) -> Result<(), Error> {
+    let spec = Spec::parse(value)?;
+    let db = connect(noms, &spec)?;
+    let value = read_value(&db, &spec)?;
+    if !out.json {
+        let stdout = io::stdout();
+        let printer = Printer::new().multiline(true).color(stdout.is_terminal());
+        let mut stdout = stdout.lock();
+        printer.print(&value, &mut stdout)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+    match blob::len(&value) {
+        Some(len) => {
+            let mut json = serde_json::Map::new();
+            json.insert("blob".to_string(), Json::from(len));
+            out.print("", Json::Object(json));
+        }
+        None => {
+            let json = to_json(&value)?;
+            out.print("", json);
+        }
+    }
+    Ok(())
+}
+
+/// Prints the type of a value, in the syntax that `Type` parses
+fn show_type(noms: &Noms, out: &Output, value: &str) -> Result<(), Error> {
+    let spec = Spec::parse(value)?;
+    let db = connect(noms, &spec)?;
+    let value_type = read_value(&db, &spec)?.type_of()?;
+    out.print(&format!("{:#}", value_type), Json::String(value_type.to_string()));
+    Ok(())
+}
+
+/// Annotates the encoding of the chunk of a value, or of a dataset's head commit
+fn inspect_chunk(noms: &Noms, value: &str) -> Result<(), Error> {
+    let spec = Spec::parse(value)?;
+    let db = connect(noms, &spec)?;
+    let hash = match spec.path {
+        Some(PathSpec::Dataset(ref ds)) => db.dataset::<Empty, NomsValue>(ds)?.head_ref().target_hash(),
+        Some(PathSpec::Hash(hash)) => hash,
+        None => return Err(Error::InvalidSpec("A database was given where a value was expected".to_string())),
+    };
+    print_inspection(inspect::inspect_chunk(&db, hash)?)
+}
+
+/// Annotates the encoding of a chunk which has been saved to a file
+fn inspect_file(file: &str) -> Result<(), Error> {
+    let mut bytes = vec![];
+    File::open(file)?.read_to_end(&mut bytes)?;
+    print_inspection(inspect::inspect(&bytes))
+}
+
+fn print_inspection(inspection: Inspection) -> Result<(), Error> {
+    print!("{}", inspection);
+    match inspection.failure() {
+        Some(failure) => Err(Error::ConversionError(format!("Decoding failed at byte {}: {}", failure.offset, failure.reason))),
+        None => Ok(()),
+    }
+}
+
+/// A difference between two JSON documents
+struct Change {
+    path: String,
+    before: Option<Json>,
+    after: Option<Json>,
+}
+
+fn diff_json(path: String, before: Json, after: Json, changes: &mut Vec<Change>) {
+    if before == after {
+        return;
+    }
+    match (before, after) {
+        (Json::Object(mut before), Json::Object(mut after)) => {
+            let mut keys: Vec<String> = before.keys().chain(after.keys()).cloned().collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = format!("{}.{}", path, key);
+                match (before.remove(&key), after.remove(&key)) {
+                    (Some(b), Some(a)) => diff_json(path, b, a, changes),
+                    (before, after) => changes.push(Change{ path, before, after }),
+                }
+            }
+        }
+        (Json::Array(before), Json::Array(after)) => {
+            let len = before.len().max(after.len());
+            let mut before = before.into_iter();
+            let mut after = after.into_iter();
+            for i in 0..len {
+                let path = format!("{}[{}]", path, i);
+                match (before.next(), after.next()) {
+                    (Some(b), Some(a)) => diff_json(path, b, a, changes),
+                    (before, after) => changes.push(Change{ path, before, after }),
+                }
+            }
+        }
+        (before, after) => changes.push(Change{ path, before: Some(before), after: Some(after) }),
+    }
+}
+
+fn diff(noms: &Noms, out: &Output, before: &str, after: &str) -> Result<(), Error> {
+    let (before, after) = (Spec::parse(before)?, Spec::parse(after)?);
+    let (before_db, after_db) = (connect(noms, &before)?, connect(noms, &after)?);
+    let before = to_json(&read_value(&before_db, &before)?)?;
+    let after = to_json(&read_value(&after_db, &after)?)?;
+    let mut changes = vec![];
+    diff_json(String::new(), before, after, &mut changes);
+    if out.json {
+        let changes: Vec<Json> = changes
+            .into_iter()
+            .map(|change| {
+                let mut json = serde_json::Map::new();
+                json.insert("path".to_string(), Json::String(change.path));
+                json.insert("before".to_string(), change.before.unwrap_or(Json::Null));
+                json.insert("after".to_string(), change.after.unwrap_or(Json::Null));
+                Json::Object(json)
+            })
+            .collect();
+        println!("{}", Json::Array(changes));
+    } else {
+        for change in changes {
+            let path = if change.path.is_empty() { "." } else { &change.path };
+            if let Some(before) = change.before {
+                println!("- {}: {}", path, before);
+            }
+            if let Some(after) = change.after {
+                println!("+ {}: {}", path, after);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copies the head of one dataset to another, which may be in another database. The destination
+/// is fast forwarded, so it must not have commits that the source does not.
+fn sync(noms: &Noms, out: &Output, source: &str, dest: &str) -> Result<(), Error> {
+    let (source_spec, source_name) = dataset_spec(source)?;
+    let (dest_spec, dest_name) = dataset_spec(dest)?;
+    let source_db = connect(noms, &source_spec)?;
+    let dest_db = connect(noms, &dest_spec)?;
+    let source_ds = source_db.dataset::<Empty, NomsValue>(&source_name)?;
+    let head = source_ds.head_ref().clone();
+    dest_db.pull(&source_db, head.target_hash())?;
+    let dest_ds = dest_db.dataset_or_empty(&dest_name)?;
+    let dest_ds = dest_db.fast_forward(dest_ds, head)?;
+    out.head(&dest_ds);
+    Ok(())
+}
+
+fn commit(noms: &Noms, out: &Output, value: &str, dataset: &str, message: Option<&str>) -> Result<(), Error> {
+    let value_spec = Spec::parse(value)?;
+    let (spec, name) = dataset_spec(dataset)?;
+    if value_spec.database != spec.database {
+        return Err(Error::InvalidSpec("The value must be in the same database as the dataset".to_string()));
+    }
+    let db = connect(noms, &spec)?;
+    let value = read_value(&db, &value_spec)?;
+    let mut meta = HashMap::new();
+    meta.insert("date".to_string(), date::rfc3339(SystemTime::now()).into_noms());
+    if let Some(message) = message {
+        meta.insert("message".to_string(), message.into_noms());
+    }
+    let options = CommitOptions{
+        meta: db.value_from(encode_struct(META_STRUCT_NAME, meta)),
+        ..CommitOptions::default()
+    };
+    let ds = db.dataset_or_empty(&name)?;
+    let ds = db.commit(ds, value, options)?;
+    out.head(&ds);
+    Ok(())
+}
+
+fn blob_put(noms: &Noms, out: &Output, file: &str, dataset: &str) -> Result<(), Error> {
+    let (spec, name) = dataset_spec(dataset)?;
+    let db = connect(noms, &spec)?;
+    let value = blob::write(&db, File::open(file)?)?;
+    let ds = db.dataset_or_empty(&name)?;
+    let ds = db.commit_value(ds, value)?;
+    out.head(&ds);
+    Ok(())
+}
+
+fn blob_export(noms: &Noms, value: &str, file: Option<&str>) -> Result<(), Error> {
+    let spec = Spec::parse(value)?;
+    let db = connect(noms, &spec)?;
+    let value = read_value(&db, &spec)?;
+    match file {
+        Some(file) => blob::export(&value, File::create(file)?),
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            blob::export(&value, &mut stdout)?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+fn json_import(noms: &Noms, out: &Output, file: &str, dataset: &str, objects: Objects) -> Result<(), Error> {
+    let (spec, name) = dataset_spec(dataset)?;
+    let db = connect(noms, &spec)?;
+    let value = json::import(&db, File::open(file)?, objects)?;
+    let ds = db.dataset_or_empty(&name)?;
+    let ds = db.commit_value(ds, value)?;
+    out.head(&ds);
+    Ok(())
+}
+
+fn json_export(noms: &Noms, value: &str) -> Result<(), Error> {
+    let spec = Spec::parse(value)?;
+    let db = connect(noms, &spec)?;
+    let value = read_value(&db, &spec)?;
+    let stdout = io::stdout();
+    json::export(&value, stdout.lock())?;
+    println!();
+    Ok(())
+}
+
+/// Prints Rust types, using `#[derive(Noms)]`, for the value and meta of the dataset's head
+fn codegen(noms: &Noms, dataset: &str) -> Result<(), Error> {
+    let (spec, name) = dataset_spec(dataset)?;
+    let db = connect(noms, &spec)?;
+    print!("{}", codegen::dataset_source(&db, &name)?);
+    Ok(())
 }
@@ -0,0 +1,203 @@
+//! Decodes deserializable values from Noms data
+
+use std::vec;
+use serde_crate::de::{self, Visitor, DeserializeSeed, IntoDeserializer};
+use value::{NomsValue, Value, Struct, FromNoms};
+use chunk::Chunk;
+use error::Error;
+use super::ser::NEWTYPE_FIELD;
+
+/// Deserializes values from Noms data which has been read from the database.
+pub struct Deserializer<'a> {
+    value: Value<'a>,
+}
+
+impl<'a> Deserializer<'a> {
+    pub fn new(value: NomsValue<'a>) -> Self {
+        Deserializer{ value: value.import() }
+    }
+
+    pub fn from_chunk(chunk: &Chunk<'a>) -> Self {
+        Deserializer{ value: Value::from_noms(chunk) }
+    }
+}
+
+fn unsupported(kind: &str) -> Error {
+    Error::ConversionError(format!("A Noms {} cannot be deserialized", kind))
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.value.compile() {
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Number(i, e) if e >= 0 => {
+                // numbers are stored as i * 2^e, which may no longer fit in an integer
+                match 2i64.checked_pow(e as u32).and_then(|p| i.checked_mul(p)) {
+                    Some(n) => visitor.visit_i64(n),
+                    None => visitor.visit_f64(i as f64 * 2f64.powi(e as i32)),
+                }
+            }
+            Value::Number(i, e) => visitor.visit_f64(i as f64 * 2f64.powi(e as i32)),
+            Value::String(s) => visitor.visit_string(s),
+            // the chunks of a collection are read before it is visited, so that an error fetching
+            // one is returned rather than a panic
+            l @ Value::List(_) => {
+                let items = l.to_list::<NomsValue>().unwrap().iter().collect::<Result<Vec<_>, Error>>()?;
+                visitor.visit_seq(Items{ iter: items.into_iter() })
+            }
+            s @ Value::Set(_) => {
+                let items = s.to_set::<NomsValue>().unwrap().iter().collect::<Result<Vec<_>, Error>>()?;
+                visitor.visit_seq(Items{ iter: items.into_iter() })
+            }
+            m @ Value::Map(_) => {
+                let entries = m.to_map::<NomsValue, NomsValue>().unwrap().iter().collect::<Result<Vec<_>, Error>>()?;
+                visitor.visit_map(Entries{ iter: entries.into_iter(), value: None })
+            }
+            Value::Struct(Struct{ props, .. }) => {
+                let entries: Vec<_> = props
+                    .into_iter()
+                    .map(|(k, v)| (Value::String(k).export(), v))
+                    .collect();
+                visitor.visit_map(Entries{ iter: entries.into_iter(), value: None })
+            }
+            Value::Union(inner) => Deserializer{ value: *inner }.deserialize_any(visitor),
+            Value::Nil => visitor.visit_unit(),
+            Value::Blob(_) => Err(unsupported("blob")),
+            Value::Ref(_) => Err(unsupported("ref")),
+            Value::Type(_) => Err(unsupported("type")),
+            Value::Value(_) => unreachable!(),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // a missing value is left out of its struct entirely, so any value that is present is Some
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.value.is_struct() {
+            visitor.visit_unit()
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self, name: &'static str, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        match self.value.compile() {
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            Value::Struct(Struct{ name, props }) => {
+                let entries: Vec<_> = props.into_iter().collect();
+                visitor.visit_enum(Variant{ name, entries })
+            }
+            _ => Err(Error::ConversionError(format!("Value is not a variant of {}", name))),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// The items of a list or set
+struct Items<'a> {
+    iter: vec::IntoIter<NomsValue<'a>>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for Items<'a> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, Error> {
+        match self.iter.next() {
+            Some(value) => seed.deserialize(Deserializer::new(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// The entries of a map, or the props of a struct
+struct Entries<'a> {
+    iter: vec::IntoIter<(NomsValue<'a>, NomsValue<'a>)>,
+    value: Option<NomsValue<'a>>,
+}
+
+impl<'de, 'a> de::MapAccess<'de> for Entries<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer::new(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self.value.take().expect("next_value_seed was called before next_key_seed");
+        seed.deserialize(Deserializer::new(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+/// A variant of an enum which was stored as a struct named after the variant
+struct Variant<'a> {
+    name: String,
+    entries: Vec<(String, NomsValue<'a>)>,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for Variant<'a> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let variant = seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.name.clone()))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for Variant<'a> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let name = self.name;
+        match self.entries.into_iter().find(|entry| entry.0 == NEWTYPE_FIELD) {
+            Some((_, value)) => seed.deserialize(Deserializer::new(value)),
+            None => Err(Error::ConversionError(format!("The variant {} has no value", name))),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _: usize, _: V) -> Result<V::Value, Error> {
+        Err(Error::Unimplemented(format!("Deserializing the tuple variant {}", self.name)))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self, _: &'static [&'static str], visitor: V) -> Result<V::Value, Error> {
+        let entries: Vec<_> = self.entries
+            .into_iter()
+            .map(|(k, v)| (Value::String(k).export(), v))
+            .collect();
+        visitor.visit_map(Entries{ iter: entries.into_iter(), value: None })
+    }
+}
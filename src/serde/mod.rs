@@ -0,0 +1,50 @@
+//! Stores any type implementing serde's `Serialize` and `Deserialize` in Noms, without needing
+//! `#[derive(Noms)]` too.
+//!
+//! Structs are mapped to Noms structs, sequences and tuples to lists, and maps to maps. `None` is
+//! represented by leaving a field out of its struct, so `Option`s are only supported as the fields
+//! of structs. Unit variants of enums are stored as strings, and struct variants as structs named
+//! after the variant, as they are by `#[derive(Noms)]`. Newtype variants are structs holding their
+//! value in a field called `value`.
+
+mod ser;
+mod de;
+
+pub use self::ser::Serializer;
+pub use self::de::Deserializer;
+
+use serde_crate::{Serialize, de::DeserializeOwned};
+use value::NomsValue;
+use chunk::Chunk;
+use error::Error;
+
+/// Encodes a value as Noms data, which can then be stored using `Database::value_from`.
+pub fn to_noms<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Error> {
+    let bytes = value.serialize(Serializer)?;
+    if bytes.is_empty() {
+        return Err(Error::ConversionError("None can only be stored as a field of a struct".to_string()));
+    }
+    Ok(bytes)
+}
+
+/// Decodes a value read from the database.
+pub fn from_value<'a, T: DeserializeOwned>(value: NomsValue<'a>) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(value))
+}
+
+/// Decodes a chunk read from the database.
+pub fn from_chunk<'a, T: DeserializeOwned>(chunk: &Chunk<'a>) -> Result<T, Error> {
+    T::deserialize(Deserializer::from_chunk(chunk))
+}
+
+impl ::serde_crate::ser::Error for Error {
+    fn custom<T: ::std::fmt::Display>(msg: T) -> Self {
+        Error::ConversionError(msg.to_string())
+    }
+}
+
+impl ::serde_crate::de::Error for Error {
+    fn custom<T: ::std::fmt::Display>(msg: T) -> Self {
+        Error::ConversionError(msg.to_string())
+    }
+}
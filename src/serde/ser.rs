@@ -0,0 +1,199 @@
+//! Encodes serializable values as Noms data
+
+use std::collections::HashMap;
+use serde_crate::ser::{self, Serialize, Impossible};
+use value::{IntoNoms, Kind, encode_struct, encode_leaf, encode_map_leaf};
+use error::Error;
+
+/// The field of the struct which holds the value of a newtype variant
+pub(crate) const NEWTYPE_FIELD: &str = "value";
+
+/// Serializes values into Noms data. `None` is serialized as no data at all, which is then left
+/// out of the struct it is a field of.
+#[derive(Copy, Clone, Debug)]
+pub struct Serializer;
+
+fn required(bytes: Vec<u8>, context: &str) -> Result<Vec<u8>, Error> {
+    if bytes.is_empty() {
+        Err(Error::ConversionError(format!("{} cannot hold None", context)))
+    } else {
+        Ok(bytes)
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = Impossible<Vec<u8>, Error>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeStruct;
+    type SerializeStructVariant = SerializeStruct;
+
+    fn serialize_bool(self, v: bool) -> Result<Vec<u8>, Error> { Ok(v.into_noms()) }
+    fn serialize_i8(self, v: i8) -> Result<Vec<u8>, Error> { Ok((v as i64).into_noms()) }
+    fn serialize_i16(self, v: i16) -> Result<Vec<u8>, Error> { Ok((v as i64).into_noms()) }
+    fn serialize_i32(self, v: i32) -> Result<Vec<u8>, Error> { Ok((v as i64).into_noms()) }
+    fn serialize_i64(self, v: i64) -> Result<Vec<u8>, Error> { Ok(v.into_noms()) }
+    fn serialize_u8(self, v: u8) -> Result<Vec<u8>, Error> { Ok((v as u64).into_noms()) }
+    fn serialize_u16(self, v: u16) -> Result<Vec<u8>, Error> { Ok((v as u64).into_noms()) }
+    fn serialize_u32(self, v: u32) -> Result<Vec<u8>, Error> { Ok((v as u64).into_noms()) }
+    fn serialize_u64(self, v: u64) -> Result<Vec<u8>, Error> { Ok(v.into_noms()) }
+    fn serialize_f32(self, v: f32) -> Result<Vec<u8>, Error> { Ok((v as f64).into_noms()) }
+    fn serialize_f64(self, v: f64) -> Result<Vec<u8>, Error> { Ok(v.into_noms()) }
+    fn serialize_char(self, v: char) -> Result<Vec<u8>, Error> { Ok(v.to_string().into_noms()) }
+    fn serialize_str(self, v: &str) -> Result<Vec<u8>, Error> { Ok(v.into_noms()) }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Vec<u8>, Error> {
+        Err(Error::Unimplemented("Serializing bytes as a Noms blob".to_string()))
+    }
+
+    fn serialize_none(self) -> Result<Vec<u8>, Error> { Ok(vec![]) }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Vec<u8>, Error> {
+        Ok(encode_struct("", HashMap::new()))
+    }
+    fn serialize_unit_struct(self, name: &'static str) -> Result<Vec<u8>, Error> {
+        Ok(encode_struct(name, HashMap::new()))
+    }
+    fn serialize_unit_variant(self, _: &'static str, _: u32, variant: &'static str) -> Result<Vec<u8>, Error> {
+        Ok(variant.into_noms())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _: &'static str, value: &T) -> Result<Vec<u8>, Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _: &'static str, _: u32, variant: &'static str, value: &T) -> Result<Vec<u8>, Error> {
+        let mut props = HashMap::new();
+        props.insert(NEWTYPE_FIELD.to_string(), required(value.serialize(self)?, variant)?);
+        Ok(encode_struct(variant, props))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, Error> {
+        Ok(SerializeList{ len: 0, items: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(self, _: &'static str, len: usize) -> Result<SerializeList, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(self, name: &'static str, _: u32, variant: &'static str, _: usize) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(Error::Unimplemented(format!("Serializing the tuple variant {}::{}", name, variant)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeMap, Error> {
+        Ok(SerializeMap{ key: None, entries: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<SerializeStruct, Error> {
+        Ok(SerializeStruct{ name, props: HashMap::with_capacity(len) })
+    }
+    fn serialize_struct_variant(self, _: &'static str, _: u32, variant: &'static str, len: usize) -> Result<SerializeStruct, Error> {
+        Ok(SerializeStruct{ name: variant, props: HashMap::with_capacity(len) })
+    }
+}
+
+/// Collects the items of a list
+pub struct SerializeList {
+    len: usize,
+    items: Vec<u8>,
+}
+
+impl SerializeList {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.extend(required(value.serialize(Serializer)?, "A list")?);
+        self.len += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> Vec<u8> {
+        encode_leaf(Kind::List, self.len, &self.items)
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> { self.push(value) }
+    fn end(self) -> Result<Vec<u8>, Error> { Ok(self.finish()) }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> { self.push(value) }
+    fn end(self) -> Result<Vec<u8>, Error> { Ok(self.finish()) }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> { self.push(value) }
+    fn end(self) -> Result<Vec<u8>, Error> { Ok(self.finish()) }
+}
+
+/// Collects the entries of a map
+pub struct SerializeMap {
+    key: Option<Vec<u8>>,
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Vec<u8>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(required(key.serialize(Serializer)?, "The key of a map")?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("serialize_value was called before serialize_key");
+        self.entries.push((key, required(value.serialize(Serializer)?, "A map")?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Vec<u8>, Error> {
+        Ok(encode_map_leaf(self.entries))
+    }
+}
+
+/// Collects the fields of a struct, leaving out those which are `None`
+pub struct SerializeStruct {
+    name: &'static str,
+    props: HashMap<String, Vec<u8>>,
+}
+
+impl SerializeStruct {
+    fn insert<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        let bytes = value.serialize(Serializer)?;
+        if !bytes.is_empty() {
+            self.props.insert(key.to_string(), bytes);
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for SerializeStruct {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+    fn end(self) -> Result<Vec<u8>, Error> { Ok(encode_struct(self.name, self.props)) }
+}
+
+impl ser::SerializeStructVariant for SerializeStruct {
+    type Ok = Vec<u8>;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+    fn end(self) -> Result<Vec<u8>, Error> { Ok(encode_struct(self.name, self.props)) }
+}
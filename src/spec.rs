@@ -0,0 +1,127 @@
+//! Parses the strings which name databases, datasets and values, following the spec format of Go
+//! Noms: `<database>`, `<database>::<dataset>` or `<database>::#<hash>`.
+//!
+//! A database is named by its URL, such as `http://localhost:8000`. The scheme may be left out, in
+//! which case HTTP is used.
+
+use database::{DatabaseBuilder, Protocol};
+use hash::{Hash, STRING_LEN};
+use error::Error;
+
+const SEPARATOR: &'static str = "::";
+
+/// Names a database
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DatabaseSpec {
+    pub protocol: Protocol,
+    /// The host, port and path of the database, as passed to `DatabaseBuilder::http`
+    pub host: String,
+}
+
+/// Names something within a database
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PathSpec {
+    /// A dataset, which stands for the value of its head when a value is expected
+    Dataset(String),
+    /// The value of the chunk with this hash
+    Hash(Hash),
+}
+
+/// Names a database, and possibly something within it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spec {
+    pub database: DatabaseSpec,
+    pub path: Option<PathSpec>,
+}
+
+impl DatabaseSpec {
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        let (protocol, host) = if let Some(host) = spec.strip_prefix("https://") {
+            (Protocol::Https, host)
+        } else if let Some(host) = spec.strip_prefix("http://") {
+            (Protocol::Http, host)
+        } else if spec.contains("://") {
+            return Err(Error::InvalidSpec(format!("{} is not an HTTP or HTTPS database", spec)));
+        } else {
+            (Protocol::Http, spec)
+        };
+        let host = host.trim_end_matches('/');
+        if host.is_empty() {
+            return Err(Error::InvalidSpec("The database is missing".to_string()));
+        }
+        Ok(DatabaseSpec{ protocol, host: host.to_string() })
+    }
+
+    /// Connects to the database, using a builder which may have been configured already
+    pub fn connect(&self, builder: DatabaseBuilder) -> Result<::database::http::Database, Error> {
+        match self.protocol {
+            Protocol::Http => builder.http(&self.host),
+            Protocol::Https => builder.https(&self.host),
+        }
+    }
+}
+
+impl PathSpec {
+    pub fn parse(path: &str) -> Result<Self, Error> {
+        if let Some(hash) = path.strip_prefix('#') {
+            if hash.len() != STRING_LEN {
+                return Err(Error::InvalidSpec(format!("{} is not a hash", hash)));
+            }
+            return Hash::from_string(hash)
+                .map(PathSpec::Hash)
+                .map_err(|_| Error::InvalidSpec(format!("{} is not a hash", hash)));
+        }
+        let valid = path.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '/');
+        if path.is_empty() || !valid {
+            return Err(Error::InvalidSpec(format!("{:?} is not a valid dataset name", path)));
+        }
+        Ok(PathSpec::Dataset(path.to_string()))
+    }
+}
+
+impl Spec {
+    pub fn parse(spec: &str) -> Result<Self, Error> {
+        match spec.find(SEPARATOR) {
+            Some(i) => Ok(Spec{
+                database: DatabaseSpec::parse(&spec[..i])?,
+                path: Some(PathSpec::parse(&spec[i + SEPARATOR.len()..])?),
+            }),
+            None => Ok(Spec{ database: DatabaseSpec::parse(spec)?, path: None }),
+        }
+    }
+
+    /// The name of the dataset, if this spec names one
+    pub fn dataset(&self) -> Option<&str> {
+        match self.path {
+            Some(PathSpec::Dataset(ref ds)) => Some(ds),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn databases() {
+        let spec = Spec::parse("localhost:8000").unwrap();
+        assert_eq!(spec.database, DatabaseSpec{ protocol: Protocol::Http, host: "localhost:8000".to_string() });
+        assert_eq!(spec.path, None);
+        let spec = Spec::parse("https://example.com/noms/").unwrap();
+        assert_eq!(spec.database, DatabaseSpec{ protocol: Protocol::Https, host: "example.com/noms".to_string() });
+        assert!(Spec::parse("ldb:///tmp/noms").is_err());
+        assert!(Spec::parse("::ds").is_err());
+    }
+
+    #[test]
+    fn paths() {
+        let spec = Spec::parse("http://localhost:8000::my-data/2017").unwrap();
+        assert_eq!(spec.dataset(), Some("my-data/2017"));
+        let hash = "0123456789abcdefghijklmnopqrstuv";
+        let spec = Spec::parse(&format!("localhost:8000::#{}", hash)).unwrap();
+        assert_eq!(spec.path, Some(PathSpec::Hash(Hash::from_string(hash).unwrap())));
+        assert!(Spec::parse("localhost:8000::#abc").is_err());
+        assert!(Spec::parse("localhost:8000::bad name").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! A cyclic polynomial rolling hash (buzhash) over a fixed window of bytes, as used by Go Noms to
+//! find chunk boundaries.
+//!
+//! This follows the algorithm of `github.com/kch42/buzhash`, which Go Noms uses, but that package
+//! maps each byte to a hash using its own table of random numbers, which is not available here.
+//! `BYTE_HASHES` is generated instead, so the hashes, and the chunk boundaries found with them,
+//! will only match those of Go Noms once it is replaced by that table: the `bytehash` array of
+//! `buzhash.go`, copied in order. That change should come with a test that a list encoded by Go
+//! Noms is split at the same items.
+
+/// The hash of each byte value, generated by SplitMix64 from a seed of 0
+const BYTE_HASHES: [u32; 256] = byte_hashes();
+
+const fn byte_hashes() -> [u32; 256] {
+    let mut hashes = [0; 256];
+    let mut state = 0u64;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        hashes[i] = ((z ^ (z >> 31)) >> 32) as u32;
+        i += 1;
+    }
+    hashes
+}
+
+/// The rolling hash of the last `window` bytes hashed.
+#[derive(Clone, Debug)]
+pub struct BuzHash {
+    state: u32,
+    window: Vec<u8>,
+    /// Where the next byte goes in the window, replacing the oldest one once the window is full
+    position: usize,
+    full: bool,
+}
+
+impl BuzHash {
+    pub fn new(window: usize) -> Self {
+        BuzHash{ state: 0, window: vec![0; window], position: 0, full: false }
+    }
+
+    /// Adds a byte to the window, removing the oldest one if it is full, and returns the new hash.
+    pub fn hash_byte(&mut self, byte: u8) -> u32 {
+        if self.position == self.window.len() {
+            self.full = true;
+            self.position = 0;
+        }
+        self.state = self.state.rotate_left(1);
+        if self.full {
+            let oldest = self.window[self.position];
+            self.state ^= BYTE_HASHES[oldest as usize].rotate_left(self.window.len() as u32 % 32);
+        }
+        self.window[self.position] = byte;
+        self.position += 1;
+        self.state ^= BYTE_HASHES[byte as usize];
+        self.state
+    }
+
+    pub fn sum(&self) -> u32 {
+        self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_depends_only_on_the_window() {
+        let bytes: Vec<u8> = (0..200u32).map(|i| (i * 7 % 251) as u8).collect();
+        let mut long = BuzHash::new(16);
+        for &b in &bytes {
+            long.hash_byte(b);
+        }
+        let mut short = BuzHash::new(16);
+        for &b in &bytes[bytes.len() - 16..] {
+            short.hash_byte(b);
+        }
+        assert_eq!(long.sum(), short.sum());
+        assert_ne!(BuzHash::new(16).hash_byte(1), BuzHash::new(16).hash_byte(2));
+    }
+}
@@ -1,2 +1,4 @@
+pub mod buzhash;
+pub mod date;
 pub mod frexp;
 pub mod varint;
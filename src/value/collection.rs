@@ -9,19 +9,19 @@ pub(crate) trait Collection<'a, V: FromNoms<'a>> {
     fn database(&self) -> &'a ChunkStore;
     fn resolve(&self, h: &MetaTuple<'a>) -> Result<V, Error> {
         self.database()
-            .get(h.reference.hash())
+            .get(h.reference.target_hash())
             .map(|v| v.export().transform())
     }
     fn resolve_all(&self, h: &Vec<MetaTuple<'a>>) -> Result<Vec<V>, Error> {
         self.database()
             .get_many(
                 h   .iter()
-                    .map(|t| t.reference.hash())
+                    .map(|t| t.reference.target_hash())
                     .collect()
             )
             .map(|mut m|
                 h   .into_iter()
-                    .map(move |mt| m.remove(&mt.reference.hash()).unwrap().export().transform())
+                    .map(move |mt| m.remove(&mt.reference.target_hash()).unwrap().export().transform())
                     .collect()
             )
     }
@@ -1,15 +1,17 @@
 //! Implements the Commit type, used as the value of a dataset in the database.
-use super::{Ref, NomsValue, Value, IntoNoms, FromNoms, NomsSet, NomsStruct, Empty};
+use super::{Ref, NomsValue, Value, IntoNoms, FromNoms, NomsSet, NomsStruct, Empty, Type, Kind};
 use chunk::Chunk;
 use std::collections::HashMap;
 
+pub(crate) const COMMIT_NAME: &'static str = "Commit";
+
 /// A commit from the Noms database. The value of every dataset is a commit, containing the actual
 /// data from the database, along with additional arbitrary metadata and the set of parent commits.
 #[derive(Clone, Debug)]
 pub struct Commit<'a, M = Empty, V = NomsValue<'a>>
 where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
     meta: M,
-    parents: NomsSet<'a, Ref<'a>>,
+    parents: NomsSet<'a, Ref<'a, Commit<'a, M, V>>>,
     value: V,
 }
 
@@ -17,7 +19,7 @@ impl<'a, M, V> Commit<'a, M, V>
 where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
     pub fn value(&self) -> &V { &self.value }
     pub fn meta(&self) -> &M { &self.meta }
-    pub fn parents(&self) -> &NomsSet<Ref> { &self.parents }
+    pub fn parents(&self) -> &NomsSet<'a, Ref<'a, Commit<'a, M, V>>> { &self.parents }
     pub fn into_value(self) -> V { self.value }
 }
 
@@ -26,6 +28,7 @@ where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
     fn into_noms(&self) -> Vec<u8> {
         unimplemented!();
     }
+    fn noms_type() -> Type { <Self as NomsStruct>::noms_type() }
 }
 
 impl<'a, M, V> FromNoms<'a> for Commit<'a, M, V>
@@ -37,7 +40,11 @@ where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
 
 impl<'a, M, V> NomsStruct<'a> for Commit<'a, M, V>
 where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
-    const NAME: &'static str = "Commit";
+    const NAME: &'static str = COMMIT_NAME;
+
+    fn noms_type() -> Type {
+        commit_type(<M as NomsStruct<'a>>::noms_type(), V::noms_type())
+    }
 
     fn from_prop_list(mut props: HashMap<String, NomsValue<'a>>) -> Option<Self> {
         Some(
@@ -53,3 +60,17 @@ where M: IntoNoms + FromNoms<'a> + NomsStruct<'a>, V: IntoNoms + FromNoms<'a> {
         HashMap::new()
     }
 }
+
+/// The type of a commit with the given types of metadata and value. The parents refer back to the
+/// commit type itself, as in the Go implementation, where `parents` is a `Set<Ref<Cycle<Commit>>>`.
+pub(crate) fn commit_type(meta: Type, value: Type) -> Type {
+    let parents = Type::compound(Kind::Set, vec![
+        Type::compound(Kind::Ref, vec![Type::cycle(COMMIT_NAME.to_string())]),
+    ]);
+    Type::structure(
+        COMMIT_NAME.to_string(),
+        vec!["meta".to_string(), "parents".to_string(), "value".to_string()],
+        vec![meta, parents, value],
+        vec![false, false, false],
+    )
+}
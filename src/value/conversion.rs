@@ -1,5 +1,5 @@
 //! Defines some conversions from basic Noms types to standard Rust types
-use super::{varint, Value, Kind};
+use super::{varint, Value, Kind, Type};
 use chunk::Chunk;
 use util::frexp::frexp;
 
@@ -7,6 +7,9 @@ use util::frexp::frexp;
 pub trait IntoNoms: ::std::fmt::Debug + Clone {
     /// Produces a unique binary representation of this value
     fn into_noms(&self) -> Vec<u8>;
+    /// The Noms type of every value of this type. Types which do not know what they hold are
+    /// described as `Value`.
+    fn noms_type() -> Type { Type::primitive(Kind::Value) }
 }
 /// For converting from Noms binary data to Rust types
 pub trait FromNoms<'a>: ::std::fmt::Debug + Clone {
@@ -55,6 +58,7 @@ impl IntoNoms for u64 {
         bytes.extend(varint::encode_i64(0i64));
         bytes
     }
+    fn noms_type() -> Type { Type::primitive(Kind::Number) }
 }
 impl<'a> FromNoms<'a> for u64 {
     fn from_noms(chunk: &Chunk<'a>) -> Self {
@@ -69,6 +73,7 @@ impl IntoNoms for i64 {
         bytes.extend(varint::encode_i64(0i64));
         bytes
     }
+    fn noms_type() -> Type { Type::primitive(Kind::Number) }
 }
 impl<'a> FromNoms<'a> for i64 {
     fn from_noms(chunk: &Chunk<'a>) -> Self {
@@ -88,6 +93,7 @@ impl IntoNoms for f64 {
         bytes.extend(varint::encode_i64(e));
         bytes
     }
+    fn noms_type() -> Type { Type::primitive(Kind::Number) }
 }
 impl<'a> FromNoms<'a> for f64 {
     fn from_noms(chunk: &Chunk<'a>) -> Self {
@@ -101,6 +107,7 @@ impl IntoNoms for bool {
         bytes.push(if *self { 1 } else { 0 });
         bytes
     }
+    fn noms_type() -> Type { Type::primitive(Kind::Boolean) }
 }
 impl<'a> FromNoms<'a> for bool {
     fn from_noms(chunk: &Chunk<'a>) -> Self {
@@ -115,6 +122,7 @@ impl IntoNoms for String {
         bytes.extend_from_slice(self.as_bytes());
         bytes
     }
+    fn noms_type() -> Type { Type::primitive(Kind::String) }
 }
 impl<'a> FromNoms<'a> for String {
     fn from_noms(chunk: &Chunk<'a>) -> Self {
@@ -126,4 +134,6 @@ impl<'a> IntoNoms for &'a str {
     fn into_noms(&self) -> Vec<u8> {
         self.to_string().into_noms()
     }
+    fn noms_type() -> Type { Type::primitive(Kind::String) }
 }
+
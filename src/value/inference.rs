@@ -0,0 +1,116 @@
+//! Works out the Noms type of a value from its encoding, following the rules of Go Noms' `TypeOf`.
+
+use chunk::ChunkReader;
+use error::Error;
+use super::{Kind, Type};
+
+/// The type of the encoded value. The items of a chunked collection are not read, as the refs to
+/// its chunks already hold their types, so no database is needed.
+///
+/// The element types of a list, set or map are the simplified union of the types of its items,
+/// and a struct's type has a field, which is not optional, for each of its fields. Fails if the
+/// bytes hold a kind which no value can be of, such as a union.
+pub(crate) fn type_of_encoded(bytes: &Vec<u8>) -> Result<Type, Error> {
+    let reader = ChunkReader::new(None, bytes);
+    let kind = reader.read_kind();
+    Ok(match kind {
+        Kind::Boolean | Kind::Number | Kind::String | Kind::Blob | Kind::Type => Type::primitive(kind),
+        Kind::Ref => {
+            reader.read_hash();
+            Type::compound(Kind::Ref, vec![reader.read_type()])
+        }
+        Kind::Struct => {
+            let name = reader.read_utf8();
+            let count = reader.read_varint() as usize;
+            let mut keys = Vec::with_capacity(count);
+            let mut types = Vec::with_capacity(count);
+            for _ in 0..count {
+                keys.push(reader.read_utf8());
+                types.push(type_of_encoded(&reader.read_item())?);
+            }
+            Type::structure(name, keys, types, vec![false; count])
+        }
+        Kind::List | Kind::Set | Kind::Map => {
+            let level = reader.read_varint();
+            let count = reader.read_varint();
+            if level > 0 {
+                let types = (0..count).map(|_| metatuple_type(&reader)).collect::<Result<_, _>>()?;
+                return Ok(Type::simplified_union(types));
+            }
+            let columns = if kind == Kind::Map { 2 } else { 1 };
+            let mut types = vec![vec![]; columns];
+            for _ in 0..count {
+                for column in types.iter_mut() {
+                    column.push(type_of_encoded(&reader.read_item())?);
+                }
+            }
+            Type::compound(kind, types.into_iter().map(Type::simplified_union).collect())
+        }
+        kind => return Err(Error::ConversionError(format!("A value cannot be of kind {:?}", kind))),
+    })
+}
+
+/// Reads a meta tuple, returning the type of the chunk it refers to.
+fn metatuple_type(reader: &ChunkReader) -> Result<Type, Error> {
+    match reader.read_kind() {
+        Kind::Ref => {}
+        kind => return Err(Error::ConversionError(format!("A meta tuple starts with a ref, not a {:?}", kind))),
+    }
+    reader.read_hash();
+    let chunk_type = reader.read_type();
+    reader.read_varint();
+    if reader.peek_kind() == Kind::Hash {
+        reader.read_kind();
+        reader.read_hash();
+    } else {
+        reader.read_item();
+    }
+    reader.read_varint();
+    Ok(chunk_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use value::{IntoNoms, encode_leaf, encode_struct};
+    use std::collections::HashMap;
+
+    fn list(items: Vec<Vec<u8>>) -> Vec<u8> {
+        encode_leaf(Kind::List, items.len(), &items.concat())
+    }
+    fn number() -> Type { Type::primitive(Kind::Number) }
+    fn string() -> Type { Type::primitive(Kind::String) }
+
+    #[test]
+    fn lists() {
+        let items = list(vec![1u64.into_noms(), "a".into_noms(), 2u64.into_noms()]);
+        assert_eq!(type_of_encoded(&items).unwrap(), Type::compound(Kind::List, vec![Type::union(vec![string(), number()])]));
+        let items = list(vec![1u64.into_noms()]);
+        assert_eq!(type_of_encoded(&items).unwrap(), Type::compound(Kind::List, vec![number()]));
+        let empty = list(vec![]);
+        assert_eq!(type_of_encoded(&empty).unwrap(), Type::compound(Kind::List, vec![Type::union(vec![])]));
+    }
+
+    #[test]
+    fn structs() {
+        let row = |props: Vec<(&str, Vec<u8>)>| encode_struct("Row", props.into_iter().map(|(k, v)| (k.to_string(), v)).collect::<HashMap<_, _>>());
+        let items = list(vec![
+            row(vec![("a", 1u64.into_noms()), ("b", "x".into_noms())]),
+            row(vec![("a", "y".into_noms())]),
+        ]);
+        let merged = Type::structure(
+            "Row".to_string(),
+            vec!["a".to_string(), "b".to_string()],
+            vec![Type::union(vec![string(), number()]), string()],
+            vec![false, true],
+        );
+        assert_eq!(type_of_encoded(&items).unwrap(), Type::compound(Kind::List, vec![merged]));
+    }
+
+    #[test]
+    fn invalid_kinds() {
+        for &kind in &[Kind::Value, Kind::Cycle, Kind::Union, Kind::Hash] {
+            assert!(type_of_encoded(&vec![kind as u8, 0]).is_err(), "{:?} has a type", kind);
+        }
+    }
+}
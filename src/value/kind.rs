@@ -1,13 +1,14 @@
 //! Defines all the Noms types (kinds), and the Noms Type type
 use super::{IntoNoms, FromNoms, Value, varint};
 use chunk::Chunk;
+use hash::hash;
 
 /// A C-Style enum, which must continue to be in the same order as the NomsKind enum in the
 /// official Noms Go package to ensure proper deserialization.
 ///
 /// See [noms/go/types/noms_kind.go](https://github.com/attic-labs/noms/go/types/noms_kind.go)
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub(crate) enum Kind {
+pub enum Kind {
     Boolean,
     Number,
     String,
@@ -34,6 +35,14 @@ impl<'a> FromNoms<'a> for Kind {
     }
 }
 impl Kind {
+    /// The kind with the given byte, or `None` if there is no such kind.
+    pub fn from_u8(byte: u8) -> Option<Kind> {
+        use self::Kind::*;
+        [Boolean, Number, String, Blob, Value, List, Map, Ref, Set, Struct, Cycle, Type, Union, Hash]
+            .get(byte as usize)
+            .cloned()
+    }
+
     pub fn is_primitive(self) -> bool {
         use self::Kind::*;
         match self {
@@ -54,18 +63,20 @@ enum TypeDesc {
         keys: Vec<String>,
         types: Vec<Type>,
         optional: Vec<bool>,
-    }
+    },
+    Cycle(String),
 }
 impl TypeDesc {
-    fn to_bytes(&self) -> Vec<u8> {
+    fn to_bytes(&self, kind: Kind) -> Vec<u8> {
         match self {
             &TypeDesc::Primitive => vec![],
             &TypeDesc::Compound(ref types) => {
-                let mut bytes = if types.len() > 1 {
+                // only unions have a variable number of element types
+                let mut bytes = if kind == Kind::Union {
                     varint::encode_u64(types.len() as u64)
                 } else { vec![] };
                 for t in types {
-                    bytes.extend(t.desc.to_bytes());
+                    bytes.extend(t.to_bytes());
                 }
                 bytes
             }
@@ -85,6 +96,11 @@ impl TypeDesc {
                 }
                 bytes
             }
+            &TypeDesc::Cycle(ref name) => {
+                let mut bytes = varint::encode_u64(name.len() as u64);
+                bytes.extend(name.as_bytes());
+                bytes
+            }
         }
     }
 }
@@ -103,43 +119,196 @@ impl ::std::hash::Hash for Type {
     }
 }
 
+/// A field of a struct type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field<'t> {
+    pub name: &'t str,
+    pub field_type: &'t Type,
+    pub optional: bool,
+}
+
 impl Type {
-    pub(crate) fn primitive(kind: Kind) -> Self {
+    /// Describes a value of a kind which has no element types, such as `Number` or `Value`.
+    pub fn primitive(kind: Kind) -> Self {
         Type {
             kind,
             desc: TypeDesc::Primitive,
         }
     }
 
-    pub(crate) fn compound(kind: Kind, types: Vec<Type>) -> Self {
+    /// Describes a collection, ref or union holding values of the given types. Lists, sets and refs
+    /// have one element type, maps have two (of their keys and their values), and unions may have
+    /// any number.
+    pub fn compound(kind: Kind, types: Vec<Type>) -> Self {
         Type {
             kind,
             desc: TypeDesc::Compound(types),
         }
     }
 
-    pub(crate) fn structure(name: String, keys: Vec<String>, types: Vec<Type>, optional: Vec<bool>) -> Self {
+    /// Describes a struct with the given name and fields, each of which has a type and whether it
+    /// is optional. The fields are sorted by their keys, as Noms encodes them.
+    pub fn structure(name: String, keys: Vec<String>, types: Vec<Type>, optional: Vec<bool>) -> Self {
         assert_eq!(keys.len(), types.len());
         assert_eq!(keys.len(), optional.len());
+        let mut fields: Vec<_> = keys.into_iter().zip(types).zip(optional).collect();
+        fields.sort_by(|a, b| (a.0).0.cmp(&(b.0).0));
+        let mut keys = vec![];
+        let mut types = vec![];
+        let mut optional = vec![];
+        for ((key, t), o) in fields {
+            keys.push(key);
+            types.push(t);
+            optional.push(o);
+        }
         Type {
             kind: Kind::Struct,
             desc: TypeDesc::Struct { name, keys, types, optional },
         }
     }
 
-    fn to_bytes(&self) -> Vec<u8> {
+    /// Describes a value which may be of any of the given types, in the order given. Use
+    /// `simplified_union` for the union Go Noms would make of them.
+    pub fn union(types: Vec<Type>) -> Self {
+        Type::compound(Kind::Union, types)
+    }
+
+    /// Refers back to the enclosing struct type with the given name, for recursive types.
+    pub fn cycle(name: String) -> Self {
+        Type {
+            kind: Kind::Cycle,
+            desc: TypeDesc::Cycle(name),
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// The element types of a collection or ref, or the types of a union. Other types have none.
+    pub fn element_types(&self) -> &[Type] {
+        match self.desc {
+            TypeDesc::Compound(ref types) => types,
+            _ => &[],
+        }
+    }
+
+    /// The name of a struct, or of the struct which a cycle refers back to
+    pub fn name(&self) -> Option<&str> {
+        match self.desc {
+            TypeDesc::Struct{ ref name, .. } | TypeDesc::Cycle(ref name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// The fields of a struct, ordered by name. Other types have none.
+    pub fn fields<'t>(&'t self) -> Vec<Field<'t>> {
+        match self.desc {
+            TypeDesc::Struct{ ref keys, ref types, ref optional, .. } => keys.iter()
+                .zip(types)
+                .zip(optional)
+                .map(|((name, field_type), &optional)| Field{ name, field_type, optional })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The union of the types, simplified as Go Noms does: unions within it are flattened,
+    /// duplicates are removed, and collections or refs of the same kind become one whose element
+    /// types are the unions of theirs. Structs with the same name are merged, and fields which are
+    /// missing from some of them become optional. A union of one type is that type.
+    ///
+    /// Like Go Noms, the types of a union are ordered by their hashes, as values of kind `Type`.
+    pub fn simplified_union(types: Vec<Type>) -> Type {
+        let mut flat = vec![];
+        flatten(types, &mut flat);
+        let mut merged: Vec<Type> = vec![];
+        for t in flat {
+            let same = merged.iter().position(|m| m.kind == t.kind && match (&m.desc, &t.desc) {
+                (&TypeDesc::Compound(_), &TypeDesc::Compound(_)) => true,
+                (&TypeDesc::Struct{ name: ref a, .. }, &TypeDesc::Struct{ name: ref b, .. }) => a == b,
+                (a, b) => a == b,
+            });
+            match same {
+                Some(i) => {
+                    let m = merged.remove(i);
+                    merged.insert(i, merge(m, t));
+                }
+                None => merged.push(t),
+            }
+        }
+        if merged.len() == 1 {
+            return merged.remove(0);
+        }
+        merged.sort_by_key(|t| hash(&t.into_noms()));
+        Type::union(merged)
+    }
+
+    /// Encodes the type without the leading `Type` kind, as it is written within a ref.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![self.kind as u8];
-        bytes.extend(self.desc.to_bytes());
+        bytes.extend(self.desc.to_bytes(self.kind));
         bytes
     }
 }
 
+fn flatten(types: Vec<Type>, flat: &mut Vec<Type>) {
+    for t in types {
+        match t {
+            Type{ kind: Kind::Union, desc: TypeDesc::Compound(types) } => flatten(types, flat),
+            t => flat.push(t),
+        }
+    }
+}
+
+/// Merges two types of the same kind, which are not unions.
+fn merge(a: Type, b: Type) -> Type {
+    match (a.desc, b.desc) {
+        (TypeDesc::Compound(a_types), TypeDesc::Compound(b_types)) => {
+            let types = a_types.into_iter()
+                .zip(b_types)
+                .map(|(a, b)| Type::simplified_union(vec![a, b]))
+                .collect();
+            Type::compound(a.kind, types)
+        }
+        (TypeDesc::Struct{ name, keys, types, optional }, TypeDesc::Struct{ keys: b_keys, types: b_types, optional: b_optional, .. }) => {
+            let mut fields: Vec<(String, Type, bool)> = keys.into_iter()
+                .zip(types)
+                .zip(optional)
+                .map(|((key, t), o)| { let missing = !b_keys.contains(&key); (key, t, o || missing) })
+                .collect();
+            for ((key, t), o) in b_keys.into_iter().zip(b_types).zip(b_optional) {
+                match fields.iter().position(|field| field.0 == key) {
+                    Some(i) => {
+                        let (key, a_type, a_optional) = fields.remove(i);
+                        fields.insert(i, (key, Type::simplified_union(vec![a_type, t]), a_optional || o));
+                    }
+                    None => fields.push((key, t, true)),
+                }
+            }
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            let (keys, types, optional) = fields.into_iter().fold(
+                (vec![], vec![], vec![]),
+                |(mut keys, mut types, mut optional), (key, t, o)| {
+                    keys.push(key);
+                    types.push(t);
+                    optional.push(o);
+                    (keys, types, optional)
+                },
+            );
+            Type::structure(name, keys, types, optional)
+        }
+        (desc, _) => Type{ kind: a.kind, desc },
+    }
+}
+
 impl IntoNoms for Type {
     fn into_noms(&self) -> Vec<u8> {
         let mut bytes = Kind::Type.into_noms();
         bytes.extend(self.to_bytes());
         bytes
     }
+    fn noms_type() -> Type { Type::primitive(Kind::Type) }
 }
 
 impl<'a> FromNoms<'a> for Type {
@@ -147,3 +316,63 @@ impl<'a> FromNoms<'a> for Type {
         Value::from_noms(chunk).to_type().unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use value::{Commit, Empty, NomsStruct};
+
+    #[test]
+    fn compound_types_round_trip() {
+        let types = vec![
+            Type::compound(Kind::Map, vec![Type::primitive(Kind::String), Type::primitive(Kind::Number)]),
+            Type::compound(Kind::Union, vec![Type::primitive(Kind::String), Type::primitive(Kind::Boolean)]),
+            <Commit<Empty, String> as NomsStruct>::noms_type(),
+        ];
+        for t in types {
+            assert_eq!(Type::from_noms(&Chunk::maybe(None, t.into_noms())), t);
+        }
+    }
+
+    #[test]
+    fn map_type_encoding() {
+        let t = Type::compound(Kind::Map, vec![Type::primitive(Kind::String), Type::primitive(Kind::Number)]);
+        assert_eq!(t.into_noms(), vec![Kind::Type as u8, Kind::Map as u8, Kind::String as u8, Kind::Number as u8]);
+    }
+
+    #[test]
+    fn unions_are_ordered_by_hash() {
+        let types = vec![
+            Type::primitive(Kind::Number),
+            Type::primitive(Kind::String),
+            Type::primitive(Kind::Boolean),
+            Type::compound(Kind::List, vec![Type::primitive(Kind::Number)]),
+        ];
+        let union = Type::simplified_union(types.clone());
+        let hashes: Vec<_> = union.element_types().iter().map(|t| hash(&t.into_noms())).collect();
+        let mut sorted = hashes.clone();
+        sorted.sort();
+        assert_eq!(hashes, sorted);
+        assert_eq!(Type::simplified_union(types.into_iter().rev().collect()), union);
+    }
+
+    #[test]
+    fn struct_fields_are_sorted() {
+        let number = Type::primitive(Kind::Number);
+        let string = Type::primitive(Kind::String);
+        let unsorted = Type::structure(
+            "Row".to_string(),
+            vec!["b".to_string(), "a".to_string()],
+            vec![number.clone(), string.clone()],
+            vec![true, false],
+        );
+        let sorted = Type::structure(
+            "Row".to_string(),
+            vec!["a".to_string(), "b".to_string()],
+            vec![string, number],
+            vec![false, true],
+        );
+        assert_eq!(unsorted, sorted);
+        assert_eq!(unsorted.into_noms(), sorted.into_noms());
+    }
+}
@@ -6,23 +6,33 @@ mod sequence;
 mod kind;
 mod structure;
 mod collection;
+mod printer;
+mod inference;
+mod syntax;
+mod subtype;
 
-pub use self::kind::Type;
+pub use self::kind::{Type, Kind, Field};
 pub use self::reference::Ref;
 pub use self::commit::Commit;
-pub use self::sequence::{NomsMap, NomsSet, NomsList};
-pub use self::structure::{NomsStruct, Empty};
+pub use self::sequence::{NomsMap, NomsSet, NomsList, ListIter, SetIter, MapIter, Merge, ListEditor, MapEditor, SetEditor};
+pub use self::structure::{NomsStruct, Empty, encode_struct};
 pub use self::conversion::{IntoNoms, FromNoms};
+pub use self::printer::Printer;
 
-pub(crate) use self::sequence::{MetaTuple, OrderedKey, Map, Set, List};
-pub(crate) use self::kind::Kind;
+pub(crate) use self::sequence::{MetaTuple, OrderedKey, Map, Set, List, encode_leaf, encode_map_leaf, encode_set_leaf};
+pub(crate) use self::sequence::{write_list, write_map, write_blob};
+pub(crate) use self::reference::{encode_ref, height_of};
+pub(crate) use self::inference::type_of_encoded;
 pub(crate) use self::collection::Collection;
 pub(crate) use self::structure::Struct;
+pub(crate) use self::commit::{commit_type, COMMIT_NAME};
 
 use util::varint;
 use chunk::Chunk;
 use hash::{hash, Hash};
+use error::Error;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
 pub struct NomsValue<'a>(Value<'a>);
@@ -39,6 +49,28 @@ impl<'a> NomsValue<'a> {
     pub fn transform<T: FromNoms<'a>>(self) -> T {
         T::from_noms(&self.import().to_chunk())
     }
+
+    /// The string this value holds, if it is one. Use `Display` to print any value.
+    pub fn into_string(self) -> Option<String> {
+        self.import().to_string()
+    }
+    /// The integer this value holds, if it is a number.
+    pub fn into_i64(self) -> Option<i64> {
+        self.import().to_i64()
+    }
+    /// The Noms type of this value, as Go Noms' `TypeOf` would give it. Fails if the value is not
+    /// encoded correctly.
+    pub fn type_of(&self) -> Result<Type, Error> {
+        type_of_encoded(&self.into_noms())
+    }
+    /// Splits a struct into its name and props, for types that may be decoded from one of several
+    /// kinds of structs. Returns `None` if this is not a struct.
+    pub fn to_struct_props(self) -> Option<(String, HashMap<String, NomsValue<'a>>)> {
+        match self.import().compile() {
+            Value::Struct(Struct{ name, props }) => Some((name, props)),
+            _ => None,
+        }
+    }
 }
 
 impl<'a> IntoNoms for NomsValue<'a> {
@@ -52,6 +84,14 @@ impl<'a> FromNoms<'a> for NomsValue<'a> {
     }
 }
 
+/// Formats a number, which is stored as `i * 2^e`, as an integer where it is one.
+pub(crate) fn number_to_string(i: i64, e: i64) -> String {
+    match 2i64.checked_pow(e as u32).and_then(|p| i.checked_mul(p)) {
+        Some(n) if e >= 0 => n.to_string(),
+        _ => (i as f64 * 2f64.powi(e as i32)).to_string(),
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub(crate) enum Value<'a> {
     Boolean(bool),
@@ -280,7 +320,7 @@ impl<'a> PartialOrd<Self> for Value<'a> {
 impl<'a> ::std::hash::Hash for Value<'a> {
     fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
         match self {
-            &Value::Ref(ref r) => { r.hash().hash(state); } // TODO: is this right?
+            &Value::Ref(ref r) => { r.target_hash().hash(state); } // TODO: is this right?
             _ => { self.compute_hash().hash(state) }
         }
     }
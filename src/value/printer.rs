@@ -0,0 +1,338 @@
+//! Prints values in the syntax used by `noms show`, so that they can be read by people rather than
+//! programs: structs as `Row { name: "Ann" }`, lists as `[1, 2]`, maps as `{"a": 1}`, sets as
+//! `set {1, 2}` and refs as `#<hash>`.
+//!
+//! Collections are read one chunk at a time as they are printed, so only the chunks holding the
+//! items which are shown are fetched.
+
+use std::fmt;
+use std::io::Write;
+use chunk::ChunkReader;
+use database::ChunkStore;
+use hash::Hash;
+use error::Error;
+use super::{NomsValue, Kind, number_to_string};
+
+/// The number of items of each collection that are shown when a value is formatted with `Display`
+const DISPLAY_ITEMS: usize = 100;
+const INDENT: &'static str = "  ";
+
+const STRING_COLOR: &'static str = "32";
+const NUMBER_COLOR: &'static str = "33";
+const REF_COLOR: &'static str = "34";
+const NAME_COLOR: &'static str = "1";
+
+/// Configures how values are printed. By default, values are printed in full on one line,
+/// without colour.
+#[derive(Clone, Debug, Default)]
+pub struct Printer {
+    max_depth: Option<usize>,
+    max_items: Option<usize>,
+    color: bool,
+    multiline: bool,
+}
+
+/// How the entries of a struct or collection are enclosed. Struct fields are padded with spaces
+/// on one line, like Rust's `Debug`.
+struct Delimiters {
+    open: &'static str,
+    close: &'static str,
+    pad: bool,
+}
+
+const STRUCT: Delimiters = Delimiters{ open: "{", close: "}", pad: true };
+const LIST: Delimiters = Delimiters{ open: "[", close: "]", pad: false };
+const SET: Delimiters = Delimiters{ open: "set {", close: "}", pad: false };
+const MAP: Delimiters = Delimiters{ open: "{", close: "}", pad: false };
+
+/// Something held by a struct or collection, as encoded values
+enum Entry {
+    Field(String, Vec<u8>),
+    Item(Vec<u8>),
+    Pair(Vec<u8>, Vec<u8>),
+}
+
+impl Printer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prints structs and collections nested more than `depth` levels deep as `{...}`
+    pub fn max_depth(self, depth: usize) -> Self {
+        Self{ max_depth: Some(depth), ..self }
+    }
+
+    /// Prints at most `items` items of each collection, followed by the number left out
+    pub fn max_items(self, items: usize) -> Self {
+        Self{ max_items: Some(items), ..self }
+    }
+
+    /// Highlights values with ANSI colour codes, for printing to a terminal
+    pub fn color(self, color: bool) -> Self {
+        Self{ color, ..self }
+    }
+
+    /// Prints each field and item of a struct or collection on its own line, indented
+    pub fn multiline(self, multiline: bool) -> Self {
+        Self{ multiline, ..self }
+    }
+
+    pub fn print<W: Write>(&self, value: &NomsValue, mut writer: W) -> Result<(), Error> {
+        let chunk = value.clone().import().to_chunk();
+        self.print_value(&mut writer, chunk.database(), chunk.data(), 0)
+    }
+
+    /// Prints a value to a string
+    pub fn format(&self, value: &NomsValue) -> Result<String, Error> {
+        let mut bytes = vec![];
+        self.print(value, &mut bytes)?;
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+
+    fn print_value<W: Write>(&self, w: &mut W, database: Option<&ChunkStore>, bytes: &Vec<u8>, depth: usize) -> Result<(), Error> {
+        let reader = ChunkReader::new(database, bytes);
+        match reader.read_kind() {
+            Kind::Boolean => self.styled(w, NUMBER_COLOR, &(reader.read_u8() == 1).to_string()),
+            Kind::Number => {
+                let (i, e) = ChunkReader::new(database, bytes).read_number();
+                self.styled(w, NUMBER_COLOR, &number_to_string(i, e))
+            }
+            Kind::String => self.styled(w, STRING_COLOR, &format!("{:?}", reader.read_utf8())),
+            Kind::Ref => self.styled(w, REF_COLOR, &format!("#{}", reader.read_hash().to_string())),
+            Kind::Type => {
+                reader.read_kind();
+                write!(w, "{}", reader.read_type())?;
+                Ok(())
+            }
+            Kind::Blob => {
+                let items = Items::new(database, bytes, 0)?;
+                write!(w, "blob ({} bytes)", separated(items.len))?;
+                Ok(())
+            }
+            Kind::Struct => {
+                let name = reader.read_utf8();
+                let count = reader.read_varint() as usize;
+                let mut fields = Vec::with_capacity(count);
+                for _ in 0..count {
+                    fields.push(Ok(Entry::Field(reader.read_utf8(), reader.read_item())));
+                }
+                if name.is_empty() {
+                    write!(w, "struct ")?;
+                } else {
+                    self.styled(w, NAME_COLOR, &name)?;
+                    write!(w, " ")?;
+                }
+                self.print_entries(w, database, depth, STRUCT, count as u64, fields.into_iter())
+            }
+            kind @ Kind::List | kind @ Kind::Set | kind @ Kind::Map => {
+                let (delimiters, per_item) = match kind {
+                    Kind::List => (LIST, 1),
+                    Kind::Set => (SET, 1),
+                    _ => (MAP, 2),
+                };
+                let items = Items::new(database, bytes, per_item)?;
+                let len = items.len;
+                let entries = items.map(|item| item.map(|mut values| match values.len() {
+                    1 => Entry::Item(values.remove(0)),
+                    _ => {
+                        let value = values.remove(1);
+                        Entry::Pair(values.remove(0), value)
+                    }
+                }));
+                self.print_entries(w, database, depth, delimiters, len, entries)
+            }
+            kind => Err(Error::ConversionError(format!("Values of kind {:?} cannot be printed", kind))),
+        }
+    }
+
+    /// Prints the fields of a struct or the items of a collection, between the delimiters.
+    fn print_entries<W, I>(&self, w: &mut W, database: Option<&ChunkStore>, depth: usize, delimiters: Delimiters, len: u64, entries: I) -> Result<(), Error>
+    where W: Write, I: Iterator<Item = Result<Entry, Error>> {
+        let Delimiters{ open, close, pad } = delimiters;
+        if len == 0 {
+            write!(w, "{}{}", open, close)?;
+            return Ok(());
+        }
+        if self.max_depth.is_some_and(|max| depth >= max) {
+            write!(w, "{}...{}", open, close)?;
+            return Ok(());
+        }
+        let shown = self.max_items.map_or(len, |max| len.min(max as u64));
+        let inner = INDENT.repeat(depth + 1);
+        write!(w, "{}", open)?;
+        let mut printed = 0;
+        for entry in entries.take(shown as usize) {
+            if self.multiline {
+                write!(w, "\n{}", inner)?;
+            } else if printed > 0 {
+                write!(w, ", ")?;
+            } else if pad {
+                write!(w, " ")?;
+            }
+            match entry? {
+                Entry::Field(name, value) => {
+                    write!(w, "{}: ", name)?;
+                    self.print_value(w, database, &value, depth + 1)?;
+                }
+                Entry::Item(value) => self.print_value(w, database, &value, depth + 1)?,
+                Entry::Pair(key, value) => {
+                    self.print_value(w, database, &key, depth + 1)?;
+                    write!(w, ": ")?;
+                    self.print_value(w, database, &value, depth + 1)?;
+                }
+            }
+            if self.multiline {
+                write!(w, ",")?;
+            }
+            printed += 1;
+        }
+        if len > printed {
+            let more = format!("... {} more", separated(len - printed));
+            if self.multiline {
+                write!(w, "\n{}{}", inner, more)?;
+            } else {
+                write!(w, ", {}", more)?;
+            }
+        }
+        if self.multiline {
+            write!(w, "\n{}{}", INDENT.repeat(depth), close)?;
+        } else if pad {
+            write!(w, " {}", close)?;
+        } else {
+            write!(w, "{}", close)?;
+        }
+        Ok(())
+    }
+
+    fn styled<W: Write>(&self, w: &mut W, color: &str, text: &str) -> Result<(), Error> {
+        if self.color {
+            write!(w, "\x1b[{}m{}\x1b[0m", color, text)?;
+        } else {
+            write!(w, "{}", text)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> NomsValue<'a> {
+    /// Prints the value as `Display` does, failing if a chunk of it cannot be read.
+    pub fn print<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        Printer::new().max_items(DISPLAY_ITEMS).print(self, writer)
+    }
+}
+
+/// Formats the value on one line, or on several with `{:#}`. At most 100 items of each collection
+/// are shown. If a chunk of the value cannot be read, what was printed before it is followed by
+/// the error, in angle brackets, rather than failing: use `print` to find out whether it did.
+impl<'a> fmt::Display for NomsValue<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut bytes = vec![];
+        let result = Printer::new()
+            .max_items(DISPLAY_ITEMS)
+            .multiline(f.alternate())
+            .print(self, &mut bytes);
+        f.write_str(&String::from_utf8_lossy(&bytes))?;
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => write!(f, "<{}>", e),
+        }
+    }
+}
+
+/// Iterates over the items of a sequence, which are read from the database one chunk at a time.
+/// Each item is made up of `per_item` encoded values.
+struct Items<'a> {
+    database: Option<&'a ChunkStore>,
+    per_item: usize,
+    /// The total number of items
+    len: u64,
+    /// The chunks which have not been reached yet, with the next one last
+    pending: Vec<Hash>,
+    reader: ChunkReader<'a>,
+    /// The number of items left in the current chunk
+    left: u64,
+}
+
+impl<'a> Items<'a> {
+    fn new(database: Option<&'a ChunkStore>, bytes: &Vec<u8>, per_item: usize) -> Result<Self, Error> {
+        let mut items = Items{
+            database,
+            per_item,
+            len: 0,
+            pending: vec![],
+            reader: ChunkReader::new(database, bytes),
+            left: 0,
+        };
+        items.len = items.start_chunk()?;
+        Ok(items)
+    }
+
+    /// Reads the header of the current chunk. If it is a leaf, its items are read next, otherwise
+    /// the chunks it refers to are. Returns the number of items it holds.
+    fn start_chunk(&mut self) -> Result<u64, Error> {
+        self.reader.read_kind();
+        let level = self.reader.read_varint();
+        let count = self.reader.read_varint();
+        if level == 0 {
+            self.left = count;
+            return Ok(count);
+        }
+        let tuples: Vec<(Hash, u64)> = (0..count).map(|_| self.reader.read_metatuple_leaves()).collect();
+        self.pending.extend(tuples.iter().rev().map(|&(hash, _)| hash));
+        Ok(tuples.iter().map(|&(_, leaves)| leaves).sum())
+    }
+
+    fn next_item(&mut self) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        while self.left == 0 {
+            let hash = match self.pending.pop() {
+                Some(hash) => hash,
+                None => return Ok(None),
+            };
+            let chunk = self.database
+                .ok_or(Error::NoValueForRef(hash))?
+                .get(hash)?
+                .to_chunk();
+            self.reader = ChunkReader::new(self.database, chunk.data());
+            self.start_chunk()?;
+        }
+        self.left -= 1;
+        Ok(Some((0..self.per_item).map(|_| self.reader.read_item()).collect()))
+    }
+}
+
+impl<'a> Iterator for Items<'a> {
+    type Item = Result<Vec<Vec<u8>>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_item() {
+            Ok(item) => item.map(Ok),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Writes a number with commas between each group of three digits
+fn separated(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn separated_numbers() {
+        assert_eq!(separated(0), "0");
+        assert_eq!(separated(999), "999");
+        assert_eq!(separated(1000), "1,000");
+        assert_eq!(separated(1204331), "1,204,331");
+    }
+}
@@ -1,77 +1,143 @@
 //! The Noms Reference type
-use super::{Kind, Type, Value, IntoNoms, FromNoms, Collection};
+use super::{Kind, Type, Value, NomsValue, IntoNoms, FromNoms, Collection};
+use util::varint;
 use database::ChunkStore;
 use hash::{Hash, EMPTY_HASH};
-use chunk::Chunk;
+use chunk::{Chunk, ChunkReader};
+use error::Error;
 use std::fmt::{Display, Formatter};
 use std::hash::Hasher;
+use std::marker::PhantomData;
 
-/// A Ref describes a reference within a Noms database.
-///
-/// TODO: type references?
-#[derive(Clone)]
-pub struct Ref<'a> {
+/// A Ref describes a reference within a Noms database, to a value which can be read as a `T`. The
+/// value is only read from the database when the ref is loaded.
+pub struct Ref<'a, T = NomsValue<'a>> {
     database: &'a ChunkStore,
     hash: Hash,
-    value_type: Type,
+    target_type: Type,
     height: u64,
+    phantom_target: PhantomData<T>,
 }
 
-impl<'a> ::std::fmt::Debug for Ref<'a> {
+impl<'a, T> Clone for Ref<'a, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.database, self.hash, self.target_type.clone(), self.height)
+    }
+}
+
+impl<'a, T> ::std::fmt::Debug for Ref<'a, T> {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
         write!(f, "Ref({})", self.hash)
     }
 }
 
-impl<'a> Ref<'a> {
-    pub(crate) fn new(database: &'a ChunkStore, hash: Hash, value_type: Type, height: u64) -> Self {
-        Self{ database, hash, value_type, height }
+impl<'a, T> Ref<'a, T> {
+    pub(crate) fn new(database: &'a ChunkStore, hash: Hash, target_type: Type, height: u64) -> Self {
+        Self{ database, hash, target_type, height, phantom_target: PhantomData }
+    }
+    /// A ref which does not refer to anything, such as the head of a dataset with no commits.
+    pub(crate) fn empty(database: &'a ChunkStore) -> Self {
+        Self::new(database, EMPTY_HASH, Type::primitive(Kind::Value), 0)
+    }
+    /// The same ref, to be read as a `U` instead. The type of the target is not checked.
+    pub(crate) fn cast<U>(self) -> Ref<'a, U> {
+        Ref::new(self.database, self.hash, self.target_type, self.height)
     }
     pub fn is_empty(&self) -> bool {
         self.hash == EMPTY_HASH
     }
-    // TODO: this function should probably be renamed so that it does not conflict with std::hash::Hash.hash
+    /// The hash of the chunk this ref refers to
+    pub fn target_hash(&self) -> Hash {
+        self.hash
+    }
+    #[deprecated(note = "use `target_hash`, which does not conflict with `std::hash::Hash::hash`")]
     pub fn hash(&self) -> Hash {
         self.hash
     }
+    /// The type of the value this ref refers to
+    pub fn target_type(&self) -> &Type {
+        &self.target_type
+    }
+    /// The length of the longest chain of refs starting from this one. A ref to a value which
+    /// holds no other refs has a height of 1.
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+}
+
+impl<'a, T: FromNoms<'a> + IntoNoms> Ref<'a, T> {
+    /// Reads the value this ref refers to from the database. Fails without reading it if the type
+    /// of the target, which the ref holds, does not fit `T`.
+    pub fn load(&self) -> Result<T, Error> {
+        if self.is_empty() {
+            return Err(Error::NoValueForRef(self.hash));
+        }
+        let differences = T::noms_type().differences(&self.target_type);
+        if !differences.is_empty() {
+            return Err(Error::ConversionError(format!(
+                "The target of the ref {} does not fit the type it is read as:\n  {}",
+                self.hash.to_string().trim(),
+                differences.join("\n  "),
+            )));
+        }
+        self.database.get(self.hash).map(|value| value.export().transform())
+    }
 }
 
-impl<'a> Display for Ref<'a> {
+impl<'a, T> Display for Ref<'a, T> {
     fn fmt(&self, f: &mut Formatter) -> ::std::fmt::Result {
         write!(f, "Ref {{ hash: {} }}", self.hash)
     }
 }
 
-impl<'a> PartialEq for Ref<'a> {
-    fn eq(&self, b: &Ref) -> bool {
+impl<'a, T, U> PartialEq<Ref<'a, U>> for Ref<'a, T> {
+    fn eq(&self, b: &Ref<'a, U>) -> bool {
         self.hash == b.hash
     }
 }
-impl<'a> Eq for Ref<'a> {}
+impl<'a, T> Eq for Ref<'a, T> {}
 
-impl<'a> ::std::hash::Hash for Ref<'a> {
+impl<'a, T> ::std::hash::Hash for Ref<'a, T> {
     fn hash<H: Hasher>(&self, state: &mut H) {
        self.hash.hash(state);
    }
 }
 
-impl<'a> IntoNoms for Ref<'a> {
+impl<'a, T: IntoNoms> IntoNoms for Ref<'a, T> {
     fn into_noms(&self) -> Vec<u8> {
-        let mut bytes = Kind::Ref.into_noms();
-        bytes.extend_from_slice(&self.hash.raw_bytes());
-        bytes.extend(self.value_type.into_noms());
-        bytes
+        encode_ref(self.hash, &self.target_type, self.height)
     }
+    fn noms_type() -> Type { Type::compound(Kind::Ref, vec![T::noms_type()]) }
 }
 
-impl<'a> FromNoms<'a> for Ref<'a> {
+impl<'a, T> FromNoms<'a> for Ref<'a, T> {
     fn from_noms(chunk: &Chunk<'a>) -> Self {
-        Value::from_noms(chunk).to_ref().unwrap()
+        Value::from_noms(chunk).to_ref().unwrap().cast()
     }
 }
 
-impl<'a> Collection<'a, Value<'a>> for Ref<'a> {
+impl<'a, T> Collection<'a, Value<'a>> for Ref<'a, T> {
     fn database(&self) -> &'a ChunkStore {
         self.database
     }
 }
+
+/// Encodes a ref to the chunk with the given hash, which holds a value of the given type.
+pub(crate) fn encode_ref(hash: Hash, value_type: &Type, height: u64) -> Vec<u8> {
+    let mut bytes = Kind::Ref.into_noms();
+    bytes.extend_from_slice(&hash.raw_bytes());
+    bytes.extend(value_type.to_bytes());
+    bytes.extend(varint::encode_u64(height));
+    bytes
+}
+
+/// The height of a ref to the given chunk, which is one more than the greatest height of the refs
+/// it holds. Fails if the chunk is not encoded correctly.
+pub(crate) fn height_of(chunk: &Vec<u8>) -> Result<u64, Error> {
+    Ok(ChunkReader::new(None, chunk)
+        .read_refs_with_heights()?
+        .into_iter()
+        .map(|(_, height)| height)
+        .max()
+        .unwrap_or(0) + 1)
+}
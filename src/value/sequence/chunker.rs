@@ -0,0 +1,280 @@
+//! Writes large sequences as prolly trees, so that they are not held in a single chunk.
+//!
+//! Chunk boundaries are found like Go Noms does: a rolling hash runs over the encoded items of
+//! each level of the tree, and a chunk ends with the item during which the hash matches a pattern.
+//! The hash starts again after every boundary, so where a chunk ends depends only on its items,
+//! and inserting an item only changes the chunks around it.
+//!
+//! The rolling hash does not use Go Noms' table of byte hashes yet (see `util::buzhash`), so the
+//! boundaries differ from those Go Noms would find. The trees are still valid, and Go Noms reads
+//! them, but the same data written by both is split into different chunks, which are not shared.
+
+use super::{OrderedKey, Kind, IntoNoms, encode_sequence, sort_entries};
+use value::{encode_ref, height_of, type_of_encoded};
+use database::Database;
+use util::buzhash::BuzHash;
+use util::varint;
+use error::Error;
+use std::io::{self, Read};
+use std::mem;
+
+/// A chunk ends where the low bits of the rolling hash are all set, so chunks are 4KB on average.
+const CHUNK_PATTERN: u32 = (1 << 12) - 1;
+/// The number of bytes the rolling hash covers
+const CHUNK_WINDOW: usize = 64;
+
+/// An encoded item of a sequence, along with what is needed to build the meta tuple of a chunk
+/// which ends with it.
+pub(super) struct Item {
+    pub bytes: Vec<u8>,
+    /// The encoded ordered key, for maps and sets
+    pub key: Vec<u8>,
+    pub leaves: u64,
+}
+
+impl Item {
+    /// The bytes which the rolling hash runs over. For a meta tuple, which is an item above the
+    /// leaves, that is only its ref, as in Go Noms.
+    fn hashed(&self, level: u64) -> &[u8] {
+        if level == 0 {
+            &self.bytes
+        } else {
+            &self.bytes[..self.bytes.len() - self.key.len() - varint::encode_u64(self.leaves).len()]
+        }
+    }
+}
+
+/// Finds where the chunks of a level of the tree end, like Go Noms' `rollingValueHasher`. The
+/// hash of each level is salted with the level, so that the levels are split differently.
+pub(super) struct Boundaries {
+    hash: BuzHash,
+    level: u64,
+    crossed: bool,
+    /// The number of items in the current chunk
+    len: usize,
+}
+
+impl Boundaries {
+    pub fn new(level: u64) -> Self {
+        Boundaries{ hash: BuzHash::new(CHUNK_WINDOW), level, crossed: false, len: 0 }
+    }
+
+    /// Adds an item to the current chunk, returning whether the chunk ends with it. A chunk of
+    /// meta tuples always holds at least two, as a chunk holding a single meta tuple would be split
+    /// again at every level above it.
+    pub fn push(&mut self, item: &Item) -> bool {
+        for &byte in item.hashed(self.level) {
+            if self.hash_byte(byte) {
+                break;
+            }
+        }
+        self.len += 1;
+        if self.crossed && (self.level == 0 || self.len > 1) {
+            *self = Boundaries::new(self.level);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Adds a byte of a blob to the current chunk, returning whether the chunk ends with it.
+    fn push_byte(&mut self, byte: u8) -> bool {
+        if self.hash_byte(byte) {
+            *self = Boundaries::new(self.level);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Hashes the byte, unless a boundary has been crossed already, returning whether one has.
+    fn hash_byte(&mut self, byte: u8) -> bool {
+        if !self.crossed {
+            let sum = self.hash.hash_byte(byte ^ self.level as u8);
+            self.crossed = sum & CHUNK_PATTERN == CHUNK_PATTERN;
+        }
+        self.crossed
+    }
+}
+
+/// Splits the items at the level into the groups that will become chunks.
+fn split(items: Vec<Item>, level: u64) -> Vec<Vec<Item>> {
+    let mut boundaries = Boundaries::new(level);
+    let mut groups = vec![];
+    let mut group = vec![];
+    for item in items {
+        let boundary = boundaries.push(&item);
+        group.push(item);
+        if boundary {
+            groups.push(group);
+            group = vec![];
+        }
+    }
+    if !group.is_empty() || groups.is_empty() {
+        groups.push(group);
+    }
+    groups
+}
+
+pub(super) fn encode_items(kind: Kind, level: u64, items: &[Item]) -> Vec<u8> {
+    let bytes: Vec<u8> = items.iter().flat_map(|item| item.bytes.iter().cloned()).collect();
+    encode_sequence(kind, level, items.len(), &bytes)
+}
+
+/// Builds the tree one level at a time, starting from items at the given level, writing every chunk
+/// except the root, which is returned.
+fn write_tree<D: Database>(database: &D, kind: Kind, mut items: Vec<Item>, mut level: u64) -> Result<Vec<u8>, Error> {
+    loop {
+        let groups = split(items, level);
+        if groups.len() == 1 {
+            return Ok(encode_items(kind, level, &groups[0]));
+        }
+        items = Vec::with_capacity(groups.len());
+        for group in groups {
+            let (key, leaves) = key_of_chunk(kind, &group);
+            let chunk = encode_items(kind, level, &group);
+            items.push(write_chunk(database, chunk, key, leaves)?);
+        }
+        level += 1;
+    }
+}
+
+/// The key and number of leaves of the meta tuple which refers to a chunk of the items. Maps and
+/// sets are ordered by the key of their last item, and other sequences by their number of leaves.
+pub(super) fn key_of_chunk(kind: Kind, items: &[Item]) -> (Vec<u8>, u64) {
+    let leaves: u64 = items.iter().map(|item| item.leaves).sum();
+    match (kind, items.last()) {
+        (Kind::Map, Some(last)) | (Kind::Set, Some(last)) => (last.key.clone(), leaves),
+        _ => (leaves.into_noms(), leaves),
+    }
+}
+
+/// Writes a chunk of a sequence, returning the meta tuple which refers to it. The ref holds the
+/// type of the chunk, so that the type of the whole sequence can be found without reading its
+/// leaves.
+pub(super) fn write_chunk<D: Database>(database: &D, chunk: Vec<u8>, key: Vec<u8>, leaves: u64) -> Result<Item, Error> {
+    let height = height_of(&chunk)?;
+    let chunk_type = type_of_encoded(&chunk)?;
+    let hash = database.write_value(chunk)?;
+    let mut bytes = encode_ref(hash, &chunk_type, height);
+    bytes.extend_from_slice(&key);
+    bytes.extend(varint::encode_u64(leaves));
+    Ok(Item{ bytes, key, leaves })
+}
+
+/// Encodes a list, writing every chunk but the root to the database. The root is returned, so
+/// that it can be held by another value, or written itself.
+pub(crate) fn write_list<D: Database>(database: &D, items: Vec<Vec<u8>>) -> Result<Vec<u8>, Error> {
+    let items = items
+        .into_iter()
+        .map(|bytes| Item{ bytes, key: vec![], leaves: 1 })
+        .collect();
+    write_tree(database, Kind::List, items, 0)
+}
+
+/// Encodes the ordered key of an encoded value, as it is held by a meta tuple: the value itself if
+/// it is ordered by value, otherwise its hash.
+pub(super) fn key_of(value: &[u8]) -> Vec<u8> {
+    match OrderedKey::of_encoded(value) {
+        OrderedKey::ByHash(h) => {
+            let mut key = Kind::Hash.into_noms();
+            key.extend_from_slice(&h.raw_bytes());
+            key
+        }
+        OrderedKey::ByValue(_) => value.to_vec(),
+    }
+}
+
+/// Encodes a map, writing every chunk but the root to the database. Entries are ordered by key,
+/// and only the last entry with each key is kept.
+pub(crate) fn write_map<D: Database>(database: &D, entries: Vec<(Vec<u8>, Vec<u8>)>) -> Result<Vec<u8>, Error> {
+    let items = sort_entries(entries)
+        .into_iter()
+        .map(|(k, v)| {
+            let key = key_of(&k);
+            let mut bytes = k;
+            bytes.extend(v);
+            Item{ bytes, key, leaves: 1 }
+        })
+        .collect();
+    write_tree(database, Kind::Map, items, 0)
+}
+
+/// Encodes a blob as it is read, writing every chunk but the root to the database. Only the leaf
+/// being read is held in memory, along with the meta tuples of the leaves before it.
+pub(crate) fn write_blob<D: Database, R: Read>(database: &D, mut reader: R) -> Result<Vec<u8>, Error> {
+    let mut boundaries = Boundaries::new(0);
+    let mut items = vec![];
+    let mut leaf = vec![];
+    // a leaf which has ended, but is only written once another follows it, as a blob of a single
+    // leaf is not chunked at all
+    let mut ended: Option<Vec<u8>> = None;
+    let mut buf = [0; 1 << 16];
+    loop {
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for &b in &buf[..len] {
+            if let Some(ended) = ended.take() {
+                items.push(write_blob_leaf(database, ended)?);
+            }
+            leaf.push(b);
+            if boundaries.push_byte(b) {
+                ended = Some(mem::take(&mut leaf));
+            }
+        }
+    }
+    let last = ended.unwrap_or(leaf);
+    if items.is_empty() {
+        return Ok(encode_sequence(Kind::Blob, 0, last.len(), &last));
+    }
+    items.push(write_blob_leaf(database, last)?);
+    write_tree(database, Kind::Blob, items, 1)
+}
+
+fn write_blob_leaf<D: Database>(database: &D, leaf: Vec<u8>) -> Result<Item, Error> {
+    let len = leaf.len() as u64;
+    let chunk = encode_sequence(Kind::Blob, 0, leaf.len(), &leaf);
+    write_chunk(database, chunk, len.into_noms(), len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(range: ::std::ops::Range<i64>) -> Vec<Item> {
+        range.map(|i| Item{ bytes: i.into_noms(), key: vec![], leaves: 1 }).collect()
+    }
+
+    #[test]
+    fn chunks_are_4kb_on_average() {
+        let groups = split(items(0..200_000), 0);
+        let bytes: usize = groups.iter().flat_map(|group| group.iter().map(|item| item.bytes.len())).sum();
+        let average = bytes / groups.len();
+        assert!(average > 2_000 && average < 8_000, "Chunks are {} bytes on average", average);
+    }
+
+    #[test]
+    fn boundaries_depend_only_on_the_items_of_a_chunk() {
+        let whole: Vec<usize> = split(items(0..50_000), 0).iter().map(Vec::len).collect();
+        assert!(whole.len() > 2);
+        let rest: Vec<usize> = split(items(whole[0] as i64..50_000), 0).iter().map(Vec::len).collect();
+        assert_eq!(&whole[1..], &rest[..]);
+        // the hash of each level is salted, so the same items are split differently
+        let meta: Vec<usize> = split(items(0..50_000), 1).iter().map(Vec::len).collect();
+        assert_ne!(whole, meta);
+    }
+
+    #[test]
+    fn meta_chunks_hold_at_least_two_items() {
+        let mut boundaries = Boundaries::new(1);
+        let item = Item{ bytes: vec![0; 100], key: vec![], leaves: 1 };
+        boundaries.crossed = true;
+        assert!(!boundaries.push(&item));
+        assert!(boundaries.push(&item));
+        assert!(!boundaries.crossed);
+    }
+}
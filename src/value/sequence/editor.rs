@@ -0,0 +1,463 @@
+//! Edits lists, maps and sets which are stored as prolly trees.
+//!
+//! Edits are recorded against the chunks of the existing tree, which are only read once an edit
+//! reaches into them. Building the edited collection groups the items into chunks again, but a
+//! chunk which was not read is kept whole wherever the new chunks line up with it, so that only
+//! the chunks on the paths to the edits are written again. Since chunk boundaries depend only on
+//! the items, the result is the same tree as writing the edited collection from scratch.
+
+use std::mem;
+use super::{OrderedKey, Kind, IntoNoms, NomsValue};
+use super::chunker::{Item, Boundaries, encode_items, key_of, key_of_chunk, write_chunk};
+use chunk::ChunkReader;
+use database::Database;
+use hash::Hash;
+use error::Error;
+
+/// A run of the tree, which is either held as items, or is a chunk which has not been read.
+enum Segment {
+    /// Items at the level being built, which are leaves while editing
+    Items(Vec<Item>),
+    /// The meta tuple which refers to a chunk, and the level of the items in that chunk
+    Chunk(Item, u64),
+}
+
+/// A chunk which has been built at some level of the tree.
+enum Built {
+    /// A new chunk, along with the key and number of leaves of its meta tuple. It is only written
+    /// once it is known not to be the root.
+    New(Vec<u8>, Vec<u8>, u64),
+    /// A chunk of the original tree, and the level of the items in it
+    Kept(Item, u64),
+}
+
+/// The edited tree of a list, map or set, which the editors share.
+struct Tree<'a, D: Database + 'a> {
+    database: &'a D,
+    kind: Kind,
+    segments: Vec<Segment>,
+}
+
+impl<'a, D: Database> Tree<'a, D> {
+    fn new(database: &'a D, kind: Kind) -> Self {
+        Tree{ database, kind, segments: vec![Segment::Items(vec![])] }
+    }
+
+    fn edit(database: &'a D, kind: Kind, value: &NomsValue<'a>) -> Result<Self, Error> {
+        let bytes = value.into_noms();
+        if bytes.first() != Some(&(kind as u8)) {
+            return Err(Error::ConversionError(format!("{:?} is not a {:?}", value, kind)));
+        }
+        let mut tree = Tree{ database, kind, segments: vec![] };
+        tree.segments = tree.children(&bytes);
+        Ok(tree)
+    }
+
+    fn len(&self) -> u64 {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                &Segment::Items(ref items) => items.len() as u64,
+                &Segment::Chunk(ref item, _) => item.leaves,
+            })
+            .sum()
+    }
+
+    /// Makes a leaf item from the encoded value, or the encoded key and value of a map entry.
+    fn item(&self, bytes: Vec<u8>, value: Option<Vec<u8>>) -> Item {
+        let key = match self.kind {
+            Kind::Map | Kind::Set => key_of(&bytes),
+            _ => vec![],
+        };
+        let mut bytes = bytes;
+        bytes.extend(value.unwrap_or_default());
+        Item{ bytes, key, leaves: 1 }
+    }
+
+    /// Reads the level of an encoded sequence, and its items.
+    fn read(&self, chunk: &Vec<u8>) -> (u64, Vec<Item>) {
+        let reader = ChunkReader::new(None, chunk);
+        reader.read_kind();
+        let level = reader.read_varint();
+        let len = reader.read_varint();
+        let items = (0..len)
+            .map(|_| if level > 0 {
+                let (bytes, key, leaves) = reader.read_metatuple_bytes();
+                Item{ bytes, key, leaves }
+            } else if self.kind == Kind::Map {
+                let key = reader.read_item();
+                self.item(key, Some(reader.read_item()))
+            } else {
+                self.item(reader.read_item(), None)
+            })
+            .collect();
+        (level, items)
+    }
+
+    /// The segments of an encoded sequence: its items if it is a leaf, otherwise its chunks.
+    fn children(&self, chunk: &Vec<u8>) -> Vec<Segment> {
+        match self.read(chunk) {
+            (0, items) => vec![Segment::Items(items)],
+            (level, items) => items.into_iter().map(|item| Segment::Chunk(item, level - 1)).collect(),
+        }
+    }
+
+    /// Reads the chunk which the meta tuple refers to.
+    fn load(&self, item: &Item) -> Result<Vec<u8>, Error> {
+        let (hash, _) = ChunkReader::new(None, &item.bytes).read_metatuple_leaves();
+        Ok(self.database.read_value(hash)?.into_noms())
+    }
+
+    /// Replaces the chunk at the position with the chunks or items it holds.
+    fn expand(&mut self, i: usize) -> Result<(), Error> {
+        let children = match self.segments[i] {
+            Segment::Chunk(ref item, _) => self.children(&self.load(item)?),
+            Segment::Items(_) => return Ok(()),
+        };
+        self.segments.splice(i..i + 1, children);
+        Ok(())
+    }
+
+    /// Reads the last chunk on every level, so that items can be added after the last one, and
+    /// returns the position of the items they are added to. The last chunk of a level is the only
+    /// one which may not end at a boundary, so it cannot be kept once items follow it.
+    fn expand_last(&mut self) -> Result<usize, Error> {
+        loop {
+            let i = match self.segments.last() {
+                Some(&Segment::Items(_)) => return Ok(self.segments.len() - 1),
+                Some(&Segment::Chunk(..)) => self.segments.len() - 1,
+                None => {
+                    self.segments.push(Segment::Items(vec![]));
+                    continue;
+                }
+            };
+            self.expand(i)?;
+        }
+    }
+
+    fn items_mut(&mut self, i: usize) -> &mut Vec<Item> {
+        match self.segments[i] {
+            Segment::Items(ref mut items) => items,
+            Segment::Chunk(..) => unreachable!("Chunks are expanded before their items are edited"),
+        }
+    }
+
+    /// Finds the item at the index, reading the chunks on the way to it, and returns the position
+    /// of the items which hold it along with its index there. An index past the last item finds
+    /// the end of the last items.
+    fn find_index(&mut self, index: u64) -> Result<(usize, usize), Error> {
+        let mut start = 0;
+        let mut i = 0;
+        while i < self.segments.len() {
+            let (len, expand) = match self.segments[i] {
+                Segment::Items(ref items) => (items.len() as u64, false),
+                Segment::Chunk(ref item, _) => (item.leaves, true),
+            };
+            if index < start + len {
+                if !expand {
+                    return Ok((i, (index - start) as usize));
+                }
+                self.expand(i)?;
+            } else {
+                start += len;
+                i += 1;
+            }
+        }
+        let i = self.expand_last()?;
+        let end = self.items_mut(i).len();
+        Ok((i, end))
+    }
+
+    /// Finds where the item with the encoded ordered key is, or would be added, reading the chunks
+    /// on the way to it. Returns the position of the items which hold it, its index there, and
+    /// whether it is there already.
+    fn find_key(&mut self, key: &[u8]) -> Result<(usize, usize, bool), Error> {
+        let key = ordered_key(key);
+        let mut i = 0;
+        while i < self.segments.len() {
+            let (expand, found) = match self.segments[i] {
+                Segment::Chunk(ref item, _) => (key <= ordered_key(&item.key), None),
+                Segment::Items(ref items) => match items.binary_search_by(|item| ordered_key(&item.key).cmp(&key)) {
+                    Ok(j) => (false, Some((j, true))),
+                    Err(j) if j < items.len() => (false, Some((j, false))),
+                    Err(_) => (false, None),
+                },
+            };
+            if let Some((j, exists)) = found {
+                return Ok((i, j, exists));
+            }
+            if expand {
+                self.expand(i)?;
+            } else {
+                i += 1;
+            }
+        }
+        let i = self.expand_last()?;
+        let end = self.items_mut(i).len();
+        Ok((i, end, false))
+    }
+
+    /// Sets the entry with the key of the item, returning whether there was one. If `add` is false,
+    /// only an entry which is there already is replaced.
+    fn put(&mut self, item: Item, add: bool) -> Result<bool, Error> {
+        let (i, j, exists) = self.find_key(&item.key)?;
+        let items = self.items_mut(i);
+        if exists {
+            items[j] = item;
+        } else if add {
+            items.insert(j, item);
+        }
+        Ok(exists)
+    }
+
+    /// Removes the entry with the encoded ordered key, returning whether there was one.
+    fn remove_key(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let (i, j, exists) = self.find_key(key)?;
+        if exists {
+            self.items_mut(i).remove(j);
+        }
+        Ok(exists)
+    }
+
+    /// Writes the chunks of the edited tree which are not in the database yet, except the root,
+    /// which is returned.
+    fn build(mut self) -> Result<Vec<u8>, Error> {
+        let mut segments = mem::take(&mut self.segments);
+        let mut level = 0;
+        loop {
+            let mut built = self.build_level(segments, level)?;
+            if built.len() == 1 {
+                let root = match built.pop().unwrap() {
+                    Built::New(chunk, _, _) => chunk,
+                    Built::Kept(item, _) => self.load(&item)?,
+                };
+                return self.descend(root);
+            }
+            segments = vec![];
+            for chunk in built {
+                let item = match chunk {
+                    Built::New(chunk, key, leaves) => write_chunk(self.database, chunk, key, leaves)?,
+                    Built::Kept(item, items_level) if items_level == level => item,
+                    Built::Kept(item, items_level) => {
+                        segments.push(Segment::Chunk(item, items_level));
+                        continue;
+                    }
+                };
+                match segments.last_mut() {
+                    Some(&mut Segment::Items(ref mut items)) => items.push(item),
+                    _ => segments.push(Segment::Items(vec![item])),
+                }
+            }
+            level += 1;
+        }
+    }
+
+    /// Groups the items at the level into chunks. A chunk of the original tree is kept where a new
+    /// chunk would start, and its items are read again otherwise.
+    fn build_level(&self, segments: Vec<Segment>, level: u64) -> Result<Vec<Built>, Error> {
+        let mut built = vec![];
+        let mut group = vec![];
+        // the hash starts again at every boundary, so it is fresh wherever a chunk is kept
+        let mut boundaries = Boundaries::new(level);
+        // the segments which have not been reached yet, with the next one last
+        let mut pending: Vec<Segment> = segments.into_iter().rev().collect();
+        while let Some(segment) = pending.pop() {
+            match segment {
+                Segment::Chunk(item, items_level) if group.is_empty() => built.push(Built::Kept(item, items_level)),
+                Segment::Chunk(item, items_level) if items_level > level =>
+                    pending.extend(self.children(&self.load(&item)?).into_iter().rev()),
+                Segment::Chunk(item, _) => pending.push(Segment::Items(self.read(&self.load(&item)?).1)),
+                Segment::Items(items) => for item in items {
+                    let boundary = boundaries.push(&item);
+                    group.push(item);
+                    if boundary {
+                        built.push(self.new_chunk(level, mem::take(&mut group)));
+                    }
+                },
+            }
+        }
+        if !group.is_empty() || built.is_empty() {
+            built.push(self.new_chunk(level, group));
+        }
+        Ok(built)
+    }
+
+    fn new_chunk(&self, level: u64, items: Vec<Item>) -> Built {
+        let (key, leaves) = key_of_chunk(self.kind, &items);
+        Built::New(encode_items(self.kind, level, &items), key, leaves)
+    }
+
+    /// A root which holds a single meta tuple is replaced by the chunk it refers to, as it would
+    /// not have been split from it.
+    fn descend(&self, mut root: Vec<u8>) -> Result<Vec<u8>, Error> {
+        loop {
+            let (level, mut items) = self.read(&root);
+            if level == 0 || items.len() != 1 {
+                return Ok(root);
+            }
+            root = self.load(&items.remove(0))?;
+        }
+    }
+}
+
+/// Decodes an encoded ordered key, as it is held by a meta tuple.
+fn ordered_key(key: &[u8]) -> OrderedKey<'static> {
+    if key.first() == Some(&(Kind::Hash as u8)) {
+        OrderedKey::by_hash(Hash::from_slice(&key[1..]))
+    } else {
+        OrderedKey::of_encoded(key)
+    }
+}
+
+/// Edits a list, writing only the chunks which change when it is built.
+pub struct ListEditor<'a, D: Database + 'a>(Tree<'a, D>);
+
+impl<'a, D: Database> ListEditor<'a, D> {
+    /// Starts a new, empty list.
+    pub fn new(database: &'a D) -> Self {
+        ListEditor(Tree::new(database, Kind::List))
+    }
+
+    /// Starts editing the list, which must have been read from the database.
+    pub fn edit(database: &'a D, list: &NomsValue<'a>) -> Result<Self, Error> {
+        Tree::edit(database, Kind::List, list).map(ListEditor)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Inserts the value before the item at the index, or after the last item if the index is
+    /// the length of the list.
+    pub fn insert<V: IntoNoms>(&mut self, index: u64, value: V) -> Result<(), Error> {
+        self.splice(index, 0, Some(value))
+    }
+
+    /// Removes the item at the index.
+    pub fn remove(&mut self, index: u64) -> Result<(), Error> {
+        self.splice(index, 1, None::<NomsValue>)
+    }
+
+    /// Replaces the item at the index with the value.
+    pub fn update<V: IntoNoms>(&mut self, index: u64, value: V) -> Result<(), Error> {
+        self.splice(index, 1, Some(value))
+    }
+
+    /// Removes `remove` items starting at the index, and inserts the values in their place.
+    pub fn splice<V, I>(&mut self, index: u64, remove: u64, values: I) -> Result<(), Error>
+    where V: IntoNoms, I: IntoIterator<Item = V> {
+        let len = self.len();
+        if index.checked_add(remove).is_none_or(|end| end > len) {
+            return Err(Error::IndexOutOfBounds(index, len));
+        }
+        for _ in 0..remove {
+            let (i, j) = self.0.find_index(index)?;
+            self.0.items_mut(i).remove(j);
+        }
+        for (offset, value) in values.into_iter().enumerate() {
+            let item = self.0.item(value.into_noms(), None);
+            let (i, j) = self.0.find_index(index + offset as u64)?;
+            self.0.items_mut(i).insert(j, item);
+        }
+        Ok(())
+    }
+
+    /// Writes the chunks of the edited list which are not in the database yet. The returned list
+    /// is not written itself, so it should be committed, or held by a value which is.
+    pub fn build(self) -> Result<NomsValue<'a>, Error> {
+        let database = self.0.database;
+        Ok(database.value_from(self.0.build()?))
+    }
+}
+
+/// Edits a map, writing only the chunks which change when it is built.
+pub struct MapEditor<'a, D: Database + 'a>(Tree<'a, D>);
+
+impl<'a, D: Database> MapEditor<'a, D> {
+    /// Starts a new, empty map.
+    pub fn new(database: &'a D) -> Self {
+        MapEditor(Tree::new(database, Kind::Map))
+    }
+
+    /// Starts editing the map, which must have been read from the database.
+    pub fn edit(database: &'a D, map: &NomsValue<'a>) -> Result<Self, Error> {
+        Tree::edit(database, Kind::Map, map).map(MapEditor)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sets the value of the key, returning whether the map held the key already.
+    pub fn insert<K: IntoNoms, V: IntoNoms>(&mut self, key: K, value: V) -> Result<bool, Error> {
+        let item = self.0.item(key.into_noms(), Some(value.into_noms()));
+        self.0.put(item, true)
+    }
+
+    /// Sets the value of the key only if the map holds the key already, returning whether it did.
+    pub fn update<K: IntoNoms, V: IntoNoms>(&mut self, key: K, value: V) -> Result<bool, Error> {
+        let item = self.0.item(key.into_noms(), Some(value.into_noms()));
+        self.0.put(item, false)
+    }
+
+    /// Removes the key, returning whether the map held it.
+    pub fn remove<K: IntoNoms>(&mut self, key: K) -> Result<bool, Error> {
+        self.0.remove_key(&key_of(&key.into_noms()))
+    }
+
+    /// Writes the chunks of the edited map which are not in the database yet. The returned map is
+    /// not written itself, so it should be committed, or held by a value which is.
+    pub fn build(self) -> Result<NomsValue<'a>, Error> {
+        let database = self.0.database;
+        Ok(database.value_from(self.0.build()?))
+    }
+}
+
+/// Edits a set, writing only the chunks which change when it is built.
+pub struct SetEditor<'a, D: Database + 'a>(Tree<'a, D>);
+
+impl<'a, D: Database> SetEditor<'a, D> {
+    /// Starts a new, empty set.
+    pub fn new(database: &'a D) -> Self {
+        SetEditor(Tree::new(database, Kind::Set))
+    }
+
+    /// Starts editing the set, which must have been read from the database.
+    pub fn edit(database: &'a D, set: &NomsValue<'a>) -> Result<Self, Error> {
+        Tree::edit(database, Kind::Set, set).map(SetEditor)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Adds the value, returning whether the set held it already.
+    pub fn insert<V: IntoNoms>(&mut self, value: V) -> Result<bool, Error> {
+        let item = self.0.item(value.into_noms(), None);
+        self.0.put(item, true)
+    }
+
+    /// Removes the value, returning whether the set held it.
+    pub fn remove<V: IntoNoms>(&mut self, value: V) -> Result<bool, Error> {
+        self.0.remove_key(&key_of(&value.into_noms()))
+    }
+
+    /// Writes the chunks of the edited set which are not in the database yet. The returned set is
+    /// not written itself, so it should be committed, or held by a value which is.
+    pub fn build(self) -> Result<NomsValue<'a>, Error> {
+        let database = self.0.database;
+        Ok(database.value_from(self.0.build()?))
+    }
+}
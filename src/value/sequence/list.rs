@@ -1,6 +1,8 @@
-use super::{NomsValue, Value, FromNoms, IntoNoms, MetaTuple, Collection};
+use super::{NomsValue, Value, FromNoms, IntoNoms, MetaTuple, Collection, Type, Kind};
 use database::ChunkStore;
 use chunk::Chunk;
+use error::Error;
+use std::vec;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NomsList<'a, V = NomsValue<'a>>(List<'a, V>)
@@ -16,9 +18,63 @@ where V: FromNoms<'a> + IntoNoms + Clone {
         NomsList(list)
     }
 
+    /// Reads every item of the list. Panics if a chunk of the list cannot be fetched; collect the
+    /// items of `iter` into a `Result` to handle that instead.
     pub fn to_vec(&self) -> Vec<V> {
         self.0.to_vec()
     }
+
+    /// Iterates over the items of the list. The chunks of a large list are only fetched once the
+    /// iterator reaches them, so the whole list is never held in memory at once. If a chunk
+    /// cannot be fetched, the error is returned in place of its items, and iteration ends.
+    pub fn iter(&self) -> ListIter<'a, V> {
+        ListIter {
+            pending: vec![Pending::List(self.0.clone())],
+            items: vec![].into_iter(),
+        }
+    }
+}
+
+/// An iterator over the items of a `NomsList`, created by `NomsList::iter`.
+pub struct ListIter<'a, V>
+where V: FromNoms<'a> + IntoNoms {
+    /// The parts of the list which have not been reached yet, with the next one last
+    pending: Vec<Pending<'a, V>>,
+    items: vec::IntoIter<V>,
+}
+
+enum Pending<'a, V>
+where V: FromNoms<'a> + IntoNoms {
+    List(List<'a, V>),
+    Chunk(&'a ChunkStore, MetaTuple<'a>),
+}
+
+impl<'a, V> Iterator for ListIter<'a, V>
+where V: FromNoms<'a> + IntoNoms {
+    type Item = Result<V, Error>;
+
+    fn next(&mut self) -> Option<Result<V, Error>> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Some(Ok(item));
+            }
+            match self.pending.pop()? {
+                Pending::List(List::Leaf{ cache, .. }) => self.items = cache.into_iter(),
+                Pending::List(List::Inner{ database, raw }) =>
+                    self.pending.extend(raw.into_iter().rev().map(|mt| Pending::Chunk(database, mt))),
+                Pending::Chunk(database, mt) => match database.get(mt.reference.target_hash()) {
+                    Ok(value) => {
+                        let child: NomsList<'a, V> = value.export().transform();
+                        self.pending.push(Pending::List(child.0));
+                    }
+                    Err(err) => {
+                        self.pending.clear();
+                        return Some(Err(err));
+                    }
+                },
+            }
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -105,6 +161,7 @@ where V: FromNoms<'a> + IntoNoms {
     fn into_noms(&self) -> Vec<u8> {
         unimplemented!();
     }
+    fn noms_type() -> Type { Type::compound(Kind::List, vec![V::noms_type()]) }
 }
 impl<'a, V> FromNoms<'a> for NomsList<'a, V>
 where V: FromNoms<'a> + IntoNoms {
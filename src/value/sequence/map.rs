@@ -1,7 +1,9 @@
-use super::{NomsValue, Value, Ref, FromNoms, IntoNoms, MetaTuple, Collection};
+use super::{NomsValue, Value, Ref, FromNoms, IntoNoms, MetaTuple, OrderedKey, Collection, Type, Kind};
+use super::merge::{sorted, Part, Keep, Walk, Merge};
 use database::ChunkStore;
 use std::collections::HashMap;
 use chunk::Chunk;
+use error::Error;
 use std::hash::Hash;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -18,15 +20,94 @@ where K: FromNoms<'a> + IntoNoms + Eq + Hash, V: FromNoms<'a> + IntoNoms {
         NomsMap(map)
     }
 
+    /// Reads every entry of the map. Panics if a chunk of the map cannot be fetched; collect the
+    /// entries of `iter` into a `Result` to handle that instead.
     pub fn to_map(&self) -> HashMap<K, V> {
         self.0.to_map()
     }
 
-    // TODO: make some way to use these collections without loading the entire thing into memory
-    pub fn get<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> Option<&V>
-    where K: ::std::borrow::Borrow<Q> {
-        self.0.get(key)
+    /// Iterates over the entries of the map, ordered by key. The chunks of a large map are only
+    /// fetched once the iterator reaches them, so the whole map is never held in memory at once.
+    /// If a chunk cannot be fetched, the error is returned in place of its entries, and iteration
+    /// ends.
+    pub fn iter(&self) -> MapIter<'a, K, V> {
+        MapIter(Walk::new(self.0.database(), self.0.clone().entries(), load_entries))
     }
+
+    /// The keys which are in either map, in order.
+    ///
+    /// Like the other operations on the keys of two maps, this reads the maps as it is iterated
+    /// over, and only reads the chunks in which they differ. Chunks which hold the same keys but
+    /// different values differ too.
+    pub fn key_union<V2>(&self, other: &NomsMap<'a, K, V2>) -> Merge<'a, K>
+    where V2: FromNoms<'a> + IntoNoms {
+        self.merge_keys(other, Keep{ left: true, both: true, right: true })
+    }
+
+    /// The keys which are in both maps, in order.
+    pub fn key_intersection<V2>(&self, other: &NomsMap<'a, K, V2>) -> Merge<'a, K>
+    where V2: FromNoms<'a> + IntoNoms {
+        self.merge_keys(other, Keep{ left: false, both: true, right: false })
+    }
+
+    /// The keys of this map which are not in the other, in order.
+    pub fn key_difference<V2>(&self, other: &NomsMap<'a, K, V2>) -> Merge<'a, K>
+    where V2: FromNoms<'a> + IntoNoms {
+        self.merge_keys(other, Keep{ left: true, both: false, right: false })
+    }
+
+    /// Whether every key of this map is in the other.
+    pub fn is_key_subset<V2>(&self, other: &NomsMap<'a, K, V2>) -> Result<bool, Error>
+    where V2: FromNoms<'a> + IntoNoms {
+        match self.key_difference(other).next() {
+            Some(Err(err)) => Err(err),
+            Some(Ok(_)) => Ok(false),
+            None => Ok(true),
+        }
+    }
+
+    fn merge_keys<V2>(&self, other: &NomsMap<'a, K, V2>, keep: Keep) -> Merge<'a, K>
+    where V2: FromNoms<'a> + IntoNoms {
+        Merge::new(
+            (self.0.database(), self.0.clone().keys(), load_keys::<K, V>),
+            (other.0.database(), other.0.clone().keys(), load_keys::<K, V2>),
+            keep,
+        )
+    }
+
+    /// Looks up the value of a key. Only the chunks on the path to the key are fetched, so a
+    /// lookup in a large map reads one chunk for each level of the tree.
+    pub fn get<Q: IntoNoms>(&self, key: Q) -> Result<Option<V>, Error> {
+        let key = key.into_noms();
+        self.0.get(&key, &OrderedKey::of_encoded(&key))
+    }
+}
+
+/// An iterator over the entries of a `NomsMap`, created by `NomsMap::iter`.
+pub struct MapIter<'a, K, V>(Walk<'a, (K, V)>);
+
+impl<'a, K, V> Iterator for MapIter<'a, K, V> {
+    type Item = Result<(K, V), Error>;
+
+    fn next(&mut self) -> Option<Result<(K, V), Error>> {
+        self.0.next()
+    }
+}
+
+/// Reads a chunk of a map, returning its entries or the chunks it refers to.
+fn load_entries<'a, K, V>(database: &'a dyn ChunkStore, mt: &MetaTuple<'a>) -> Result<Vec<Part<'a, (K, V)>>, Error>
+where K: FromNoms<'a> + IntoNoms + Eq + Hash, V: FromNoms<'a> + IntoNoms {
+    database
+        .get(mt.reference.target_hash())
+        .map(|value| value.export().transform::<NomsMap<'a, K, V>>().0.entries())
+}
+
+/// Reads a chunk of a map, returning its keys or the chunks it refers to.
+fn load_keys<'a, K, V>(database: &'a dyn ChunkStore, mt: &MetaTuple<'a>) -> Result<Vec<Part<'a, K>>, Error>
+where K: FromNoms<'a> + IntoNoms + Eq + Hash, V: FromNoms<'a> + IntoNoms {
+    database
+        .get(mt.reference.target_hash())
+        .map(|value| value.export().transform::<NomsMap<'a, K, V>>().0.keys())
 }
 
 #[derive(Clone, Debug)]
@@ -100,11 +181,34 @@ where K: FromNoms<'a> + IntoNoms + Eq + Hash, V: FromNoms<'a> + IntoNoms {
         }
     }
 
-    pub fn get<Q: ?Sized + Hash + Eq>(&self, key: &Q) -> Option<&V>
-    where K: ::std::borrow::Borrow<Q> {
+    /// The entries of a leaf ordered by key, or the chunks of an inner node.
+    fn entries(self) -> Vec<Part<'a, (K, V)>> {
+        match self {
+            Map::Leaf{ cache, .. } => sorted(cache.into_iter().map(|(k, v)| (k.into_noms(), (k, v)))),
+            Map::Inner{ raw, .. } => raw.into_iter().map(Part::Chunk).collect(),
+        }
+    }
+
+    /// The keys of a leaf in order, or the chunks of an inner node.
+    fn keys(self) -> Vec<Part<'a, K>> {
+        match self {
+            Map::Leaf{ cache, .. } => sorted(cache.into_iter().map(|(k, _)| (k.into_noms(), k))),
+            Map::Inner{ raw, .. } => raw.into_iter().map(Part::Chunk).collect(),
+        }
+    }
+
+    /// Looks up the value of an encoded key. Meta tuples hold the last key of their chunk, so the
+    /// key can only be in the first chunk whose last key is not before it.
+    fn get(&self, key: &[u8], ordered: &OrderedKey) -> Result<Option<V>, Error> {
         match self {
-            &Map::Inner { .. }           => unimplemented!(),
-            &Map::Leaf { ref cache, .. } => cache.get(key)
+            &Map::Inner{ ref raw, .. } => match raw.iter().find(|mt| mt.key >= *ordered) {
+                Some(mt) => self.resolve(mt)?.0.get(key, ordered),
+                None => Ok(None),
+            },
+            &Map::Leaf{ ref cache, .. } => Ok(cache
+                .iter()
+                .find(|&(k, _)| k.into_noms() == key)
+                .map(|(_, v)| v.clone())),
         }
     }
 }
@@ -130,6 +234,7 @@ where K: FromNoms<'a> + IntoNoms + Eq + Hash, V: FromNoms<'a> + IntoNoms {
     fn into_noms(&self) -> Vec<u8> {
         unimplemented!();
     }
+    fn noms_type() -> Type { Type::compound(Kind::Map, vec![K::noms_type(), V::noms_type()]) }
 }
 impl<'a, K, V> FromNoms<'a> for NomsMap<'a, K, V>
 where K: FromNoms<'a> + IntoNoms + Eq + Hash, V: FromNoms<'a> + IntoNoms {
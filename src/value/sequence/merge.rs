@@ -0,0 +1,245 @@
+//! Merges two ordered sequences, such as the items of two sets or the keys of two maps, by walking
+//! their prolly trees side by side. Chunks are only read where the trees differ: a chunk which is
+//! in both trees has the same hash in each, so it is kept or skipped whole, and a chunk which ends
+//! before the next item of the other tree is too.
+
+use super::{MetaTuple, OrderedKey};
+use database::ChunkStore;
+use error::Error;
+use std::cmp::Ordering;
+
+/// A part of an ordered sequence: an item along with its key, or a chunk which has not been read
+pub(crate) enum Part<'a, T> {
+    Item(OrderedKey<'a>, T),
+    Chunk(MetaTuple<'a>),
+}
+
+/// Which items a merge keeps: those only in the left sequence, those in both, and those only in
+/// the right sequence. Items in both are taken from the left sequence.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Keep {
+    pub left: bool,
+    pub both: bool,
+    pub right: bool,
+}
+
+/// Orders the items of a leaf by the keys of their encodings.
+pub(crate) fn sorted<'a, T, I>(items: I) -> Vec<Part<'a, T>>
+where I: IntoIterator<Item = (Vec<u8>, T)> {
+    let mut parts: Vec<_> = items
+        .into_iter()
+        .map(|(bytes, item)| (OrderedKey::of_encoded(&bytes), item))
+        .collect();
+    parts.sort_by(|a, b| a.0.cmp(&b.0));
+    parts.into_iter().map(|(key, item)| Part::Item(key, item)).collect()
+}
+
+/// Reads the chunk which a meta tuple refers to, returning its parts in order
+pub(crate) type Load<'a, T> = fn(&'a dyn ChunkStore, &MetaTuple<'a>) -> Result<Vec<Part<'a, T>>, Error>;
+
+/// Iterates over the items of an ordered sequence, reading each chunk once it is reached. If a
+/// chunk cannot be read, the error is returned in place of its items, and iteration ends.
+pub(crate) struct Walk<'a, T> {
+    database: &'a dyn ChunkStore,
+    /// The parts which have not been reached yet, with the next one last
+    pending: Vec<Part<'a, T>>,
+    load: Load<'a, T>,
+}
+
+impl<'a, T> Walk<'a, T> {
+    pub fn new(database: &'a dyn ChunkStore, parts: Vec<Part<'a, T>>, load: Load<'a, T>) -> Self {
+        Walk{ database, pending: parts.into_iter().rev().collect(), load }
+    }
+}
+
+impl<'a, T> Iterator for Walk<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        loop {
+            match self.pending.pop()? {
+                Part::Item(_, item) => return Some(Ok(item)),
+                Part::Chunk(mt) => match (self.load)(self.database, &mt) {
+                    Ok(parts) => self.pending.extend(parts.into_iter().rev()),
+                    Err(err) => {
+                        self.pending.clear();
+                        return Some(Err(err));
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// The parts of a sequence which have not been reached yet, with the next one last
+struct Cursor<'a, T> {
+    database: &'a dyn ChunkStore,
+    pending: Vec<Part<'a, T>>,
+    load: Load<'a, T>,
+}
+
+impl<'a, T> Cursor<'a, T> {
+    fn new(database: &'a dyn ChunkStore, parts: Vec<Part<'a, T>>, load: Load<'a, T>) -> Self {
+        Cursor{ database, pending: parts.into_iter().rev().collect(), load }
+    }
+
+    /// Replaces the next part, which is a chunk, with the parts it holds.
+    fn expand(&mut self) -> Result<(), Error> {
+        if let Some(Part::Chunk(mt)) = self.pending.pop() {
+            let parts = (self.load)(self.database, &mt)?;
+            self.pending.extend(parts.into_iter().rev());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Left,
+    Right,
+}
+
+enum Step {
+    Left,
+    Right,
+    Both,
+    ExpandLeft,
+    ExpandRight,
+    ExpandBoth,
+}
+
+/// Iterates over the items which a merge of two ordered sequences keeps, in order, reading chunks
+/// as it goes. Only the chunk being read is held in memory, along with the meta tuples of the
+/// chunks which have not been reached yet. If a chunk cannot be read, the error is returned, and
+/// iteration ends.
+pub struct Merge<'a, T> {
+    left: Cursor<'a, T>,
+    right: Cursor<'a, T>,
+    keep: Keep,
+    /// The side whose next part is being kept whole, and how many parts it holds once it has been
+    taking: Option<(Side, usize)>,
+    done: bool,
+}
+
+impl<'a, T> Merge<'a, T> {
+    pub(crate) fn new(left: (&'a dyn ChunkStore, Vec<Part<'a, T>>, Load<'a, T>), right: (&'a dyn ChunkStore, Vec<Part<'a, T>>, Load<'a, T>), keep: Keep) -> Self {
+        Merge {
+            left: Cursor::new(left.0, left.1, left.2),
+            right: Cursor::new(right.0, right.1, right.2),
+            keep,
+            taking: None,
+            done: false,
+        }
+    }
+
+    fn cursor(&mut self, side: Side) -> &mut Cursor<'a, T> {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    /// Keeps every item of the next part of a side.
+    fn take(&mut self, side: Side) {
+        let end = self.cursor(side).pending.len() - 1;
+        self.taking = Some((side, end));
+    }
+
+    /// The next item of the part being kept, reading its chunks as they are reached.
+    fn next_taken(&mut self, side: Side, end: usize) -> Result<Option<T>, Error> {
+        let cursor = self.cursor(side);
+        while cursor.pending.len() > end {
+            match cursor.pending.pop().unwrap() {
+                Part::Item(_, item) => return Ok(Some(item)),
+                Part::Chunk(mt) => {
+                    let parts = (cursor.load)(cursor.database, &mt)?;
+                    cursor.pending.extend(parts.into_iter().rev());
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Moves past the next part of either sequence, returning whether the merge may keep more.
+    fn step(&mut self) -> Result<bool, Error> {
+        let keep = self.keep;
+        let step = match (self.left.pending.last(), self.right.pending.last()) {
+            (None, None) => return Ok(false),
+            (Some(_), None) if keep.left => Step::Left,
+            (None, Some(_)) if keep.right => Step::Right,
+            (Some(_), None) | (None, Some(_)) => return Ok(false),
+            (Some(&Part::Chunk(ref l)), Some(&Part::Chunk(ref r))) => {
+                if l.reference.target_hash() == r.reference.target_hash() {
+                    Step::Both
+                } else {
+                    match l.reference.height().cmp(&r.reference.height()) {
+                        Ordering::Greater => Step::ExpandLeft,
+                        Ordering::Less => Step::ExpandRight,
+                        Ordering::Equal => Step::ExpandBoth,
+                    }
+                }
+            }
+            // a chunk is ordered by its last key, so it ends before an item with a greater key
+            (Some(&Part::Chunk(ref l)), Some(&Part::Item(ref key, _))) =>
+                if l.key < *key { Step::Left } else { Step::ExpandLeft },
+            (Some(&Part::Item(ref key, _)), Some(&Part::Chunk(ref r))) =>
+                if r.key < *key { Step::Right } else { Step::ExpandRight },
+            (Some(&Part::Item(ref l, _)), Some(&Part::Item(ref r, _))) => match l.cmp(r) {
+                Ordering::Less => Step::Left,
+                Ordering::Greater => Step::Right,
+                Ordering::Equal => Step::Both,
+            },
+        };
+        match step {
+            Step::Left if keep.left => self.take(Side::Left),
+            Step::Left => { self.left.pending.pop(); }
+            Step::Right if keep.right => self.take(Side::Right),
+            Step::Right => { self.right.pending.pop(); }
+            Step::Both => {
+                self.right.pending.pop();
+                if keep.both {
+                    self.take(Side::Left);
+                } else {
+                    self.left.pending.pop();
+                }
+            }
+            Step::ExpandLeft => self.left.expand()?,
+            Step::ExpandRight => self.right.expand()?,
+            Step::ExpandBoth => {
+                self.left.expand()?;
+                self.right.expand()?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl<'a, T> Iterator for Merge<'a, T> {
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Result<T, Error>> {
+        while !self.done {
+            let next = match self.taking {
+                Some((side, end)) => self.next_taken(side, end).map(|item| {
+                    if item.is_none() {
+                        self.taking = None;
+                    }
+                    item
+                }),
+                None => self.step().map(|more| {
+                    self.done = !more;
+                    None
+                }),
+            };
+            match next {
+                Ok(Some(item)) => return Some(Ok(item)),
+                Ok(None) => {}
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+        None
+    }
+}
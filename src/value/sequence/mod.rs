@@ -1,19 +1,30 @@
 mod map;
 mod set;
 mod list;
+mod chunker;
+mod editor;
+mod merge;
 
-pub use self::map::NomsMap;
+pub use self::map::{NomsMap, MapIter};
 pub(crate) use self::map::Map;
 
-pub use self::set::NomsSet;
+pub use self::set::{NomsSet, SetIter};
 pub(crate) use self::set::Set;
 
-pub use self::list::NomsList;
+pub use self::list::{NomsList, ListIter};
 pub(crate) use self::list::List;
 
-use super::{NomsValue, Value, Ref, FromNoms, IntoNoms, Collection};
+pub use self::editor::{ListEditor, MapEditor, SetEditor};
 
-use hash::Hash;
+pub use self::merge::Merge;
+
+pub(crate) use self::chunker::{write_list, write_map, write_blob};
+
+use super::{NomsValue, Value, Ref, FromNoms, IntoNoms, Collection, Type, Kind};
+
+use hash::{self, Hash};
+use chunk::Chunk;
+use util::varint;
 use std::cmp::Ordering;
 
 // Somethingsomething prolly tree node. See the noms source for more (meta_sequence.go).
@@ -71,4 +82,68 @@ impl<'a> OrderedKey<'a> {
             _ => false,
         }
     }
+
+    /// The key of an encoded value. Primitive values are ordered by value, and all others by hash.
+    pub fn of_encoded(bytes: &[u8]) -> Self {
+        match bytes.first() {
+            Some(&k) if k == Kind::Boolean as u8 || k == Kind::Number as u8 || k == Kind::String as u8 =>
+                OrderedKey::by_value(Chunk::maybe(None, bytes.to_vec()).reader().read_value()),
+            _ => OrderedKey::by_hash(hash::hash(bytes)),
+        }
+    }
+}
+
+/// Encodes a sequence: its kind, its level in the tree (which is 0 for a leaf), and the number of
+/// items, followed by the encoded items themselves, which are meta tuples above the leaves.
+pub(crate) fn encode_sequence(kind: Kind, level: u64, len: usize, items: &[u8]) -> Vec<u8> {
+    let mut bytes = kind.into_noms();
+    bytes.extend(varint::encode_u64(level));
+    bytes.extend(varint::encode_u64(len as u64));
+    bytes.extend_from_slice(items);
+    bytes
+}
+
+/// Encodes a sequence which fits in a single chunk.
+pub(crate) fn encode_leaf(kind: Kind, len: usize, items: &[u8]) -> Vec<u8> {
+    encode_sequence(kind, 0, len, items)
+}
+
+/// Orders the entries of a map by key, keeping only the last entry with each key.
+pub(crate) fn sort_entries(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let mut keyed: Vec<_> = entries
+        .into_iter()
+        .map(|(k, v)| (OrderedKey::of_encoded(&k), k, v))
+        .collect();
+    // the sort is stable, so the last of the entries with equal keys is still last
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut sorted: Vec<(Vec<u8>, Vec<u8>)> = Vec::with_capacity(keyed.len());
+    for (_, k, v) in keyed {
+        match sorted.last_mut() {
+            Some(last) if last.0 == k => last.1 = v,
+            _ => sorted.push((k, v)),
+        }
+    }
+    sorted
+}
+
+/// Encodes the entries of a map as a single leaf. Entries are ordered by key, and only the last
+/// entry with each key is kept.
+pub(crate) fn encode_map_leaf(entries: Vec<(Vec<u8>, Vec<u8>)>) -> Vec<u8> {
+    let entries = sort_entries(entries);
+    let len = entries.len();
+    let items: Vec<u8> = entries.into_iter().flat_map(|(k, v)| k.into_iter().chain(v)).collect();
+    encode_leaf(Kind::Map, len, &items)
+}
+
+/// Encodes the items of a set as a single leaf. Items are ordered, and duplicates are removed.
+pub(crate) fn encode_set_leaf(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut keyed: Vec<_> = items
+        .into_iter()
+        .map(|v| (OrderedKey::of_encoded(&v), v))
+        .collect();
+    keyed.sort_by(|a, b| a.0.cmp(&b.0));
+    keyed.dedup_by(|a, b| a.1 == b.1);
+    let len = keyed.len();
+    let bytes: Vec<u8> = keyed.into_iter().flat_map(|(_, v)| v).collect();
+    encode_leaf(Kind::Set, len, &bytes)
 }
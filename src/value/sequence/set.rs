@@ -1,6 +1,8 @@
-use super::{NomsValue, Value, Ref, FromNoms, IntoNoms, MetaTuple, Collection};
+use super::{NomsValue, Value, Ref, FromNoms, IntoNoms, MetaTuple, Collection, Type, Kind};
+use super::merge::{sorted, Part, Keep, Walk, Merge};
 use database::ChunkStore;
 use chunk::Chunk;
+use error::Error;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 
@@ -18,9 +20,74 @@ where V: Eq + Hash + FromNoms<'a> + IntoNoms {
         NomsSet(set)
     }
 
+    /// Reads every item of the set. Panics if a chunk of the set cannot be fetched; collect the
+    /// items of `iter` into a `Result` to handle that instead.
     pub fn to_set(&self) -> HashSet<V> {
         self.0.to_set()
     }
+
+    /// Iterates over the items of the set, in order. The chunks of a large set are only fetched
+    /// once the iterator reaches them, so the whole set is never held in memory at once. If a
+    /// chunk cannot be fetched, the error is returned in place of its items, and iteration ends.
+    pub fn iter(&self) -> SetIter<'a, V> {
+        SetIter(Walk::new(self.0.database(), self.0.clone().parts(), load_parts))
+    }
+
+    /// The items which are in either set, in order.
+    ///
+    /// Like the other operations on two sets, this reads the sets as it is iterated over, and
+    /// only reads the chunks in which they differ, but it does not make a set: build one from the
+    /// items with a `SetEditor` to keep them.
+    pub fn union(&self, other: &Self) -> Merge<'a, V> {
+        self.merge(other, Keep{ left: true, both: true, right: true })
+    }
+
+    /// The items which are in both sets, in order.
+    pub fn intersection(&self, other: &Self) -> Merge<'a, V> {
+        self.merge(other, Keep{ left: false, both: true, right: false })
+    }
+
+    /// The items of this set which are not in the other, in order.
+    pub fn difference(&self, other: &Self) -> Merge<'a, V> {
+        self.merge(other, Keep{ left: true, both: false, right: false })
+    }
+
+    /// Whether every item of this set is in the other. Only the chunks up to the first item which
+    /// is not are read.
+    pub fn is_subset(&self, other: &Self) -> Result<bool, Error> {
+        match self.difference(other).next() {
+            Some(Err(err)) => Err(err),
+            Some(Ok(_)) => Ok(false),
+            None => Ok(true),
+        }
+    }
+
+    fn merge(&self, other: &Self, keep: Keep) -> Merge<'a, V> {
+        Merge::new(
+            (self.0.database(), self.0.clone().parts(), load_parts),
+            (other.0.database(), other.0.clone().parts(), load_parts),
+            keep,
+        )
+    }
+}
+
+/// An iterator over the items of a `NomsSet`, created by `NomsSet::iter`.
+pub struct SetIter<'a, V>(Walk<'a, V>);
+
+impl<'a, V> Iterator for SetIter<'a, V> {
+    type Item = Result<V, Error>;
+
+    fn next(&mut self) -> Option<Result<V, Error>> {
+        self.0.next()
+    }
+}
+
+/// Reads a chunk of a set, returning its items or the chunks it refers to.
+fn load_parts<'a, V>(database: &'a dyn ChunkStore, mt: &MetaTuple<'a>) -> Result<Vec<Part<'a, V>>, Error>
+where V: FromNoms<'a> + IntoNoms + Hash + Eq {
+    database
+        .get(mt.reference.target_hash())
+        .map(|value| value.export().transform::<NomsSet<'a, V>>().0.parts())
 }
 
 #[derive(Clone, Debug)]
@@ -80,6 +147,14 @@ where V: FromNoms<'a> + IntoNoms + Eq + Hash{
         }
     }
 
+    /// The items of a leaf in order, or the chunks of an inner node.
+    fn parts(self) -> Vec<Part<'a, V>> {
+        match self {
+            Set::Leaf{ cache, .. } => sorted(cache.into_iter().map(|v| (v.into_noms(), v))),
+            Set::Inner{ raw, .. } => raw.into_iter().map(Part::Chunk).collect(),
+        }
+    }
+
     pub fn transform<V2>(self) -> Set<'a, V2>
     where V2: FromNoms<'a> + IntoNoms + Eq + Hash {
         match self {
@@ -110,6 +185,7 @@ where V: FromNoms<'a> + IntoNoms + Hash + Eq {
     fn into_noms(&self) -> Vec<u8> {
         unimplemented!()
     }
+    fn noms_type() -> Type { Type::compound(Kind::Set, vec![V::noms_type()]) }
 }
 impl<'a, V> FromNoms<'a> for NomsSet<'a, V>
 where V: FromNoms<'a> + IntoNoms + Hash + Eq {
@@ -1,11 +1,13 @@
 //! The Noms Struct type
 use std::collections::HashMap;
 use chunk::Chunk;
-use super::{varint, NomsValue, Value, FromNoms, IntoNoms, Kind};
+use super::{varint, NomsValue, Value, FromNoms, IntoNoms, Kind, Type};
 
 pub trait NomsStruct<'a>: Sized {
     const NAME: &'static str;
 
+    /// The Noms type of this struct, including the types of each of its fields
+    fn noms_type() -> Type;
     fn from_prop_list(props: HashMap<String, NomsValue<'a>>) -> Option<Self>;
     fn to_prop_list(&self) -> HashMap<String, Vec<u8>>;
 }
@@ -32,16 +34,10 @@ impl<'a> PartialEq for Struct<'a> {
 
 impl<'a> IntoNoms for Struct<'a> {
     fn into_noms(&self) -> Vec<u8> {
-        let mut bytes = Kind::Struct.into_noms();
-        bytes.extend(varint::encode_u64(self.name.len() as u64));
-        bytes.extend(self.name.as_bytes());
-        bytes.extend(varint::encode_u64(self.props.len() as u64));
-        for (key, value) in &self.props {
-            bytes.extend(varint::encode_u64(key.len() as u64));
-            bytes.extend(key.as_bytes());
-            bytes.extend(value.into_noms());
-        }
-        bytes
+        encode_struct(
+            &self.name,
+            self.props.iter().map(|(key, value)| (key.clone(), value.into_noms())).collect(),
+        )
     }
 }
 impl<'a> FromNoms<'a> for Struct<'a> {
@@ -50,12 +46,30 @@ impl<'a> FromNoms<'a> for Struct<'a> {
     }
 }
 
+/// Encodes a struct from its name and the encoded values of its properties. The properties are
+/// written in order of their keys, as Noms expects.
+pub fn encode_struct(name: &str, props: HashMap<String, Vec<u8>>) -> Vec<u8> {
+    let mut props: Vec<_> = props.into_iter().collect();
+    props.sort_by(|a, b| a.0.cmp(&b.0));
+    let mut bytes = Kind::Struct.into_noms();
+    bytes.extend(varint::encode_u64(name.len() as u64));
+    bytes.extend(name.as_bytes());
+    bytes.extend(varint::encode_u64(props.len() as u64));
+    for (key, value) in props {
+        bytes.extend(varint::encode_u64(key.len() as u64));
+        bytes.extend(key.as_bytes());
+        bytes.extend(value);
+    }
+    bytes
+}
+
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub struct Empty;
 impl IntoNoms for Empty {
     fn into_noms(&self) -> Vec<u8> {
         Struct{ name: "".to_string(), props: HashMap::new() }.into_noms()
     }
+    fn noms_type() -> Type { <Self as NomsStruct>::noms_type() }
 }
 impl<'a> FromNoms<'a> for Empty {
     fn from_noms(chunk: &Chunk<'a>) -> Self {
@@ -64,6 +78,7 @@ impl<'a> FromNoms<'a> for Empty {
 }
 impl<'a> NomsStruct<'a> for Empty {
     const NAME: &'static str = "";
+    fn noms_type() -> Type { Type::structure("".to_string(), vec![], vec![], vec![]) }
     fn from_prop_list(_: HashMap<String, NomsValue<'a>>) -> Option<Self> { Some(Empty) }
     fn to_prop_list(&self) -> HashMap<String, Vec<u8>> { HashMap::new() }
 }
@@ -0,0 +1,125 @@
+//! Decides whether values of one type can be read as another, as `IsSubtype` does in Go Noms.
+//! Cycles are not unrolled: a cycle fits a cycle or struct of the same name.
+
+use super::{Kind, Type};
+
+impl Type {
+    /// Whether every value of the type `concrete` is also a value of this type
+    pub fn accepts(&self, concrete: &Type) -> bool {
+        self.differences(concrete).is_empty()
+    }
+
+    /// Describes where values of the type `concrete` do not fit this type, one line for each
+    /// difference, such as `.value[].count: expected Number, found String`. Paths are written as
+    /// in Noms: `.field` for struct fields, `[]` for the items of lists and sets and the values of
+    /// maps, `@key` for map keys and `@target` for the targets of refs.
+    pub fn differences(&self, concrete: &Type) -> Vec<String> {
+        let mut differences = vec![];
+        compare(self, concrete, "", &mut differences);
+        differences
+    }
+}
+
+fn compare(required: &Type, concrete: &Type, path: &str, differences: &mut Vec<String>) {
+    match (required.kind(), concrete.kind()) {
+        (Kind::Value, _) => {}
+        // every member of a union must fit, and the empty union of an empty collection always does
+        (_, Kind::Union) => for member in concrete.element_types() {
+            compare(required, member, path, differences);
+        },
+        (Kind::Union, _) => {
+            if !required.element_types().iter().any(|member| member.accepts(concrete)) {
+                differences.push(mismatch(required, concrete, path));
+            }
+        }
+        (Kind::Cycle, Kind::Cycle) | (Kind::Cycle, Kind::Struct) | (Kind::Struct, Kind::Cycle) => {
+            if required.name() != concrete.name() {
+                differences.push(mismatch(required, concrete, path));
+            }
+        }
+        (Kind::Struct, Kind::Struct) => {
+            match required.name() {
+                Some(name) if !name.is_empty() && Some(name) != concrete.name() => {
+                    differences.push(mismatch(required, concrete, path));
+                    return;
+                }
+                _ => {}
+            }
+            let fields = concrete.fields();
+            for field in required.fields() {
+                let path = format!("{}.{}", path, field.name);
+                match fields.iter().find(|f| f.name == field.name) {
+                    Some(found) => {
+                        if found.optional && !field.optional {
+                            differences.push(format!("{}: optional, but required", path));
+                        }
+                        compare(field.field_type, found.field_type, &path, differences);
+                    }
+                    None if field.optional => {}
+                    None => differences.push(format!("{}: missing, but required", path)),
+                }
+            }
+        }
+        (kind, other) if kind == other => {
+            let segments: &[&str] = match kind {
+                Kind::List | Kind::Set => &["[]"],
+                Kind::Map => &["@key", "[]"],
+                Kind::Ref => &["@target"],
+                _ => &[],
+            };
+            let elements = required.element_types().iter().zip(concrete.element_types());
+            for (segment, (r, c)) in segments.iter().zip(elements) {
+                compare(r, c, &format!("{}{}", path, segment), differences);
+            }
+        }
+        _ => differences.push(mismatch(required, concrete, path)),
+    }
+}
+
+fn mismatch(required: &Type, concrete: &Type, path: &str) -> String {
+    let path = if path.is_empty() { "." } else { path };
+    format!("{}: expected {}, found {}", path, required, concrete)
+}
+
+#[cfg(test)]
+mod tests {
+    fn differences(required: &str, concrete: &str) -> Vec<String> {
+        let required: super::Type = required.parse().unwrap();
+        required.differences(&concrete.parse().unwrap())
+    }
+
+    #[test]
+    fn fitting_types() {
+        for &(required, concrete) in &[
+            ("Value", "Map<String, Blob>"),
+            ("Number", "Number"),
+            ("List<Number>", "List<Union<>>"),
+            ("Number | String", "String"),
+            ("List<Number | String>", "List<Number | String>"),
+            ("Struct {}", "Struct Row { a: Number }"),
+            ("Struct Row { a?: Number, b: String }", "Struct Row { b: String, c: Bool }"),
+            ("Struct Commit { parents: Set<Ref<Cycle<Commit>>> }", "Struct Commit { parents: Set<Ref<Cycle<Commit>>> }"),
+        ] {
+            assert_eq!(differences(required, concrete), Vec::<String>::new(), "{} should accept {}", required, concrete);
+        }
+    }
+
+    #[test]
+    fn differing_types() {
+        assert_eq!(differences("Number", "String"), vec![".: expected Number, found String"]);
+        assert_eq!(differences("List<Number>", "List<Number | String>"), vec!["[]: expected Number, found String"]);
+        assert_eq!(differences("Struct Row {}", "Struct Other {}"), vec![".: expected Struct Row {}, found Struct Other {}"]);
+        assert_eq!(
+            differences(
+                "Map<String, Struct Row { a: Number, b: String, c: Bool }>",
+                "Map<Number, Struct Row { a?: Number, b: Number }>",
+            ),
+            vec![
+                "@key: expected String, found Number",
+                "[].a: optional, but required",
+                "[].b: expected String, found Number",
+                "[].c: missing, but required",
+            ],
+        );
+    }
+}
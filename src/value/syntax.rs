@@ -0,0 +1,324 @@
+//! Prints and parses types in the syntax of Go Noms, such as `List<Number | String>` or
+//! `Struct Row { countFemale: String, countMale?: Number }`.
+
+use std::fmt;
+use std::str::FromStr;
+use error::Error;
+use super::{Kind, Type};
+
+const INDENT: &'static str = "  ";
+
+/// Prints the type on one line, or with each field of a struct on its own line with `{:#}`, as
+/// `noms show` does.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let multiline = f.alternate();
+        write_type(f, self, 0, multiline)
+    }
+}
+
+fn write_type(f: &mut fmt::Formatter, t: &Type, depth: usize, multiline: bool) -> fmt::Result {
+    match t.kind() {
+        Kind::Boolean => write!(f, "Bool"),
+        Kind::List | Kind::Set | Kind::Ref | Kind::Map => {
+            write!(f, "{:?}<", t.kind())?;
+            for (i, element) in t.element_types().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_type(f, element, depth, multiline)?;
+            }
+            write!(f, ">")
+        }
+        Kind::Union if t.element_types().is_empty() => write!(f, "Union<>"),
+        Kind::Union => {
+            for (i, element) in t.element_types().iter().enumerate() {
+                if i > 0 {
+                    write!(f, " | ")?;
+                }
+                write_type(f, element, depth, multiline)?;
+            }
+            Ok(())
+        }
+        Kind::Cycle => write!(f, "Cycle<{}>", t.name().unwrap_or_default()),
+        Kind::Struct => {
+            write!(f, "Struct ")?;
+            match t.name() {
+                Some(name) if !name.is_empty() => write!(f, "{} {{", name)?,
+                _ => write!(f, "{{")?,
+            }
+            let fields = t.fields();
+            if fields.is_empty() {
+                return write!(f, "}}");
+            }
+            for (i, field) in fields.iter().enumerate() {
+                if multiline {
+                    write!(f, "\n{}", INDENT.repeat(depth + 1))?;
+                } else if i > 0 {
+                    write!(f, ", ")?;
+                } else {
+                    write!(f, " ")?;
+                }
+                write!(f, "{}{}: ", field.name, if field.optional { "?" } else { "" })?;
+                write_type(f, field.field_type, depth + 1, multiline)?;
+                if multiline {
+                    write!(f, ",")?;
+                }
+            }
+            if multiline {
+                write!(f, "\n{}}}", INDENT.repeat(depth))
+            } else {
+                write!(f, " }}")
+            }
+        }
+        kind => write!(f, "{:?}", kind),
+    }
+}
+
+/// Parses a type in the syntax it is printed in. Every field of a struct must have a different
+/// name, and the fields may be given in any order.
+impl FromStr for Type {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Type, Error> {
+        let mut parser = Parser{ text: s, tokens: tokenize(s)?, position: 0 };
+        let t = parser.union()?;
+        match parser.tokens.get(parser.position) {
+            Some(&(offset, _)) => Err(parser.error(offset, "the end of the type")),
+            None => Ok(t),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Name(String),
+    Symbol(char),
+}
+
+/// Splits the text into names and symbols, along with the offset each starts at.
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if "<>{},:?|".contains(c) {
+            tokens.push((offset, Token::Symbol(c)));
+        } else if c.is_ascii_alphanumeric() || c == '_' {
+            let mut name = c.to_string();
+            while let Some(&(_, c)) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_') {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            tokens.push((offset, Token::Name(name)));
+        } else {
+            return Err(Error::InvalidType(format!("{:?} is not expected at {} in {:?}", c, offset, text)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'s> {
+    text: &'s str,
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error(&self, offset: usize, expected: &str) -> Error {
+        Error::InvalidType(format!("Expected {} at {} in {:?}", expected, offset, self.text))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|&(_, ref token)| token)
+    }
+
+    /// The offset of the next token, or of the end of the text
+    fn offset(&self) -> usize {
+        self.tokens.get(self.position).map_or(self.text.len(), |&(offset, _)| offset)
+    }
+
+    /// Reads past the symbol if it is next, returning whether it was.
+    fn accept(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), Error> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(self.offset(), &format!("{:?}", symbol)))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some(&Token::Name(ref name)) => {
+                let name = name.clone();
+                self.position += 1;
+                Ok(name)
+            }
+            _ => Err(self.error(self.offset(), "a name")),
+        }
+    }
+
+    /// Parses types separated by `|`, which are a union if there is more than one.
+    fn union(&mut self) -> Result<Type, Error> {
+        let mut types = vec![self.single()?];
+        while self.accept('|') {
+            types.push(self.single()?);
+        }
+        if types.len() == 1 {
+            Ok(types.remove(0))
+        } else {
+            Ok(Type::union(types))
+        }
+    }
+
+    fn single(&mut self) -> Result<Type, Error> {
+        let offset = self.offset();
+        let name = self.name().map_err(|_| self.error(offset, "a type"))?;
+        match name.as_str() {
+            "Bool" => Ok(Type::primitive(Kind::Boolean)),
+            "Number" => Ok(Type::primitive(Kind::Number)),
+            "String" => Ok(Type::primitive(Kind::String)),
+            "Blob" => Ok(Type::primitive(Kind::Blob)),
+            "Value" => Ok(Type::primitive(Kind::Value)),
+            "Type" => Ok(Type::primitive(Kind::Type)),
+            "List" | "Set" | "Ref" => {
+                let kind = match name.as_str() {
+                    "List" => Kind::List,
+                    "Set" => Kind::Set,
+                    _ => Kind::Ref,
+                };
+                self.expect('<')?;
+                let element = self.union()?;
+                self.expect('>')?;
+                Ok(Type::compound(kind, vec![element]))
+            }
+            "Map" => {
+                self.expect('<')?;
+                let key = self.union()?;
+                self.expect(',')?;
+                let value = self.union()?;
+                self.expect('>')?;
+                Ok(Type::compound(Kind::Map, vec![key, value]))
+            }
+            "Union" => {
+                self.expect('<')?;
+                let mut types = vec![];
+                while !self.accept('>') {
+                    if !types.is_empty() {
+                        self.expect(',')?;
+                    }
+                    types.push(self.union()?);
+                }
+                Ok(Type::union(types))
+            }
+            "Cycle" => {
+                self.expect('<')?;
+                let name = self.name()?;
+                self.expect('>')?;
+                Ok(Type::cycle(name))
+            }
+            "Struct" => self.structure(),
+            _ => Err(self.error(offset, "a type")),
+        }
+    }
+
+    /// Parses the rest of a struct type, after `Struct`.
+    fn structure(&mut self) -> Result<Type, Error> {
+        let name = match self.peek() {
+            Some(&Token::Name(_)) => self.name()?,
+            _ => String::new(),
+        };
+        self.expect('{')?;
+        let mut fields: Vec<(String, Type, bool)> = vec![];
+        while !self.accept('}') {
+            if !fields.is_empty() {
+                self.expect(',')?;
+                if self.accept('}') {
+                    break;
+                }
+            }
+            let offset = self.offset();
+            let field = self.name()?;
+            if fields.iter().any(|f| f.0 == field) {
+                return Err(Error::InvalidType(format!("The field {} at {} is given more than once in {:?}", field, offset, self.text)));
+            }
+            let optional = self.accept('?');
+            self.expect(':')?;
+            fields.push((field, self.union()?, optional));
+        }
+        let mut keys = vec![];
+        let mut types = vec![];
+        let mut optional = vec![];
+        for (key, t, o) in fields {
+            keys.push(key);
+            types.push(t);
+            optional.push(o);
+        }
+        Ok(Type::structure(name, keys, types, optional))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Type {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for s in &[
+            "Bool",
+            "List<Number | String>",
+            "Map<String, Set<Ref<Blob>>>",
+            "Union<>",
+            "Struct {}",
+            "Struct Row { countFemale: String, countMale?: Number }",
+            "Struct Commit { meta: Struct {}, parents: Set<Ref<Cycle<Commit>>>, value: Value }",
+        ] {
+            assert_eq!(parse(s).to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn multiline() {
+        let t = parse("List<Struct Row { name: String, tags: Set<String> | Struct Tag { id: Number } }>");
+        assert_eq!(
+            format!("{:#}", t),
+            "List<Struct Row {\n  name: String,\n  tags: Set<String> | Struct Tag {\n    id: Number,\n  },\n}>",
+        );
+        assert_eq!(parse(&format!("{:#}", t)), t);
+    }
+
+    #[test]
+    fn parsed_structs() {
+        let t = parse("Struct Row { b: Number, a?: String, }");
+        let fields = t.fields();
+        assert_eq!(t.name(), Some("Row"));
+        assert_eq!(fields[0].name, "a");
+        assert!(fields[0].optional);
+        assert_eq!(fields[1].field_type, &Type::primitive(Kind::Number));
+    }
+
+    #[test]
+    fn errors() {
+        for s in &["", "List<Number", "Map<String>", "Struct Row { a: Number, a: String }", "Number |", "Strings", "List<Number>>", "Bool!"] {
+            assert!(s.parse::<Type>().is_err(), "{:?} should not parse", s);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::blob;
+use nomrs::value::IntoNoms;
+use std::cmp::min;
+use std::io::{self, Read};
+
+mod common;
+
+use common::{database, writable_database};
+
+/// Bytes which are not too regular, so that the blob is split into chunks
+fn data(len: usize) -> Vec<u8> {
+    let mut x = 1u32;
+    (0..len).map(|_| { x = x.wrapping_mul(1103515245).wrapping_add(12345); (x >> 16) as u8 }).collect()
+}
+
+#[test]
+fn small_blobs() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let value = blob::write(&db, &b"hello"[..]).unwrap();
+    assert_eq!(blob::len(&value), Some(5));
+    let mut output = vec![];
+    blob::export(&value, &mut output).unwrap();
+    assert_eq!(output, b"hello");
+    assert_eq!(blob::len(&db.value_from("hello")), None);
+}
+
+#[test]
+fn large_blobs_are_chunked() {
+    let noms = Noms::new();
+    let (db, requests) = writable_database(&noms);
+    let input = data(100_000);
+    let value = blob::write(&db, &input[..]).unwrap();
+    assert_eq!(blob::len(&value), Some(100_000));
+    let mut output = vec![];
+    blob::export(&value, &mut output).unwrap();
+    assert!(output == input);
+
+    let ds = db.dataset_or_empty("file").unwrap();
+    db.commit_value(ds, value).unwrap();
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[1].starts_with("POST /writeValue/ "));
+}
+
+/// Reads the data a few bytes at a time
+struct Trickle<'a>(&'a [u8]);
+
+impl<'a> Read for Trickle<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = min(7, min(buf.len(), self.0.len()));
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+#[test]
+fn blobs_are_chunked_as_they_are_read() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let input = data(100_000);
+    let whole = blob::write(&db, &input[..]).unwrap();
+    let trickled = blob::write(&db, Trickle(&input)).unwrap();
+    assert!(whole.into_noms() == trickled.into_noms());
+    let mut output = vec![];
+    blob::export(&trickled, &mut output).unwrap();
+    assert!(output == input);
+}
@@ -0,0 +1,94 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::database::CachingChunkStore;
+use nomrs::dataset::Dataset;
+use nomrs::value::{Empty, NomsList, ListEditor};
+use std::env::temp_dir;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+mod common;
+
+use common::server;
+
+/// Writes a list large enough to be split into chunks, and commits it to the dataset.
+fn commit_list<D: Database>(db: &D, id: &str, len: i64) {
+    let mut editor = ListEditor::new(db);
+    editor.splice(0, 0, 0..len).unwrap();
+    let list = editor.build().unwrap();
+    db.commit_value(db.dataset_or_empty(id).unwrap(), list).unwrap();
+}
+
+/// Reads every item of the dataset's list, so that all of its chunks are cached.
+fn read_list<D: Database>(db: &D, id: &str) -> usize {
+    let ds: Dataset<Empty, NomsList<i64>> = db.dataset(id).unwrap();
+    ds.head_value().unwrap().iter().count()
+}
+
+/// An empty directory for the cache of a test, which is not used by any other test run
+fn directory(name: &str) -> PathBuf {
+    let directory = temp_dir().join(format!("nomrs-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    directory
+}
+
+#[test]
+fn gc_removes_unreachable_chunks() {
+    let noms = Noms::new();
+    let server = server();
+    let directory = directory("gc-removes-unreachable-chunks");
+    let db = CachingChunkStore::new(noms.database().http(&server.address).unwrap(), &directory, 1 << 30).unwrap();
+
+    commit_list(&db, "kept", 5_000);
+    commit_list(&db, "deleted", 20_000);
+    assert_eq!(read_list(&db, "kept"), 5_000);
+    assert_eq!(read_list(&db, "deleted"), 20_000);
+    let deleted: Dataset = db.dataset("deleted").unwrap();
+    db.delete(deleted).unwrap();
+    // caches the new root, which the collection starts from
+    assert_eq!(db.datasets().unwrap().to_map().len(), 1);
+
+    let files = || fs::read_dir(&directory).unwrap().count();
+    let cached = files();
+    let fetched = server.fetched().len();
+    let report = db.gc(true).unwrap();
+    assert!(report.unreachable_chunks > 10);
+    assert!(report.unreachable_bytes > 0);
+    assert_eq!(report.reachable_chunks + report.unreachable_chunks, cached);
+    assert_eq!(files(), cached);
+
+    assert_eq!(db.gc(false).unwrap(), report);
+    assert_eq!(files(), report.reachable_chunks);
+    assert_eq!(read_list(&db, "kept"), 5_000);
+    let after = db.gc(false).unwrap();
+    assert_eq!(after.unreachable_chunks, 0);
+    assert_eq!(after.reachable_bytes, report.reachable_bytes);
+    // every chunk the collections read was cached
+    assert_eq!(server.fetched().len(), fetched);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn gc_does_not_fetch_chunks() {
+    let noms = Noms::new();
+    let server = server();
+    commit_list(&server.connect(&noms), "list", 20_000);
+
+    let directory = directory("gc-does-not-fetch-chunks");
+    let db = CachingChunkStore::new(noms.database().http(&server.address).unwrap(), &directory, 1 << 30).unwrap();
+    let fetched = server.fetched().len();
+    assert_eq!(db.gc(false).unwrap(), Default::default());
+    assert_eq!(server.fetched().len(), fetched);
+
+    // the list's chunks cannot be found once the root is not cached
+    assert_eq!(read_list(&db, "list"), 20_000);
+    let cached = fs::read_dir(&directory).unwrap().count();
+    fs::remove_file(directory.join(db.root_hash().unwrap().to_string().trim())).unwrap();
+    let fetched = server.fetched().len();
+    let report = db.gc(false).unwrap();
+    assert_eq!(server.fetched().len(), fetched);
+    assert_eq!((report.reachable_chunks, report.unreachable_chunks), (0, cached - 1));
+    fs::remove_dir_all(directory).unwrap();
+}
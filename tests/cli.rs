@@ -0,0 +1,148 @@
+extern crate nomrs;
+#[macro_use] extern crate serde_json;
+
+use serde_json::Value as Json;
+use std::env::temp_dir;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod common;
+
+use common::{server, server_with};
+
+/// Runs the `nomrs` binary with the arguments.
+fn nomrs(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_nomrs")).args(args).output().unwrap()
+}
+
+/// Runs the `nomrs` binary, which must succeed, returning what it printed.
+fn run(args: &[&str]) -> String {
+    let output = nomrs(args);
+    assert!(output.status.success(), "nomrs {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// Runs the `nomrs` binary, which must fail with the status, returning what it printed as the
+/// reason.
+fn fail(args: &[&str], status: i32) -> String {
+    let output = nomrs(args);
+    assert_eq!(output.status.code(), Some(status), "nomrs {:?} printed {}", args, String::from_utf8_lossy(&output.stdout));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+/// A directory for the files of a test, which is empty and not used by any other test run
+fn directory(test: &str) -> PathBuf {
+    let directory = temp_dir().join(format!("nomrs-cli-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+#[test]
+fn usage() {
+    assert!(fail(&[], 2).starts_with("Usage:"));
+    assert!(fail(&["show"], 2).starts_with("Usage:"));
+    assert!(fail(&["ds", "a", "b", "c"], 2).starts_with("Usage:"));
+}
+
+#[test]
+fn invalid_specs() {
+    let server = server();
+    let database = format!("http://{}", server.address);
+    assert_eq!(fail(&["log", &database], 1), format!("Invalid spec: {} does not name a dataset\n", database));
+    assert_eq!(fail(&["show", "ftp://localhost::a"], 1), "Invalid spec: ftp://localhost is not an HTTP or HTTPS database\n");
+    assert_eq!(fail(&["show", &format!("{}::a b", database)], 1), "Invalid spec: \"a b\" is not a valid dataset name\n");
+    assert_eq!(fail(&["show", &format!("{}::missing", database)], 1), "There is no dataset named missing\n");
+    // no request is made for a spec which cannot be parsed
+    assert!(server.requests.lock().unwrap().iter().all(|request| request.starts_with("GET /root/")));
+}
+
+#[test]
+fn json_import_and_export() {
+    let server = server();
+    let directory = directory("json");
+    let file = directory.join("people.json");
+    fs::write(&file, r#"[{"name": "Ann", "age": 31}, {"name": "Bob"}]"#).unwrap();
+    let dataset = format!("http://{}::people", server.address);
+
+    let head: Json = serde_json::from_str(&run(&["--json", "json", "import", "--structs", file.to_str().unwrap(), &dataset])).unwrap();
+    assert_eq!(head["dataset"], "people");
+    assert_eq!(head["head"].as_str().unwrap().len(), 32);
+    assert_eq!(run(&["json", "export", &dataset]), "[{\"age\":31,\"name\":\"Ann\"},{\"name\":\"Bob\"}]\n");
+    assert_eq!(run(&["--json", "ds", &format!("http://{}", server.address)]), "[\"people\"]\n");
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn log_and_fetch_errors() {
+    let failing = Arc::new(AtomicBool::new(false));
+    let fail_fetches = failing.clone();
+    let server = server_with(move |line, _| match line.starts_with("POST /getRefs/") && fail_fetches.load(Ordering::SeqCst) {
+        true => Some((500, vec![])),
+        false => None,
+    });
+    let directory = directory("log");
+    let database = format!("http://{}", server.address);
+    for &(name, json) in &[("b", "1"), ("a", "2"), ("b", "3")] {
+        let file = directory.join(name);
+        fs::write(&file, json).unwrap();
+        run(&["json", "import", file.to_str().unwrap(), &format!("{}::{}", database, name)]);
+    }
+    let dataset = format!("{}::b", database);
+
+    assert_eq!(run(&["--json", "ds", &database]), "[\"a\",\"b\"]\n");
+    let commits: Json = serde_json::from_str(&run(&["--json", "log", &dataset])).unwrap();
+    let commits = commits.as_array().unwrap();
+    assert_eq!(commits.len(), 2);
+    assert_eq!(commits[0]["parents"], json!([commits[1]["hash"]]));
+    assert_eq!(commits[1]["parents"], json!([]));
+
+    failing.store(true, Ordering::SeqCst);
+    let error = "Server responded with 500 Internal Server Error\n";
+    assert_eq!(fail(&["log", &dataset], 1), error);
+    assert_eq!(fail(&["ds", &database], 1), error);
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn diff() {
+    let server = server();
+    let directory = directory("diff");
+    let database = format!("http://{}", server.address);
+    for &(name, json) in &[("before", r#"{"a": 1, "b": [1, 2], "c": "same"}"#), ("after", r#"{"b": [1, 3, 4], "c": "same", "d": true}"#)] {
+        let file = directory.join(name);
+        fs::write(&file, json).unwrap();
+        run(&["json", "import", file.to_str().unwrap(), &format!("{}::{}", database, name)]);
+    }
+    let (before, after) = (format!("{}::before", database), format!("{}::after", database));
+
+    let changes: Json = serde_json::from_str(&run(&["--json", "diff", &before, &after])).unwrap();
+    assert_eq!(changes, json!([
+        { "path": ".a", "before": 1, "after": null },
+        { "path": ".b[1]", "before": 2, "after": 3 },
+        { "path": ".b[2]", "before": null, "after": 4 },
+        { "path": ".d", "before": null, "after": true },
+    ]));
+    assert_eq!(run(&["diff", &before, &after]), "- .a: 1\n- .b[1]: 2\n+ .b[1]: 3\n+ .b[2]: 4\n+ .d: true\n");
+    assert_eq!(run(&["--json", "diff", &before, &before]), "[]\n");
+    fs::remove_dir_all(directory).unwrap();
+}
+
+#[test]
+fn blobs() {
+    let server = server();
+    let directory = directory("blobs");
+    let (input, output) = (directory.join("input"), directory.join("output"));
+    let data: Vec<u8> = (0..50_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+    fs::write(&input, &data).unwrap();
+    let dataset = format!("http://{}::file", server.address);
+
+    run(&["blob", "put", input.to_str().unwrap(), &dataset]);
+    run(&["blob", "export", &dataset, output.to_str().unwrap()]);
+    assert!(fs::read(&output).unwrap() == data);
+    assert_eq!(run(&["--json", "show", &dataset]), "{\"blob\":50000}\n");
+    fs::remove_dir_all(directory).unwrap();
+}
@@ -0,0 +1,38 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::csv::{Importer, ColumnType};
+use nomrs::codegen::dataset_source;
+use nomrs::error::Error;
+
+mod common;
+
+use common::writable_database;
+
+#[test]
+fn dataset_types() {
+    let noms = Noms::new();
+    let (db, _) = writable_database(&noms);
+    let rows = Importer::new()
+        .column_types(vec![ColumnType::String, ColumnType::Number])
+        .import(&db, "countFemale,countMale\n3,\n4,5\n".as_bytes())
+        .unwrap();
+    db.commit_value(db.dataset_or_empty("names-2020").unwrap(), rows).unwrap();
+
+    assert_eq!(
+        dataset_source(&db, "names-2020").unwrap(),
+        "pub type Names2020Value<'a> = ::nomrs::value::NomsList<'a, Row>;\n\
+         \n\
+         pub type Names2020Meta = ::nomrs::value::Empty;\n\
+         \n\
+         #[derive(Clone, Debug, Noms)]\n\
+         pub struct Row {\n    \
+             pub count_female: String,\n    \
+             pub count_male: Option<f64>,\n\
+         }\n",
+    );
+    match dataset_source(&db, "missing") {
+        Err(Error::NoDataset(_)) => {}
+        other => panic!("expected no dataset, got {:?}", other),
+    }
+}
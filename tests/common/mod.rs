@@ -0,0 +1,225 @@
+//! Helpers shared by the integration tests
+
+extern crate snap;
+
+use nomrs::{Noms, Database};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// What the server responds with at `/stats/`
+pub const STATS: &'static str = "GetLatency: Mean 1.5ms\nPutLatency: Mean 3ms\n";
+
+const EMPTY_HASH: &'static str = "00000000000000000000000000000000";
+const HASH_LEN: usize = 20;
+
+/// Connects to a new server. This is enough to encode values and decode them again.
+pub fn database(noms: &Noms) -> impl Database {
+    writable_database(noms).0
+}
+
+/// Connects to a new server, returning the first line of every request it receives.
+pub fn writable_database(noms: &Noms) -> (impl Database, Arc<Mutex<Vec<String>>>) {
+    let server = server();
+    (noms.database().http(&server.address).unwrap(), server.requests)
+}
+
+/// A Noms server which keeps its chunks in memory. Its root starts out empty. It accepts every
+/// chunk written to it, and moves its root like a Noms server does. It reports `STATS` at
+/// `/stats/`.
+pub struct Server {
+    pub address: String,
+    /// The first line of every request received
+    pub requests: Arc<Mutex<Vec<String>>>,
+    store: Arc<Mutex<Store>>,
+}
+
+#[derive(Default)]
+struct Store {
+    root: String,
+    chunks: HashMap<String, Vec<u8>>,
+    /// The hashes of the chunks sent to clients, and written by them, in order
+    fetched: Vec<String>,
+    written: Vec<String>,
+}
+
+impl Server {
+    /// The hashes of the chunks which clients have fetched, in order
+    pub fn fetched(&self) -> Vec<String> {
+        self.store.lock().unwrap().fetched.clone()
+    }
+
+    /// The hashes of the chunks which clients have written, in order
+    pub fn written(&self) -> Vec<String> {
+        self.store.lock().unwrap().written.clone()
+    }
+
+    /// Connects a new client, which has not fetched any chunks yet.
+    pub fn connect(&self, noms: &Noms) -> impl Database {
+        noms.database().http(&self.address).unwrap()
+    }
+}
+
+/// Starts a new server.
+pub fn server() -> Server {
+    server_with(|_, _| None)
+}
+
+/// Starts a server like `server`, except that `handler` is given the first line and body of each
+/// request first. If it returns a status and body, they are sent instead.
+pub fn server_with<F>(handler: F) -> Server
+where F: Fn(&str, &[u8]) -> Option<(u16, Vec<u8>)> + Send + 'static {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let requests = Arc::new(Mutex::new(vec![]));
+    let store = Arc::new(Mutex::new(Store{ root: EMPTY_HASH.to_string(), ..Store::default() }));
+    let (recorded, stored) = (requests.clone(), store.clone());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            respond(stream.unwrap(), &recorded, &stored, &handler);
+        }
+    });
+    Server{ address: format!("127.0.0.1:{}", port), requests, store }
+}
+
+fn respond(mut stream: TcpStream, recorded: &Mutex<Vec<String>>, store: &Mutex<Store>, handler: &Fn(&str, &[u8]) -> Option<(u16, Vec<u8>)>) {
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    let head_len = loop {
+        if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        let len = stream.read(&mut buf).unwrap();
+        request.extend_from_slice(&buf[..len]);
+    };
+    let head = String::from_utf8_lossy(&request[..head_len]).into_owned();
+    let body_len = head
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(2, ':');
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if name.eq_ignore_ascii_case("content-length") => value.trim().parse().ok(),
+                _ => None,
+            }
+        })
+        .next()
+        .unwrap_or(0);
+    while request.len() < head_len + body_len {
+        let len = stream.read(&mut buf).unwrap();
+        request.extend_from_slice(&buf[..len]);
+    }
+    let line = head.lines().next().unwrap().to_string();
+    let body = &request[head_len..];
+    let (status, response) = handler(&line, body)
+        .unwrap_or_else(|| serve(&line, body, &mut store.lock().unwrap()));
+    // recorded before responding, so that the client never sees a response to an unrecorded request
+    recorded.lock().unwrap().push(line);
+    write!(stream, "HTTP/1.1 {} -\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", status, response.len()).unwrap();
+    stream.write_all(&response).unwrap();
+    // closing with unread data would reset the connection, so wait for the client to close first
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+    while let Ok(len) = stream.read(&mut buf) {
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+/// Responds to a request the way a Noms server does.
+fn serve(line: &str, body: &[u8], store: &mut Store) -> (u16, Vec<u8>) {
+    let path = line.split_whitespace().nth(1).unwrap_or("");
+    if line.starts_with("GET /root/") {
+        (200, store.root.clone().into_bytes())
+    } else if line.starts_with("POST /root/") {
+        let query = |name: &str| path
+            .split(|c| c == '?' || c == '&')
+            .find(|field| field.starts_with(&format!("{}=", name)))
+            .map(|field| field[name.len() + 1..].to_string());
+        if query("last").as_ref() != Some(&store.root) {
+            return (409, vec![]);
+        }
+        store.root = query("current").unwrap();
+        (200, vec![])
+    } else if line.starts_with("POST /writeValue/") {
+        let mut data = vec![];
+        snap::read::FrameDecoder::new(body).read_to_end(&mut data).unwrap();
+        let mut i = 0;
+        while i < data.len() {
+            let hash = encode_hash(&data[i..i + HASH_LEN]);
+            let len = read_u32(&data[i + HASH_LEN..]) as usize;
+            i += HASH_LEN + 4;
+            store.written.push(hash.clone());
+            store.chunks.insert(hash, data[i..i + len].to_vec());
+            i += len;
+        }
+        (200, vec![])
+    } else if line.starts_with("POST /getRefs/") {
+        let mut response = vec![];
+        for raw in body[4..].chunks(HASH_LEN) {
+            let hash = encode_hash(raw);
+            if let Some(data) = store.chunks.get(&hash) {
+                response.extend_from_slice(raw);
+                response.extend_from_slice(&[(data.len() >> 24) as u8, (data.len() >> 16) as u8, (data.len() >> 8) as u8, data.len() as u8]);
+                response.extend_from_slice(data);
+                store.fetched.push(hash);
+            }
+        }
+        (200, response)
+    } else if line.starts_with("POST /hasRefs/") {
+        let response: String = body[4..]
+            .chunks(HASH_LEN)
+            .map(encode_hash)
+            .map(|hash| format!("{} {}\n", hash, store.chunks.contains_key(&hash)))
+            .collect();
+        (200, response.into_bytes())
+    } else if line.starts_with("GET /stats/") {
+        (200, STATS.as_bytes().to_vec())
+    } else {
+        (200, vec![])
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    bytes[..4].iter().fold(0, |n, &b| n << 8 | b as u32)
+}
+
+/// Encodes a hash the way Noms writes it, in base 32
+fn encode_hash(raw: &[u8]) -> String {
+    const SYMBOLS: &'static [u8] = b"0123456789abcdefghijklmnopqrstuv";
+    let mut encoded = String::with_capacity(raw.len() * 8 / 5);
+    let (mut bits, mut len) = (0u32, 0);
+    for &byte in raw {
+        bits = bits << 8 | byte as u32;
+        len += 8;
+        while len >= 5 {
+            len -= 5;
+            encoded.push(SYMBOLS[(bits >> len) as usize & 31] as char);
+        }
+    }
+    encoded
+}
+
+/// The decoded value of a field of an `application/x-www-form-urlencoded` body
+pub fn form_value(body: &[u8], name: &str) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let value = body.split('&').find(|field| field.starts_with(&format!("{}=", name)))?;
+    let value = value[name.len() + 1..].as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < value.len() {
+        match value[i] {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                decoded.push(u8::from_str_radix(&String::from_utf8_lossy(&value[i + 1..i + 3]), 16).unwrap());
+                i += 2;
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    Some(String::from_utf8(decoded).unwrap())
+}
@@ -0,0 +1,148 @@
+extern crate nomrs;
+
+use nomrs::Noms;
+use nomrs::csv::{self, Importer, ColumnType};
+use nomrs::value::{NomsList, NomsMap, NomsValue, Type, Kind};
+
+mod common;
+
+use common::{database, writable_database};
+
+fn export(value: &NomsValue) -> String {
+    let mut output = vec![];
+    csv::export(value, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let input = "Name,Year of birth,alive\nAnn,1950,true\n\"Bob, Jr.\",,f\n";
+    let value = Importer::new()
+        .column_types(vec![ColumnType::String, ColumnType::Number, ColumnType::Bool])
+        .import(&db, input.as_bytes())
+        .unwrap();
+    assert_eq!(export(&value), "Name,alive,yearOfBirth\nAnn,true,1950\n\"Bob, Jr.\",false,\n");
+}
+
+#[test]
+fn large_lists_are_chunked() {
+    let noms = Noms::new();
+    let (db, requests) = writable_database(&noms);
+    let mut input = "n;square\n".to_string();
+    for n in 0..1000 {
+        input.push_str(&format!("{};{}\n", n, n * n));
+    }
+    let value = Importer::new()
+        .delimiter(b';')
+        .column_types(vec![ColumnType::Number, ColumnType::Number])
+        .import(&db, input.as_bytes())
+        .unwrap();
+    assert_eq!(value.clone().transform::<NomsList>().to_vec().len(), 1000);
+
+    let mut expected = "n,square\n".to_string();
+    for n in 0..1000 {
+        expected.push_str(&format!("{},{}\n", n, n * n));
+    }
+    assert_eq!(export(&value), expected);
+    // besides reading the root, nothing is sent until a commit
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn keyed_rows() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let value = Importer::new()
+        .header(vec!["id".to_string(), "name".to_string()])
+        .column_types(vec![ColumnType::Number])
+        .key_column("id")
+        .import(&db, "2,Bob\n1,Ann\n2,Cat\n".as_bytes())
+        .unwrap();
+    assert_eq!(value.clone().transform::<NomsMap<NomsValue, NomsValue>>().to_map().len(), 2);
+    assert_eq!(export(&value), "id,name\n1,Ann\n2,Cat\n");
+
+    let missing = Importer::new().key_column("nope").import(&db, "id\n1\n".as_bytes());
+    assert!(missing.is_err());
+}
+
+#[test]
+fn large_keyed_rows() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let mut input = "id,square\n".to_string();
+    for n in 0..1000 {
+        let id = n * 7 % 1000;
+        input.push_str(&format!("{},{}\n", id, id * id));
+    }
+    let value = Importer::new()
+        .column_types(vec![ColumnType::Number, ColumnType::Number])
+        .key_column("id")
+        .import(&db, input.as_bytes())
+        .unwrap();
+    let map = value.clone().transform::<NomsMap<i64, NomsValue>>();
+    assert_eq!(map.get(12i64).unwrap().unwrap().to_string(), "Row { id: 12, square: 144 }");
+    assert!(map.get(1000i64).unwrap().is_none());
+
+    let mut expected = "id,square\n".to_string();
+    for n in 0..1000 {
+        expected.push_str(&format!("{},{}\n", n, n * n));
+    }
+    assert_eq!(export(&value), expected);
+}
+
+#[test]
+fn commit_records_meta() {
+    let noms = Noms::new();
+    let (db, requests) = writable_database(&noms);
+    let ds = Importer::new()
+        .commit(&db, "people", "name\nAnn\n".as_bytes(), "people.csv")
+        .unwrap();
+    assert_eq!(ds.id(), "people");
+    assert_eq!(ds.head_ref().height(), 1);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[1].starts_with("POST /writeValue/ "));
+    assert!(requests[2].starts_with("POST /root/"));
+}
+
+#[test]
+fn types_of_chunked_lists() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let import = |rows: usize| {
+        let mut input = "n,note\n".to_string();
+        for n in 0..rows {
+            input.push_str(&format!("{},{}\n", n, if n % 2 == 0 { n.to_string() } else { String::new() }));
+        }
+        Importer::new()
+            .column_types(vec![ColumnType::Number, ColumnType::Number])
+            .import(&db, input.as_bytes())
+            .unwrap()
+    };
+    let small = import(2);
+    let large = import(1000);
+    assert_eq!(large.type_of().unwrap(), small.type_of().unwrap());
+    assert_ne!(small.type_of().unwrap(), import(1).type_of().unwrap());
+}
+
+#[test]
+fn schema() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let value = Importer::new()
+        .column_types(vec![ColumnType::String, ColumnType::Number])
+        .import(&db, "countFemale,countMale\n3,\n4,5\n".as_bytes())
+        .unwrap();
+    let list_type = value.type_of().unwrap();
+    assert_eq!(list_type.kind(), Kind::List);
+    assert_eq!(list_type.to_string(), "List<Struct Row { countFemale: String, countMale?: Number }>");
+
+    let row = &list_type.element_types()[0];
+    assert_eq!(row.name(), Some("Row"));
+    let optional: Vec<(&str, bool)> = row.fields().iter().map(|f| (f.name, f.optional)).collect();
+    assert_eq!(optional, vec![("countFemale", false), ("countMale", true)]);
+    assert_eq!(list_type.to_string().parse::<Type>().unwrap(), list_type);
+}
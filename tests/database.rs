@@ -0,0 +1,198 @@
+extern crate nomrs;
+#[macro_use] extern crate nomrs_derive;
+
+use nomrs::{Noms, Database};
+use nomrs::dataset::Dataset;
+use nomrs::value::{Empty, NomsList, NomsValue, ListEditor, Ref, Commit};
+use nomrs::csv::{Importer, ColumnType};
+use nomrs::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod common;
+
+use common::{writable_database, server_with};
+
+fn dataset<'a, D: Database>(db: &'a D, id: &str) -> Result<Dataset<'a>, Error> {
+    db.dataset(id)
+}
+
+#[test]
+fn delete_and_set_head() {
+    let noms = Noms::new();
+    let (db, _) = writable_database(&noms);
+    let first = db.commit_value(db.dataset_or_empty("a").unwrap(), db.value_from("first")).unwrap();
+    let first_ref = first.head_ref().clone();
+    db.commit_value(db.dataset_or_empty("b").unwrap(), db.value_from("other")).unwrap();
+    let mut names: Vec<_> = db.datasets().unwrap().to_map().into_iter().map(|(k, _)| k).collect();
+    names.sort();
+    assert_eq!(names, vec!["a", "b"]);
+
+    let b = dataset(&db, "b").unwrap();
+    let b = db.set_head(b, first_ref.clone()).unwrap();
+    assert_eq!(b.head_ref(), &first_ref);
+    assert_eq!(dataset(&db, "b").unwrap().head_value().unwrap(), db.value_from("first"));
+
+    let a = db.delete(dataset(&db, "a").unwrap()).unwrap();
+    assert!(a.head_ref().is_empty());
+    match dataset(&db, "a") {
+        Err(Error::NoDataset(_)) => {}
+        other => panic!("expected no dataset, got {:?}", other),
+    }
+    assert_eq!(db.datasets().unwrap().to_map().len(), 1);
+}
+
+#[test]
+fn failed_writes_are_sent_again() {
+    let noms = Noms::new();
+    let failed = AtomicBool::new(false);
+    let server = server_with(move |line, _| match line.starts_with("POST /writeValue/") && !failed.swap(true, Ordering::SeqCst) {
+        true => Some((500, vec![])),
+        false => None,
+    });
+    let db = server.connect(&noms);
+    let mut editor = ListEditor::new(&db);
+    editor.splice(0, 0, 0..5_000i64).unwrap();
+    let list = editor.build().unwrap();
+    match db.commit_value(db.dataset_or_empty("a").unwrap(), list.clone()) {
+        Err(Error::Http(status)) => assert_eq!(status.as_u16(), 500),
+        other => panic!("expected the write to fail, got {:?}", other.map(|_| ())),
+    }
+    db.commit_value(db.dataset_or_empty("a").unwrap(), list).unwrap();
+
+    let other = server.connect(&noms);
+    let ds = other.dataset::<Empty, NomsList<i64>>("a").unwrap();
+    assert_eq!(ds.head_value().unwrap().to_vec(), (0..5_000).collect::<Vec<_>>());
+}
+
+#[test]
+fn malformed_chunks_are_not_written() {
+    let noms = Noms::new();
+    let server = server_with(|_, _| None);
+    let db = server.connect(&noms);
+    // a ref which ends part way through its hash
+    match db.write_value(vec![7u8, 1, 2]) {
+        Err(Error::ConversionError(_)) => {}
+        other => panic!("expected the chunk to be rejected, got {:?}", other),
+    }
+    db.commit_value(db.dataset_or_empty("a").unwrap(), db.value_from("fine")).unwrap();
+    assert_eq!(dataset(&db, "a").unwrap().head_value().unwrap(), db.value_from("fine"));
+}
+
+#[test]
+fn fast_forward() {
+    let noms = Noms::new();
+    let (db, _) = writable_database(&noms);
+    let old = db.commit_value(db.dataset_or_empty("a").unwrap(), db.value_from(1i64)).unwrap();
+    let old_ref = old.head_ref().clone();
+    let new = db.commit_value(old, db.value_from(2i64)).unwrap();
+    let new_ref = new.head_ref().clone();
+
+    let b = db.fast_forward(db.dataset_or_empty("b").unwrap(), old_ref.clone()).unwrap();
+    let b = db.fast_forward(b, new_ref.clone()).unwrap();
+    assert_eq!(b.head_ref(), &new_ref);
+    match db.fast_forward(b, old_ref) {
+        Err(Error::MergeNeeded) => {}
+        other => panic!("expected a merge to be needed, got {:?}", other),
+    }
+}
+
+#[test]
+fn load_refs() {
+    let noms = Noms::new();
+    let (db, _) = writable_database(&noms);
+    let first = db.commit_value(db.dataset_or_empty("a").unwrap(), db.value_from(1i64)).unwrap();
+    let second = db.commit_value(first, db.value_from(2i64)).unwrap();
+
+    let ds = db.dataset::<Empty, i64>("a").unwrap();
+    let head = ds.head_ref().load().unwrap();
+    assert_eq!(head.value(), &2);
+    let parents = head.parents().to_set();
+    assert_eq!(parents.len(), 1);
+    let parent = parents.into_iter().next().unwrap();
+    assert_eq!(parent.height(), 1);
+    assert_eq!(parent.load().unwrap().value(), &1);
+    assert!(parent.load().unwrap().parents().to_set().is_empty());
+    assert_eq!(second.head_ref(), ds.head_ref());
+    #[allow(deprecated)]
+    let hash = ds.head_ref().hash();
+    assert_eq!(hash, ds.head_ref().target_hash());
+}
+
+#[test]
+fn load_checks_the_target_type() {
+    let noms = Noms::new();
+    let (db, _) = writable_database(&noms);
+    db.commit_value(db.dataset_or_empty("a").unwrap(), db.value_from(1i64)).unwrap();
+
+    let ds = db.dataset::<Empty, NomsValue>("a").unwrap();
+    assert_eq!(ds.head_ref().load().unwrap().value(), &db.value_from(1i64));
+    let as_string: Ref<Commit<Empty, String>> = db.value_from(ds.head_ref().clone()).transform();
+    match as_string.load() {
+        Err(Error::ConversionError(ref message)) => assert!(message.ends_with(":\n  .value: expected String, found Number"), "{}", message),
+        other => panic!("expected a type mismatch, got {:?}", other),
+    }
+}
+
+#[derive(Clone, Debug, Noms)]
+struct Row {
+    name: String,
+    count: Option<i64>,
+}
+
+#[test]
+fn schema_checked_datasets() {
+    let noms = Noms::new();
+    let (db, _) = writable_database(&noms);
+    let rows = Importer::new()
+        .column_types(vec![ColumnType::String, ColumnType::Number])
+        .import(&db, "name,count\na,1\nb,\n".as_bytes())
+        .unwrap();
+    db.commit_value(db.dataset_or_empty("rows").unwrap(), rows).unwrap();
+
+    let ds = db.dataset::<Empty, NomsList<Row>>("rows").unwrap();
+    let rows = ds.head_value().unwrap().to_vec();
+    assert_eq!((rows[1].name.as_str(), rows[1].count), ("b", None));
+    match db.dataset::<Empty, String>("rows") {
+        Err(Error::SchemaMismatch(ref id, ref differences)) if id == "rows" => assert_eq!(
+            differences,
+            &vec![".value: expected String, found List<Struct Row { count?: Number, name: String }>".to_string()],
+        ),
+        other => panic!("expected a schema mismatch, got {:?}", other),
+    }
+    let err = db.dataset::<Empty, NomsList<String>>("rows").map(|_| ()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The dataset rows does not fit the type it is read as:\n  .value[]: expected String, found Struct Row { count?: Number, name: String }",
+    );
+}
+
+/// The value of the statistic with the given name in a summary
+fn statistic<'a>(summary: &'a str, name: &str) -> &'a str {
+    summary
+        .lines()
+        .find(|line| line.starts_with(&format!("{}: ", name)))
+        .map(|line| &line[name.len() + 2..])
+        .unwrap_or_else(|| panic!("{} is not in the summary:\n{}", name, summary))
+}
+
+#[test]
+fn stats() {
+    let noms = Noms::new();
+    let (db, requests) = writable_database(&noms);
+    let stats = db.stats().unwrap();
+    assert!(stats.is_supported());
+    assert_eq!(stats.get("PutLatency"), Some("Mean 3ms"));
+    assert_eq!(stats.values.len(), 2);
+
+    db.commit_value(db.dataset_or_empty("a").unwrap(), db.value_from("value")).unwrap();
+    assert_eq!(dataset(&db, "a").unwrap().head_value().unwrap(), db.value_from("value"));
+
+    let summary = db.stats_summary();
+    assert!(summary.ends_with(common::STATS.trim_end()));
+    assert_eq!(statistic(&summary, "Requests"), requests.lock().unwrap().len().to_string());
+    assert!(statistic(&summary, "BytesSent").parse::<u64>().unwrap() > 0);
+    assert!(statistic(&summary, "BytesReceived").parse::<u64>().unwrap() >= 2 * common::STATS.len() as u64);
+    // every chunk read was written by this client, so none had to be fetched
+    assert_eq!(statistic(&summary, "ChunksFetched"), "0");
+    assert_eq!(statistic(&summary, "CacheHitRate"), "100.0%");
+}
@@ -0,0 +1,216 @@
+extern crate nomrs;
+#[macro_use] extern crate nomrs_derive;
+
+use nomrs::{Noms, Database};
+use nomrs::value::{IntoNoms, NomsList, NomsStruct, Type};
+
+mod common;
+
+use common::database;
+
+#[derive(Clone, Debug, Noms)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Clone, Debug, Noms)]
+struct Shape<'a> {
+    line_width: u64,
+    points: NomsList<'a, Point>,
+    fill: Point,
+}
+
+#[test]
+fn derived_struct_type() {
+    let bytes = <Point as NomsStruct>::noms_type().into_noms();
+    assert_eq!(bytes, vec![11, 9, 5, b'P', b'o', b'i', b'n', b't', 2, 1, b'x', 1, b'y', 1, 1, 0, 0]);
+}
+
+#[test]
+fn derived_nested_type() {
+    let expected = Type::structure(
+        "Shape".to_string(),
+        vec!["fill".to_string(), "lineWidth".to_string(), "points".to_string()],
+        vec![<Point as IntoNoms>::noms_type(), u64::noms_type(), NomsList::<Point>::noms_type()],
+        vec![false, false, false],
+    );
+    assert_eq!(<Shape as NomsStruct>::noms_type(), expected);
+    assert_eq!(<Shape as IntoNoms>::noms_type(), expected);
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+struct Track {
+    title: String,
+    rating: Option<u64>,
+    #[noms(default)]
+    album: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+struct OldTrack {
+    title: String,
+}
+
+#[test]
+fn optional_fields() {
+    let expected = Type::structure(
+        "Track".to_string(),
+        vec!["album".to_string(), "rating".to_string(), "title".to_string()],
+        vec![String::noms_type(), u64::noms_type(), String::noms_type()],
+        vec![true, true, false],
+    );
+    assert_eq!(<Track as NomsStruct>::noms_type(), expected);
+
+    let rated = Track{ title: "a".to_string(), rating: Some(3), album: Some("b".to_string()) };
+    let unrated = Track{ title: "a".to_string(), rating: None, album: None };
+    let old = OldTrack{ title: "a".to_string() };
+    assert_eq!(unrated.into_noms(), encode_with_name(&old, "Track"));
+
+    let noms = Noms::new();
+    let db = database(&noms);
+    assert_eq!(db.value_from(rated.clone()).transform::<Track>(), rated);
+    assert_eq!(db.value_from(unrated.clone()).transform::<Track>(), unrated);
+}
+
+fn encode_with_name<'a, T: NomsStruct<'a>>(value: &T, name: &str) -> Vec<u8> {
+    nomrs::value::encode_struct(name, value.to_prop_list())
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+enum Event {
+    Started,
+    Stopped,
+    Click { x: u64, y: u64 },
+    KeyPress { key: String, modifier: Option<String> },
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+enum Priority {
+    Low = 1,
+    High = 10,
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+#[repr(u8)]
+enum Signal {
+    Off = 2,
+    On,
+    Level { value: u64 },
+    Blink = 7,
+}
+
+#[test]
+fn derived_enum_type() {
+    let click = Type::structure(
+        "Click".to_string(),
+        vec!["x".to_string(), "y".to_string()],
+        vec![u64::noms_type(), u64::noms_type()],
+        vec![false, false],
+    );
+    let key_press = Type::structure(
+        "KeyPress".to_string(),
+        vec!["key".to_string(), "modifier".to_string()],
+        vec![String::noms_type(), String::noms_type()],
+        vec![false, true],
+    );
+    // the types of a union are ordered by hash, whatever order the variants are in
+    let event = Type::simplified_union(vec![key_press.clone(), String::noms_type(), click.clone()]);
+    assert_eq!(Event::noms_type(), event);
+    assert_eq!(event, Type::simplified_union(vec![click, key_press, String::noms_type()]));
+    assert_eq!(Priority::noms_type(), i64::noms_type());
+    let level = Type::structure("Level".to_string(), vec!["value".to_string()], vec![u64::noms_type()], vec![false]);
+    assert_eq!(Signal::noms_type(), Type::simplified_union(vec![level, i64::noms_type()]));
+}
+
+#[test]
+fn derived_enum_round_trip() {
+    assert_eq!(Event::Started.into_noms(), "Started".into_noms());
+    assert_eq!(Priority::High.into_noms(), 10i64.into_noms());
+
+    let noms = Noms::new();
+    let db = database(&noms);
+    let events = vec![
+        Event::Started,
+        Event::Click{ x: 3, y: 4 },
+        Event::KeyPress{ key: "a".to_string(), modifier: None },
+        Event::KeyPress{ key: "b".to_string(), modifier: Some("shift".to_string()) },
+        Event::Stopped,
+    ];
+    for event in events {
+        assert_eq!(db.value_from(event.clone()).transform::<Event>(), event);
+    }
+    assert_eq!(db.value_from(Priority::Low).transform::<Priority>(), Priority::Low);
+
+    // unit variants are numbered as Rust numbers them, counting the variants with fields
+    assert_eq!(Signal::Off.into_noms(), 2i64.into_noms());
+    assert_eq!(Signal::On.into_noms(), 3i64.into_noms());
+    assert_eq!(Signal::Blink.into_noms(), 7i64.into_noms());
+    for signal in vec![Signal::Off, Signal::On, Signal::Level{ value: 5 }, Signal::Blink] {
+        assert_eq!(db.value_from(signal.clone()).transform::<Signal>(), signal);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+#[noms(name = "Person", rename_all = "snake_case")]
+struct GoPerson {
+    #[noms(rename = "ID")]
+    id: String,
+    full_name: String,
+    #[noms(default)]
+    age: u64,
+    #[noms(skip)]
+    visits: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+#[noms(name = "Person")]
+struct OldPerson {
+    #[noms(rename = "ID")]
+    id: String,
+    #[noms(rename = "full_name")]
+    full_name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Noms)]
+#[noms(rename_all = "snake_case")]
+enum Status {
+    Ready,
+    #[noms(rename = "done")]
+    Finished,
+    #[noms(rename_all = "PascalCase")]
+    InProgress { percent_done: u64 },
+}
+
+#[test]
+fn attributes() {
+    assert_eq!(<GoPerson as NomsStruct>::NAME, "Person");
+    let expected = Type::structure(
+        "Person".to_string(),
+        vec!["ID".to_string(), "age".to_string(), "full_name".to_string()],
+        vec![String::noms_type(), u64::noms_type(), String::noms_type()],
+        vec![false, false, false],
+    );
+    assert_eq!(<GoPerson as NomsStruct>::noms_type(), expected);
+
+    let noms = Noms::new();
+    let db = database(&noms);
+    let person = GoPerson{ id: "1".to_string(), full_name: "Ada".to_string(), age: 36, visits: 5 };
+    assert_eq!(
+        db.value_from(person).transform::<GoPerson>(),
+        GoPerson{ id: "1".to_string(), full_name: "Ada".to_string(), age: 36, visits: 0 },
+    );
+    let old = OldPerson{ id: "2".to_string(), full_name: "Grace".to_string() };
+    assert_eq!(
+        db.value_from(old).transform::<GoPerson>(),
+        GoPerson{ id: "2".to_string(), full_name: "Grace".to_string(), age: 0, visits: 0 },
+    );
+
+    assert_eq!(Status::Ready.into_noms(), "ready".into_noms());
+    assert_eq!(Status::Finished.into_noms(), "done".into_noms());
+    let in_progress = Status::InProgress{ percent_done: 50 };
+    let mut props = std::collections::HashMap::new();
+    props.insert("PercentDone".to_string(), 50u64.into_noms());
+    assert_eq!(in_progress.into_noms(), nomrs::value::encode_struct("in_progress", props));
+    assert_eq!(db.value_from(in_progress.clone()).transform::<Status>(), in_progress);
+}
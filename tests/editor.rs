@@ -0,0 +1,155 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::value::{NomsValue, NomsList, ListEditor, MapEditor, SetEditor, IntoNoms};
+use nomrs::error::Error;
+
+mod common;
+
+use common::{database, server};
+
+fn list<'a, D: Database>(db: &'a D, items: &[i64]) -> NomsValue<'a> {
+    let mut editor = ListEditor::new(db);
+    editor.splice(0, 0, items.iter().cloned()).unwrap();
+    editor.build().unwrap()
+}
+
+#[test]
+fn list_edits() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let mut items: Vec<i64> = (0..20_000).collect();
+    let original = list(&db, &items);
+
+    let mut editor = ListEditor::edit(&db, &original).unwrap();
+    editor.update(5_000, -1i64).unwrap();
+    editor.remove(0).unwrap();
+    editor.insert(12_345, -2i64).unwrap();
+    editor.splice(15_000, 3_000, vec![-3i64, -4]).unwrap();
+    let len = editor.len();
+    editor.insert(len, -5i64).unwrap();
+    let edited = editor.build().unwrap();
+
+    items[5_000] = -1;
+    items.remove(0);
+    items.insert(12_345, -2);
+    items.splice(15_000..18_000, vec![-3, -4]);
+    items.push(-5);
+    assert_eq!(edited.clone().transform::<NomsList<i64>>().to_vec(), items);
+    assert!(edited.into_noms() == list(&db, &items).into_noms());
+}
+
+#[test]
+fn list_edits_share_untouched_chunks() {
+    let noms = Noms::new();
+    let server = server();
+    let db = server.connect(&noms);
+    let mut items: Vec<i64> = (0..100_000).collect();
+    let original = list(&db, &items);
+    db.commit_value(db.dataset_or_empty("a").unwrap(), original.clone()).unwrap();
+    let before = server.written().len();
+    assert!(before > 100);
+
+    let mut editor = ListEditor::edit(&db, &original).unwrap();
+    editor.update(50_000, -1i64).unwrap();
+    let edited = editor.build().unwrap();
+    // only the leaf holding the item and the chunk above it are new, as the root is not a chunk,
+    // along with the new commit and the map of datasets
+    db.commit_value(db.dataset_or_empty("a").unwrap(), edited.clone()).unwrap();
+    assert_eq!(server.written().len() - before, 4);
+
+    items[50_000] = -1;
+    assert!(edited.into_noms() == list(&db, &items).into_noms());
+}
+
+#[test]
+fn list_index_out_of_bounds() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let mut editor = ListEditor::new(&db);
+    editor.insert(0, "a").unwrap();
+    match editor.remove(1) {
+        Err(Error::IndexOutOfBounds(1, 1)) => {}
+        other => panic!("Expected the index to be out of bounds, got {:?}", other),
+    }
+    assert!(ListEditor::edit(&db, &db.value_from("a")).is_err());
+}
+
+#[test]
+fn map_edits() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let mut editor = MapEditor::new(&db);
+    for i in 0..10_000u64 {
+        assert!(!editor.insert(format!("row {}", i), i).unwrap());
+    }
+    let original = editor.build().unwrap();
+
+    let mut editor = MapEditor::edit(&db, &original).unwrap();
+    assert!(editor.insert("row 5000", 0u64).unwrap());
+    assert!(editor.update("row 17", 1u64).unwrap());
+    assert!(!editor.update("row 10000", 2u64).unwrap());
+    assert!(editor.remove("row 9999").unwrap());
+    assert!(!editor.remove("row 10001").unwrap());
+    assert!(!editor.insert("zzz", 3u64).unwrap());
+    assert!(!editor.insert("", 4u64).unwrap());
+    assert_eq!(editor.len(), 10_001);
+    let edited = editor.build().unwrap();
+
+    let mut expected = MapEditor::new(&db);
+    for i in 0..10_000u64 {
+        let value = match i { 5000 => 0, 17 => 1, _ => i };
+        if i != 9999 {
+            expected.insert(format!("row {}", i), value).unwrap();
+        }
+    }
+    expected.insert("zzz", 3u64).unwrap();
+    expected.insert("", 4u64).unwrap();
+    assert!(edited.into_noms() == expected.build().unwrap().into_noms());
+
+    let map = edited.transform::<nomrs::value::NomsMap<String, u64>>();
+    assert_eq!(map.to_map().len(), 10_001);
+    assert_eq!(map.get("row 5000").unwrap(), Some(0));
+    assert_eq!(map.get("row 42").unwrap(), Some(42));
+    assert_eq!(map.get("zzz").unwrap(), Some(3));
+    assert_eq!(map.get("").unwrap(), Some(4));
+    assert_eq!(map.get("row 9999").unwrap(), None);
+    assert_eq!(map.get("zzzz").unwrap(), None);
+    assert_eq!(map.get(5000u64).unwrap(), None);
+}
+
+#[test]
+fn set_edits() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let mut editor = SetEditor::new(&db);
+    for i in 0..5_000i64 {
+        editor.insert(i * 2).unwrap();
+    }
+    let original = editor.build().unwrap();
+
+    let mut editor = SetEditor::edit(&db, &original).unwrap();
+    assert!(editor.insert(4_000i64).unwrap());
+    assert!(!editor.insert(4_001i64).unwrap());
+    assert!(editor.remove(0i64).unwrap());
+    assert!(!editor.remove(1i64).unwrap());
+    let edited = editor.build().unwrap();
+
+    let mut expected = SetEditor::new(&db);
+    for i in (1..5_000i64).map(|i| i * 2).chain(Some(4_001)) {
+        expected.insert(i).unwrap();
+    }
+    assert!(edited.into_noms() == expected.build().unwrap().into_noms());
+    assert_eq!(edited.transform::<nomrs::value::NomsSet<i64>>().to_set().len(), 5_000);
+}
+
+#[test]
+fn removing_everything() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let items: Vec<i64> = (0..1_000).collect();
+    let mut editor = ListEditor::edit(&db, &list(&db, &items)).unwrap();
+    editor.splice(0, 1_000, Vec::<i64>::new()).unwrap();
+    assert!(editor.is_empty());
+    assert!(editor.build().unwrap().into_noms() == list(&db, &[]).into_noms());
+}
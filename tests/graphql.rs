@@ -0,0 +1,90 @@
+extern crate nomrs;
+#[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
+
+use nomrs::{Noms, Database};
+use nomrs::error::Error;
+use nomrs::graphql::{head_value, list_page, map_lookup};
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+use common::{server_with, form_value};
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Person {
+    name: String,
+    age: u32,
+}
+
+/// Answers GraphQL queries the way a Noms server would if the dataset `numbers` held the list of
+/// numbers from 0 to 99, `people` held a map of people by their ids, and `missing` did not exist.
+/// Every dataset, query and set of variables received is recorded.
+fn graphql_server() -> (String, Arc<Mutex<Vec<(String, String, Option<String>)>>>) {
+    let queries = Arc::new(Mutex::new(vec![]));
+    let recorded = queries.clone();
+    let server = server_with(move |line, body| {
+        if !line.starts_with("POST /graphql/") {
+            return None;
+        }
+        let ds = form_value(body, "ds").unwrap();
+        let query = form_value(body, "query").unwrap();
+        recorded.lock().unwrap().push((ds.clone(), query.clone(), form_value(body, "vars")));
+        // whitespace is insignificant in GraphQL
+        let query = query.split_whitespace().collect::<Vec<_>>().join(" ");
+        let response = match ds.as_str() {
+            "missing" => json!({ "data": { "root": null } }),
+            "numbers" if query == "{ root { value { elements(at: 10, count: 3) } } }" =>
+                json!({ "data": { "root": { "value": { "elements": [10, 11, 12] } } } }),
+            "people" if query == r#"{ root { value { entries(keys: ["ann","nobody","bob"]) { key value { name age } } } } }"# =>
+                json!({ "data": { "root": { "value": { "entries": [
+                    { "key": "ann", "value": { "name": "Ann", "age": 31 } },
+                    { "key": "bob", "value": { "name": "Bob", "age": 27 } },
+                ] } } } }),
+            "numbers" if query == "{ root { value } }" => json!({ "data": { "root": { "value": 100 } } }),
+            _ => json!({ "data": null, "errors": [{ "message": "Cannot query this" }] }),
+        };
+        Some((200, response.to_string().into_bytes()))
+    });
+    (server.address, queries)
+}
+
+#[test]
+fn raw_queries() {
+    let noms = Noms::new();
+    let (address, queries) = graphql_server();
+    let db = noms.database().http(&address).unwrap();
+    let query = "query($at: Int) { root { value } }";
+    let response = db.graphql("numbers", query, &json!({ "at": 5 })).unwrap();
+    assert_eq!(response["errors"][0]["message"], "Cannot query this");
+    assert_eq!(
+        queries.lock().unwrap().pop(),
+        Some(("numbers".to_string(), query.to_string(), Some(r#"{"at":5}"#.to_string()))),
+    );
+    db.graphql("numbers", "{ root { value } }", &json!(null)).unwrap();
+    assert_eq!(queries.lock().unwrap().pop().unwrap().2, None);
+}
+
+#[test]
+fn typed_queries() {
+    let noms = Noms::new();
+    let (address, _) = graphql_server();
+    let db = noms.database().http(&address).unwrap();
+
+    assert_eq!(head_value::<u64, _>(&db, "numbers", "").unwrap(), 100);
+    assert_eq!(list_page::<u64, _>(&db, "numbers", 10, 3, "").unwrap(), vec![10, 11, 12]);
+    let people: Vec<(String, Person)> = map_lookup(&db, "people", &["ann", "nobody", "bob"], "{ name age }").unwrap();
+    assert_eq!(people, vec![
+        ("ann".to_string(), Person{ name: "Ann".to_string(), age: 31 }),
+        ("bob".to_string(), Person{ name: "Bob".to_string(), age: 27 }),
+    ]);
+
+    match head_value::<u64, _>(&db, "missing", "") {
+        Err(Error::NoDataset(ref ds)) if ds == "missing" => {}
+        other => panic!("Expected there to be no dataset, got {:?}", other),
+    }
+    match list_page::<u64, _>(&db, "people", 0, 10, "") {
+        Err(Error::GraphQL(ref messages)) => assert_eq!(messages, &["Cannot query this"]),
+        other => panic!("Expected the query to fail, got {:?}", other),
+    }
+}
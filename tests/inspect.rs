@@ -0,0 +1,44 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::csv::{Importer, ColumnType};
+use nomrs::inspect::{inspect, inspect_chunk};
+
+mod common;
+
+use common::writable_database;
+
+#[test]
+fn commits() {
+    let noms = Noms::new();
+    let (db, _) = writable_database(&noms);
+    let mut input = "n\n".to_string();
+    for n in 0..1000 {
+        input.push_str(&format!("{}\n", n));
+    }
+    let value = Importer::new()
+        .column_types(vec![ColumnType::Number])
+        .import(&db, input.as_bytes())
+        .unwrap();
+    let ds = db.commit_value(db.dataset_or_empty("numbers").unwrap(), value).unwrap();
+
+    let inspection = inspect_chunk(&db, ds.head_ref().target_hash()).unwrap();
+    assert_eq!(inspection.failure(), None);
+    let text = inspection.to_string();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "     0  [Kind 9] Struct:");
+    assert_eq!(lines[1], "     1    Name: [Length 6] \"Commit\"");
+    assert_eq!(lines[16], "    35          [Level 1] [Count 5]");
+    assert_eq!(lines.iter().filter(|line| line.ends_with("MetaTuple:")).count(), 5);
+    assert!(text.contains("Name: [Length 3] \"Row\""));
+}
+
+#[test]
+fn trailing_bytes() {
+    let inspection = inspect(&[0, 1, 0]);
+    assert_eq!(inspection.failure().unwrap().offset, 2);
+    assert_eq!(
+        inspection.to_string(),
+        "     0  [Kind 0] Boolean: [Byte 1] true\n     2  !! 1 bytes follow the value\n",
+    );
+}
@@ -0,0 +1,174 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::error::Error;
+use nomrs::json::{self, Objects};
+use nomrs::value::{Empty, NomsValue, IntoNoms, ListEditor, SetEditor, MapEditor, encode_struct};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod common;
+
+use common::{database, writable_database, server, server_with};
+
+fn round_trip(input: &str, objects: Objects) -> String {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let value = json::import(&db, input.as_bytes(), objects).unwrap();
+    let mut output = vec![];
+    json::export(&value, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn export_maps() {
+    let input = r#"{"b": [1, 2.5, -3], "a": {"nested": true, "none": null}, "c": "text"}"#;
+    let expected = r#"{"a":{"nested":true},"b":[1,2.5,-3],"c":"text"}"#;
+    assert_eq!(round_trip(input, Objects::Maps), expected);
+}
+
+#[test]
+fn export_structs() {
+    let input = r#"[{"name": "Ann", "age": 31}, {"name": "Bob", "tags": ["x"]}]"#;
+    let expected = r#"[{"age":31,"name":"Ann"},{"name":"Bob","tags":["x"]}]"#;
+    assert_eq!(round_trip(input, Objects::Structs), expected);
+}
+
+#[test]
+fn objects_as_structs() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let json = br#"{"b": true, "a": "x", "none": null, "my key": 1}"#;
+    let mut props = HashMap::new();
+    props.insert("a".to_string(), "x".into_noms());
+    props.insert("b".to_string(), true.into_noms());
+    props.insert("myQ20key".to_string(), 1i64.into_noms());
+    assert_eq!(json::encode(&db, &json[..], Objects::Structs).unwrap(), encode_struct("", props));
+}
+
+#[test]
+fn arrays_and_maps() {
+    let input = r#"[1, null, {"b": 2.5, "a": []}]"#;
+    assert_eq!(round_trip(input, Objects::Maps), r#"[1,{"a":[],"b":2.5}]"#);
+    let noms = Noms::new();
+    assert!(json::encode(&database(&noms), &b"null"[..], Objects::Maps).is_err());
+}
+
+#[test]
+fn large_arrays_are_chunked() {
+    let noms = Noms::new();
+    let server = server();
+    let db = server.connect(&noms);
+    let input = format!("[{}]", (0..20_000).map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+    let value = json::import(&db, input.as_bytes(), Objects::Maps).unwrap();
+    db.commit_value(db.dataset_or_empty("big").unwrap(), value).unwrap();
+    // the leaves are written along with the root, the commit and the map of datasets
+    assert!(server.written().len() > 4);
+
+    let other = server.connect(&noms);
+    let list = other.dataset::<Empty, NomsValue>("big").unwrap().head_value().unwrap();
+    let mut output = vec![];
+    json::export(&list, &mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), input);
+}
+
+#[test]
+fn large_sets_and_maps_are_streamed() {
+    let noms = Noms::new();
+    let server = server();
+    let db = server.connect(&noms);
+    let mut set = SetEditor::new(&db);
+    let mut map = MapEditor::new(&db);
+    for i in 0..20_000i64 {
+        set.insert(i).unwrap();
+        map.insert(format!("key {:05}", i), i).unwrap();
+    }
+    db.commit_value(db.dataset_or_empty("set").unwrap(), set.build().unwrap()).unwrap();
+    db.commit_value(db.dataset_or_empty("map").unwrap(), map.build().unwrap()).unwrap();
+
+    let other = server.connect(&noms);
+    let set = other.dataset::<Empty, NomsValue>("set").unwrap().head_value().unwrap();
+    let mut output = vec![];
+    json::export(&set, &mut output).unwrap();
+    let expected = format!("[{}]", (0..20_000).map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+
+    let map = other.dataset::<Empty, NomsValue>("map").unwrap().head_value().unwrap();
+    let mut output = vec![];
+    json::export(&map, &mut output).unwrap();
+    let expected = format!("{{{}}}", (0..20_000).map(|i| format!("\"key {:05}\":{}", i, i)).collect::<Vec<_>>().join(","));
+    assert_eq!(String::from_utf8(output).unwrap(), expected);
+}
+
+#[test]
+fn import_commits() {
+    let noms = Noms::new();
+    let (db, requests) = writable_database(&noms);
+    let value = json::import(&db, &br#"{"answer": 42}"#[..], Objects::Maps).unwrap();
+    let ds = db.dataset_or_empty("imported").unwrap();
+    assert!(ds.head().is_none());
+    let ds = db.commit_value(ds, value).unwrap();
+    assert_eq!(ds.id(), "imported");
+    assert!(!ds.head_ref().is_empty());
+    assert_eq!(ds.head_ref().height(), 1);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[1].starts_with("POST /writeValue/ "));
+    assert!(requests[2].starts_with("POST /root/?last=00000000000000000000000000000000&current="));
+}
+
+#[test]
+fn export_fails_if_a_chunk_cannot_be_fetched() {
+    let noms = Noms::new();
+    let failing = Arc::new(AtomicBool::new(false));
+    let fail = failing.clone();
+    let server = server_with(move |line, _| match line.starts_with("POST /getRefs/") && fail.load(Ordering::SeqCst) {
+        true => Some((500, vec![])),
+        false => None,
+    });
+    let db = server.connect(&noms);
+    let mut editor = ListEditor::new(&db);
+    editor.splice(0, 0, 0..20_000i64).unwrap();
+    db.commit_value(db.dataset_or_empty("big").unwrap(), editor.build().unwrap()).unwrap();
+
+    let other = server.connect(&noms);
+    let list = other.dataset::<Empty, NomsValue>("big").unwrap().head_value().unwrap();
+    failing.store(true, Ordering::SeqCst);
+    match json::export(&list, vec![]) {
+        Err(Error::Http(status)) => assert_eq!(status.as_u16(), 500),
+        other => panic!("expected the export to fail, got {:?}", other),
+    }
+}
+
+#[test]
+fn set_and_map_exports_fail_if_a_chunk_cannot_be_fetched() {
+    let noms = Noms::new();
+    let failing = Arc::new(AtomicBool::new(false));
+    let fail = failing.clone();
+    let server = server_with(move |line, _| match line.starts_with("POST /getRefs/") && fail.load(Ordering::SeqCst) {
+        true => Some((500, vec![])),
+        false => None,
+    });
+    let db = server.connect(&noms);
+    let mut set = SetEditor::new(&db);
+    let mut map = MapEditor::new(&db);
+    for i in 0..20_000i64 {
+        set.insert(i).unwrap();
+        map.insert(format!("key {:05}", i), i).unwrap();
+    }
+    db.commit_value(db.dataset_or_empty("set").unwrap(), set.build().unwrap()).unwrap();
+    db.commit_value(db.dataset_or_empty("map").unwrap(), map.build().unwrap()).unwrap();
+
+    let other = server.connect(&noms);
+    let set = other.dataset::<Empty, NomsValue>("set").unwrap().head_value().unwrap();
+    let map = other.dataset::<Empty, NomsValue>("map").unwrap().head_value().unwrap();
+    failing.store(true, Ordering::SeqCst);
+    for value in &[set, map] {
+        match json::export(value, vec![]) {
+            Err(Error::Http(status)) => assert_eq!(status.as_u16(), 500),
+            other => panic!("expected the export to fail, got {:?}", other),
+        }
+    }
+}
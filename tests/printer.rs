@@ -0,0 +1,128 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::csv::{Importer, ColumnType};
+use nomrs::error::Error;
+use nomrs::value::{Empty, NomsValue, ListEditor, IntoNoms, Printer, encode_struct};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod common;
+
+use common::{database, server_with};
+
+const PEOPLE: &'static str = "name,age\nAnn,30\nBob,2.5\n";
+
+#[test]
+fn display() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let value = Importer::new()
+        .column_types(vec![ColumnType::String, ColumnType::Number])
+        .import(&db, PEOPLE.as_bytes())
+        .unwrap();
+    assert_eq!(
+        value.to_string(),
+        r#"[Row { age: 30, name: "Ann" }, Row { age: 2.5, name: "Bob" }]"#,
+    );
+    assert_eq!(
+        format!("{:#}", value),
+        "[\n  Row {\n    age: 30,\n    name: \"Ann\",\n  },\n  Row {\n    age: 2.5,\n    name: \"Bob\",\n  },\n]",
+    );
+}
+
+#[test]
+fn maps_and_depth() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let value = Importer::new()
+        .key_column("name")
+        .import(&db, PEOPLE.as_bytes())
+        .unwrap();
+    assert_eq!(
+        Printer::new().format(&value).unwrap(),
+        r#"{"Ann": Row { age: "30", name: "Ann" }, "Bob": Row { age: "2.5", name: "Bob" }}"#,
+    );
+    assert_eq!(
+        Printer::new().max_depth(1).format(&value).unwrap(),
+        r#"{"Ann": Row {...}, "Bob": Row {...}}"#,
+    );
+    assert_eq!(Printer::new().max_depth(0).format(&value).unwrap(), "{...}");
+}
+
+#[test]
+fn large_lists_are_elided() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let mut input = "n\n".to_string();
+    for n in 0..2000 {
+        input.push_str(&format!("{}\n", n));
+    }
+    let value = Importer::new()
+        .column_types(vec![ColumnType::Number])
+        .import(&db, input.as_bytes())
+        .unwrap();
+    assert_eq!(
+        Printer::new().max_items(2).format(&value).unwrap(),
+        "[Row { n: 0 }, Row { n: 1 }, ... 1,998 more]",
+    );
+    assert_eq!(
+        Printer::new().max_items(1).multiline(true).color(true).format(&value).unwrap(),
+        "[\n  \u{1b}[1mRow\u{1b}[0m {\n    n: \u{1b}[33m0\u{1b}[0m,\n  },\n  ... 1,999 more\n]",
+    );
+    let full = Printer::new().format(&value).unwrap();
+    assert!(full.ends_with("Row { n: 1998 }, Row { n: 1999 }]"));
+}
+
+#[test]
+fn wide_structs() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    // more than 127 fields, so that the field count takes two bytes
+    let props: HashMap<_, _> = (0..200).map(|i| (format!("f{:03}", i), (i as u64).into_noms())).collect();
+    let value = db.value_from(encode_struct("Wide", props));
+    let text = Printer::new().format(&value).unwrap();
+    assert!(text.starts_with("Wide { f000: 0, f001: 1, "));
+    assert!(text.ends_with(", f198: 198, f199: 199 }"));
+    assert!(value.to_string().ends_with(", f099: 99, ... 100 more }"));
+}
+
+#[test]
+fn fetch_errors() {
+    let noms = Noms::new();
+    let failing = Arc::new(AtomicBool::new(false));
+    let fail = failing.clone();
+    let server = server_with(move |line, _| match line.starts_with("POST /getRefs/") && fail.load(Ordering::SeqCst) {
+        true => Some((500, vec![])),
+        false => None,
+    });
+    let db = server.connect(&noms);
+    let mut editor = ListEditor::new(&db);
+    editor.splice(0, 0, 0..20_000i64).unwrap();
+    db.commit_value(db.dataset_or_empty("big").unwrap(), editor.build().unwrap()).unwrap();
+
+    let other = server.connect(&noms);
+    let list = other.dataset::<Empty, NomsValue>("big").unwrap().head_value().unwrap();
+    failing.store(true, Ordering::SeqCst);
+    match list.print(&mut vec![]) {
+        Err(Error::Http(status)) => assert_eq!(status.as_u16(), 500),
+        other => panic!("expected printing to fail, got {:?}", other),
+    }
+    assert_eq!(list.to_string(), "[<Server responded with 500 Internal Server Error>");
+}
+
+#[test]
+fn refs() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    db.commit_value(db.dataset_or_empty("a").unwrap(), db.value_from(1i64)).unwrap();
+    let head = db.dataset::<Empty, i64>("a").unwrap().head_ref().clone();
+    let mut props = HashMap::new();
+    props.insert("head".to_string(), head.into_noms());
+    props.insert("n".to_string(), 1u64.into_noms());
+    let value = db.value_from(encode_struct("Row", props));
+    let hash = head.target_hash().to_string();
+    assert_eq!(Printer::new().format(&value).unwrap(), format!("Row {{ head: #{}, n: 1 }}", hash));
+    assert_eq!(format!("{:#}", value), format!("Row {{\n  head: #{},\n  n: 1,\n}}", hash));
+}
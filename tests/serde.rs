@@ -0,0 +1,128 @@
+extern crate nomrs;
+#[macro_use] extern crate serde_derive;
+
+use nomrs::{Noms, Database};
+use nomrs::error::Error;
+use nomrs::value::{Empty, NomsValue, IntoNoms, ListEditor, SetEditor, MapEditor, encode_struct};
+use nomrs::serde::{to_noms, from_value};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod common;
+
+use common::{database, server_with};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Album {
+    title: String,
+    year: u16,
+    rating: Option<f64>,
+    tracks: Vec<String>,
+    plays: HashMap<String, u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Event {
+    Started,
+    Click { x: u64, y: u64 },
+    Key(String),
+}
+
+#[test]
+fn struct_encoding() {
+    #[derive(Serialize)]
+    struct Point { y: i32, x: i32 }
+
+    let mut props = HashMap::new();
+    props.insert("x".to_string(), 1i64.into_noms());
+    props.insert("y".to_string(), 2i64.into_noms());
+    assert_eq!(to_noms(&Point{ x: 1, y: 2 }).unwrap(), encode_struct("Point", props));
+}
+
+#[test]
+fn round_trip() {
+    let noms = Noms::new();
+    let db = database(&noms);
+
+    let mut plays = HashMap::new();
+    plays.insert("Intro".to_string(), 3);
+    plays.insert("Outro".to_string(), 12);
+    let rated = Album {
+        title: "Noms".to_string(),
+        year: 2017,
+        rating: Some(4.5),
+        tracks: vec!["Intro".to_string(), "Outro".to_string()],
+        plays,
+    };
+    let unrated = Album{ rating: None, plays: HashMap::new(), ..rated.clone() };
+
+    for album in vec![rated, unrated] {
+        let value = db.value_from(to_noms(&album).unwrap());
+        assert_eq!(from_value::<Album>(value).unwrap(), album);
+    }
+}
+
+#[test]
+fn enums() {
+    assert_eq!(to_noms(&Event::Started).unwrap(), "Started".into_noms());
+
+    let noms = Noms::new();
+    let db = database(&noms);
+    let events = vec![
+        Event::Started,
+        Event::Click{ x: 3, y: 4 },
+        Event::Key("a".to_string()),
+    ];
+    for event in events {
+        let value = db.value_from(to_noms(&event).unwrap());
+        assert_eq!(from_value::<Event>(value).unwrap(), event);
+    }
+}
+
+#[test]
+fn none_outside_struct() {
+    assert!(to_noms(&None::<u64>).is_err());
+    assert!(to_noms(&vec![Some(1), None]).is_err());
+}
+
+#[test]
+fn chunked_collections() {
+    let noms = Noms::new();
+    let failing = Arc::new(AtomicBool::new(false));
+    let fail = failing.clone();
+    let server = server_with(move |line, _| match line.starts_with("POST /getRefs/") && fail.load(Ordering::SeqCst) {
+        true => Some((500, vec![])),
+        false => None,
+    });
+    let db = server.connect(&noms);
+    let mut list = ListEditor::new(&db);
+    list.splice(0, 0, 0..20_000i64).unwrap();
+    let mut set = SetEditor::new(&db);
+    let mut map = MapEditor::new(&db);
+    for i in 0..20_000i64 {
+        set.insert(i).unwrap();
+        map.insert(format!("key {}", i), i).unwrap();
+    }
+    for (name, value) in vec![("list", list.build().unwrap()), ("set", set.build().unwrap()), ("map", map.build().unwrap())] {
+        db.commit_value(db.dataset_or_empty(name).unwrap(), value).unwrap();
+    }
+
+    let other = server.connect(&noms);
+    let head = |name| other.dataset::<Empty, NomsValue>(name).unwrap().head_value().unwrap();
+    let (list, set, map) = (head("list"), head("set"), head("map"));
+    assert_eq!(from_value::<Vec<i64>>(list.clone()).unwrap(), (0..20_000).collect::<Vec<_>>());
+    assert_eq!(from_value::<BTreeSet<i64>>(set.clone()).unwrap(), (0..20_000).collect());
+    let entries = from_value::<HashMap<String, i64>>(map.clone()).unwrap();
+    assert_eq!((entries.len(), entries["key 12345"]), (20_000, 12_345));
+
+    let other = server.connect(&noms);
+    let (list, set, map) = (other.value_from(list.into_noms()), other.value_from(set.into_noms()), other.value_from(map.into_noms()));
+    failing.store(true, Ordering::SeqCst);
+    for result in vec![from_value::<Vec<i64>>(list).map(|_| ()), from_value::<BTreeSet<i64>>(set).map(|_| ()), from_value::<HashMap<String, i64>>(map).map(|_| ())] {
+        match result {
+            Err(Error::Http(status)) => assert_eq!(status.as_u16(), 500),
+            other => panic!("expected the chunks not to be fetched, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,203 @@
+extern crate nomrs;
+
+use nomrs::{Noms, Database};
+use nomrs::error::Error;
+use nomrs::value::{Empty, NomsValue, NomsSet, NomsMap, SetEditor, MapEditor, Merge};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+mod common;
+
+use common::{database, server, server_with};
+
+fn set_value<'a, D: Database>(db: &'a D, items: &[i64]) -> NomsValue<'a> {
+    let mut editor = SetEditor::new(db);
+    for &item in items {
+        editor.insert(item).unwrap();
+    }
+    editor.build().unwrap()
+}
+
+fn set<'a, D: Database>(db: &'a D, items: &[i64]) -> NomsSet<'a, i64> {
+    set_value(db, items).transform()
+}
+
+/// Reads the set at the head of a dataset
+fn head<'a, D: Database>(db: &'a D, id: &str) -> NomsSet<'a, i64> {
+    db.dataset::<Empty, NomsSet<i64>>(id).unwrap().head_value().unwrap()
+}
+
+fn collect<T>(merge: Merge<T>) -> Vec<T> {
+    merge.collect::<Result<_, _>>().unwrap()
+}
+
+fn sorted(items: &[i64]) -> Vec<i64> {
+    let mut items = items.to_vec();
+    items.sort();
+    items.dedup();
+    items
+}
+
+#[test]
+fn small_sets() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let a = set(&db, &[3, 1, 2]);
+    let b = set(&db, &[4, 2, 3]);
+    assert_eq!(collect(a.union(&b)), vec![1, 2, 3, 4]);
+    assert_eq!(collect(a.intersection(&b)), vec![2, 3]);
+    assert_eq!(collect(a.difference(&b)), vec![1]);
+    assert!(!a.is_subset(&b).unwrap());
+    assert!(set(&db, &[2, 3]).is_subset(&a).unwrap());
+    assert!(set(&db, &[]).is_subset(&a).unwrap());
+}
+
+#[test]
+fn large_sets() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let evens: Vec<i64> = (0..10_000).map(|i| i * 2).collect();
+    let threes: Vec<i64> = (0..7_000).map(|i| i * 3).collect();
+    let a = set(&db, &evens);
+    let b = set(&db, &threes);
+
+    let union: Vec<i64> = evens.iter().chain(threes.iter()).cloned().collect();
+    assert_eq!(collect(a.union(&b)), sorted(&union));
+    assert_eq!(collect(a.intersection(&b)), (0..3_334).map(|i| i * 6).collect::<Vec<_>>());
+    assert_eq!(collect(a.difference(&b)), evens.iter().cloned().filter(|i| i % 3 != 0).collect::<Vec<_>>());
+    assert!(!a.is_subset(&b).unwrap());
+    assert!(a.is_subset(&a).unwrap());
+}
+
+#[test]
+fn sets_sharing_chunks() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let items: Vec<i64> = (0..50_000).collect();
+    let value = set_value(&db, &items);
+    let original: NomsSet<i64> = value.clone().transform();
+    let mut editor = SetEditor::edit(&db, &value).unwrap();
+    editor.remove(10_000i64).unwrap();
+    editor.insert(-1i64).unwrap();
+    editor.insert(60_000i64).unwrap();
+    let edited: NomsSet<i64> = editor.build().unwrap().transform();
+
+    assert_eq!(collect(edited.difference(&original)), vec![-1, 60_000]);
+    assert_eq!(collect(original.difference(&edited)), vec![10_000]);
+    assert_eq!(collect(edited.intersection(&original)).len(), 49_999);
+    assert_eq!(collect(edited.union(&original)).len(), 50_002);
+    assert!(!original.is_subset(&edited).unwrap());
+}
+
+#[test]
+fn map_keys() {
+    let noms = Noms::new();
+    let db = database(&noms);
+    let mut a = MapEditor::new(&db);
+    let mut b = MapEditor::new(&db);
+    for i in 0..5_000i64 {
+        a.insert(format!("customer {:05}", i), i).unwrap();
+        if i % 2 == 0 {
+            b.insert(format!("customer {:05}", i), true).unwrap();
+        }
+    }
+    b.insert("someone else", false).unwrap();
+    let a: NomsMap<String, i64> = a.build().unwrap().transform();
+    let b: NomsMap<String, bool> = b.build().unwrap().transform();
+
+    assert_eq!(collect(a.key_intersection(&b)).len(), 2_500);
+    assert_eq!(collect(a.key_difference(&b))[..2], ["customer 00001".to_string(), "customer 00003".to_string()]);
+    assert_eq!(collect(b.key_difference(&a)), vec!["someone else".to_string()]);
+    assert_eq!(collect(a.key_union(&b)).len(), 5_001);
+    assert!(!b.is_key_subset(&a).unwrap());
+}
+
+#[test]
+fn shared_chunks_are_not_fetched() {
+    let noms = Noms::new();
+    let server = server();
+    let db = server.connect(&noms);
+    let items: Vec<i64> = (0..50_000).collect();
+    let value = set_value(&db, &items);
+    let mut editor = SetEditor::edit(&db, &value).unwrap();
+    editor.remove(10_000i64).unwrap();
+    db.commit_value(db.dataset_or_empty("edited").unwrap(), editor.build().unwrap()).unwrap();
+    db.commit_value(db.dataset_or_empty("original").unwrap(), value).unwrap();
+
+    let other = server.connect(&noms);
+    let (original, edited) = (head(&other, "original"), head(&other, "edited"));
+    let before = server.fetched().len();
+    assert_eq!(collect(original.difference(&edited)), vec![10_000]);
+    // only the chunks above the leaf which lost the item, and the leaf itself, are read
+    let fetched = server.fetched().len() - before;
+    assert!(fetched > 0 && fetched <= 6, "fetched {} chunks", fetched);
+}
+
+#[test]
+fn chunks_before_an_item_are_not_fetched() {
+    // the keys of meta tuples must be read as the numbers they are, or every chunk would seem to
+    // end after the item
+    let noms = Noms::new();
+    let server = server();
+    let db = server.connect(&noms);
+    let items: Vec<i64> = (0..50_000).collect();
+    db.commit_value(db.dataset_or_empty("many").unwrap(), set_value(&db, &items)).unwrap();
+    db.commit_value(db.dataset_or_empty("one").unwrap(), set_value(&db, &[1_000_000])).unwrap();
+
+    let other = server.connect(&noms);
+    let (many, one) = (head(&other, "many"), head(&other, "one"));
+    let before = server.fetched().len();
+    assert!(collect(many.intersection(&one)).is_empty());
+    assert!(!one.is_subset(&many).unwrap());
+    assert_eq!(server.fetched().len(), before);
+}
+
+#[test]
+fn fetch_errors_are_returned() {
+    let noms = Noms::new();
+    let failing = Arc::new(AtomicBool::new(false));
+    let fail = failing.clone();
+    let server = server_with(move |line, _| match line.starts_with("POST /getRefs/") && fail.load(Ordering::SeqCst) {
+        true => Some((500, vec![])),
+        false => None,
+    });
+    let db = server.connect(&noms);
+    let evens: Vec<i64> = (0..20_000).map(|i| i * 2).collect();
+    db.commit_value(db.dataset_or_empty("evens").unwrap(), set_value(&db, &evens)).unwrap();
+    db.commit_value(db.dataset_or_empty("small").unwrap(), set_value(&db, &[1, 2, 3])).unwrap();
+
+    let other = server.connect(&noms);
+    let (evens, small) = (head(&other, "evens"), head(&other, "small"));
+    failing.store(true, Ordering::SeqCst);
+    match small.is_subset(&evens) {
+        Err(Error::Http(status)) => assert_eq!(status.as_u16(), 500),
+        other => panic!("expected the merge to fail, got {:?}", other),
+    }
+    // the items before the chunk which cannot be fetched are returned, then the error
+    let mut union = small.union(&evens);
+    match union.next() {
+        Some(Err(Error::Http(status))) => assert_eq!(status.as_u16(), 500),
+        other => panic!("expected the merge to fail, got {:?}", other),
+    }
+    assert!(union.next().is_none());
+}
+
+#[test]
+fn merges_are_read_as_they_are_iterated() {
+    let noms = Noms::new();
+    let server = server();
+    let db = server.connect(&noms);
+    let items: Vec<i64> = (0..50_000).collect();
+    db.commit_value(db.dataset_or_empty("many").unwrap(), set_value(&db, &items)).unwrap();
+    db.commit_value(db.dataset_or_empty("none").unwrap(), set_value(&db, &[])).unwrap();
+
+    let other = server.connect(&noms);
+    let (many, none) = (head(&other, "many"), head(&other, "none"));
+    let before = server.fetched().len();
+    let first: Vec<i64> = many.union(&none).take(3).map(Result::unwrap).collect();
+    assert_eq!(first, vec![0, 1, 2]);
+    // only the chunks on the path to the first leaf are read
+    let fetched = server.fetched().len() - before;
+    assert!(fetched > 0 && fetched <= 3, "fetched {} chunks", fetched);
+    assert_eq!(collect(many.difference(&none)).len(), 50_000);
+}
@@ -0,0 +1,93 @@
+extern crate nomrs;
+extern crate openssl;
+
+use nomrs::Noms;
+use nomrs::error::Error;
+use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod, SslVerifyMode};
+use std::io::{self, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+const FIXTURES: &'static str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls/");
+const CA: &'static [u8] = include_bytes!("fixtures/tls/ca.pem");
+const OTHER_CA: &'static [u8] = include_bytes!("fixtures/tls/other-ca.pem");
+/// The client's certificate and key, signed by `CA`, with the password `noms`
+const CLIENT: &'static [u8] = include_bytes!("fixtures/tls/client.p12");
+
+/// Starts an HTTPS server for `localhost`, whose certificate is signed by `CA`, and which has an
+/// empty root. If `client_auth` is set, it only accepts clients with a certificate signed by `CA`.
+fn https_server(client_auth: bool) -> String {
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    acceptor.set_private_key_file(format!("{}server.key", FIXTURES), SslFiletype::PEM).unwrap();
+    acceptor.set_certificate_chain_file(format!("{}server.pem", FIXTURES)).unwrap();
+    if client_auth {
+        acceptor.set_ca_file(format!("{}ca.pem", FIXTURES)).unwrap();
+        acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+    }
+    let acceptor = acceptor.build();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            // clients which fail the handshake are simply turned away
+            let mut stream = match acceptor.accept(stream.unwrap()) {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let _ = respond(&mut stream);
+            let _ = stream.shutdown();
+        }
+    });
+    format!("localhost:{}", port)
+}
+
+/// Reads a request, and responds with an empty root.
+fn respond<S: Read + Write>(stream: &mut S) -> io::Result<()> {
+    let mut request = vec![];
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf)? {
+            0 => return Ok(()),
+            len => request.extend_from_slice(&buf[..len]),
+        }
+    }
+    let root = "00000000000000000000000000000000";
+    write!(stream, "HTTP/1.1 200 -\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", root.len(), root)
+}
+
+#[test]
+fn custom_ca_certificates() {
+    let noms = Noms::new();
+    let address = https_server(false);
+    noms.database().ca_certificate(CA).https(&address).unwrap();
+    // the server's certificate is not signed by a CA the system trusts
+    assert!(noms.database().https(&address).is_err());
+    assert!(noms.database().ca_certificate(OTHER_CA).https(&address).is_err());
+}
+
+#[test]
+fn client_certificates() {
+    let noms = Noms::new();
+    let address = https_server(true);
+    noms.database().ca_certificate(CA).client_certificate(CLIENT, "noms").https(&address).unwrap();
+    assert!(noms.database().ca_certificate(CA).https(&address).is_err());
+}
+
+#[test]
+fn invalid_certificates_are_rejected() {
+    let noms = Noms::new();
+    let address = https_server(true);
+    let corrupt = String::from_utf8_lossy(CA).replace("M", "*");
+    match noms.database().ca_certificate(corrupt.as_bytes()).https(&address) {
+        Err(Error::Tls(_)) => {}
+        other => panic!("expected the CA certificate to be rejected, got {:?}", other.map(|_| ())),
+    }
+    match noms.database().ca_certificate(CA).client_certificate(CLIENT, "wrong").https(&address) {
+        Err(Error::Tls(_)) => {}
+        other => panic!("expected the client certificate to be rejected, got {:?}", other.map(|_| ())),
+    }
+    match noms.database().ca_certificate(CA).client_certificate(CA, "noms").https(&address) {
+        Err(Error::Tls(_)) => {}
+        other => panic!("expected the client certificate to be rejected, got {:?}", other.map(|_| ())),
+    }
+}